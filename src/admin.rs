@@ -1,10 +1,12 @@
 use std::mem::transmute;
 use std::net::SocketAddr;
 use eventual::Future;
-use std::boxed::{FnBox as Thunk};
-use std::sync::mpsc::{channel, Sender, Receiver};
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Sender, Receiver, TryRecvError};
 use std::thread;
-use std::ptr::{Unique, null, null_mut};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use std::ptr::{null, null_mut};
 
 use libc::*;
 
@@ -13,12 +15,16 @@ use hyperdex::*;
 use hyperdex_admin::*;
 
 use helpers;
-use helpers::FutureHelpers;
+use reactor::{Reactor, Waker};
+use schema::{parse_config, SpaceSchema};
+use helpers::{FutureHelpers, Unique};
 
 /// A HyperDex Admin object, used for meta operations like creating and removing spaces.
 pub struct Admin {
     ptr: *mut Struct_hyperdex_admin,
-    req_tx: Sender<AdminRequest>,
+    req_tx: Sender<AdminMessage>,
+    waker: Arc<Waker>,
+    thread: Option<JoinHandle<()>>,
 }
 
 pub struct AdminRequest {
@@ -28,6 +34,35 @@ pub struct AdminRequest {
     failure: Option<Box<Fn(HyperError) + Send>>,
 }
 
+enum AdminMessage {
+    Request(AdminRequest),
+    Shutdown(Instant),
+}
+
+unsafe fn complete_request(ptr: *mut Struct_hyperdex_admin, req: AdminRequest, status: u32) {
+    if status == HYPERDEX_ADMIN_SUCCESS {
+        match *req.status {
+            HYPERDEX_ADMIN_SUCCESS => {
+                match req.success {
+                    Some(func) => func(),
+                    None => (),
+                }
+            },
+            _ => {
+                match req.failure {
+                    Some(func) => func(get_admin_error(ptr, *req.status)),
+                    None => (),
+                }
+            }
+        }
+    } else {
+        match req.failure {
+            Some(func) => func(get_admin_error(ptr, status)),
+            None => (),
+        }
+    }
+}
+
 impl Admin {
     pub fn new(coordinator: SocketAddr) -> Result<Admin, String> {
         unsafe {
@@ -38,98 +73,132 @@ impl Admin {
         let (req_tx, req_rx) = channel();
         if ptr.is_null() {
             return Err(format!("Could not create hyperdex_admin ({})", coordinator));
-        } else {
-            let ptr = Unique::new(ptr);
-
-            thread::spawn(move|| {
-                // A list of pending requests
-                let mut pending: Vec<AdminRequest> = Vec::new();
-                let loop_fn = |pending: &mut Vec<AdminRequest>| {
-                    if pending.len() == 0 {
-                        return;
+        }
+
+        // The thread sleeps in epoll until the admin's socket becomes readable or a request is
+        // submitted.
+        let reactor = match Reactor::new() {
+            Ok(r) => r,
+            Err(err) => {
+                hyperdex_admin_destroy(ptr);
+                return Err(format!("Unable to create the event loop: {}", err));
+            },
+        };
+        if let Err(err) = reactor.register(hyperdex_admin_poll_fd(ptr), 0) {
+            hyperdex_admin_destroy(ptr);
+            return Err(format!("Unable to watch the admin's socket: {}", err));
+        }
+        let waker = reactor.waker();
+
+        let uptr = Unique::new(ptr);
+
+        let thread = thread::spawn(move|| {
+            let ptr = uptr;
+            // A list of pending requests
+            let mut pending: Vec<AdminRequest> = Vec::new();
+            // Completions that arrived before their request did
+            let mut unmatched: Vec<(int64_t, u32)> = Vec::new();
+            let mut ready = Vec::new();
+            let mut deadline = None;
+
+            loop {
+                while deadline.is_none() {
+                    match req_rx.try_recv() {
+                        Ok(AdminMessage::Request(req)) => pending.push(req),
+                        Ok(AdminMessage::Shutdown(d)) => deadline = Some(d),
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => deadline = Some(Instant::now()),
                     }
+                }
 
+                // Take every completion that is available without blocking
+                loop {
                     let mut status = 0;
-                    let ret = hyperdex_admin_loop(*ptr, -1, &mut status);
+                    let ret = hyperdex_admin_loop(*ptr, 0, &mut status);
                     if ret < 0 {
-                        if ret == -1 {
-                            return;
-                        } else {
-                            panic!(format!("the return code was: {}", ret));
-                        }
+                        break;
                     }
-                    let req_index = pending.iter().position(|req| {
-                        if req.id == ret {
-                            true
-                        } else {
-                            false
-                        }
-                    }).unwrap();  // TODO: better error handling
-                    let req = pending.remove(req_index);
-
-                    if status == HYPERDEX_ADMIN_SUCCESS {
-                        match *req.status {
-                            HYPERDEX_ADMIN_SUCCESS => {
-                                match req.success {
-                                    Some(func) => func(),
-                                    None => (),
-                                }
-                            },
-                            _ => {
-                                match req.failure {
-                                    Some(func) => func(get_admin_error(*ptr, *req.status)),
-                                    None => (),
-                                }
-                            }
-                        }
-                    } else {
-                        match req.failure {
-                            Some(func) => func(get_admin_error(*ptr, status)),
-                            None => (),
-                        }
-                    }
-                };
+                    unmatched.push((ret, status));
+                }
 
-                let (periodic_tx, periodic_rx) = channel();
-                thread::spawn(move || {
-                    loop {
-                        thread::sleep_ms(100);
-                        periodic_tx.send(());
+                let mut i = 0;
+                while i < unmatched.len() {
+                    match pending.iter().position(|req| req.id == unmatched[i].0) {
+                        Some(idx) => {
+                            let (_, status) = unmatched.remove(i);
+                            complete_request(*ptr, pending.remove(idx), status);
+                        },
+                        None => i += 1,
                     }
-                });
+                }
 
-                loop {
-                    select!(
-                        // Add a new request
-                        msg = req_rx.recv() => {
-                            match msg {
-                                Ok(req) => {
-                                    pending.push(req);
-                                    loop_fn(&mut pending);
-                                },
-                                Err(_) => {
-                                    // TODO: this is causing trouble for some reason
-                                    hyperdex_admin_destroy(*ptr);
-                                    return;
-                                }
-                            };
-                        },
-                        // Wake up and call loop()
-                        _ = periodic_rx.recv() => {
-                            loop_fn(&mut pending);
+                let timeout = match deadline {
+                    None => -1,
+                    Some(d) => {
+                        let now = Instant::now();
+                        if pending.len() == 0 || now >= d {
+                            break;
                         }
-                    )
+                        let left = d - now;
+                        (left.as_secs() * 1000 + (left.subsec_nanos() / 1000000) as u64 + 1) as i32
+                    },
+                };
+
+                if reactor.poll(&mut ready, timeout).is_err() {
+                    deadline = Some(Instant::now());
                 }
-            });
-        }
+            }
+
+            for req in pending.into_iter() {
+                match req.failure {
+                    Some(func) => func(HyperError {
+                        status: HYPERDEX_ADMIN_INTERRUPTED,
+                        message: "the admin was shut down before the request completed".to_string(),
+                        location: String::new(),
+                    }),
+                    None => (),
+                }
+            }
+
+            hyperdex_admin_destroy(*ptr);
+        });
 
         return Ok(Admin {
             ptr: ptr,
             req_tx: req_tx,
+            waker: waker,
+            thread: Some(thread),
         })
         }
     }
 
+    /// Shuts the admin down.
+    ///
+    /// Pending requests get until `timeout` elapses to complete; the rest fail with
+    /// `HYPERDEX_ADMIN_INTERRUPTED`.  Returns once the background thread has exited and the
+    /// underlying handle has been destroyed.
+    ///
+    /// Dropping an `Admin` is equivalent to shutting it down with a zero timeout.
+    pub fn shutdown(mut self, timeout: Duration) {
+        self.stop(timeout);
+    }
+
+    fn stop(&mut self, timeout: Duration) {
+        match self.thread.take() {
+            Some(t) => {
+                self.req_tx.send(AdminMessage::Shutdown(Instant::now() + timeout));
+                self.waker.wake();
+                t.join();
+            },
+            None => (),
+        }
+    }
+
+    fn submit(&self, req: AdminRequest) {
+        self.req_tx.send(AdminMessage::Request(req));
+        self.waker.wake();
+    }
+
     pub fn add_space(&self, desc: &str) -> Result<(), HyperError> {
         self.async_add_space(desc).into_inner()
     }
@@ -182,7 +251,7 @@ impl Admin {
                 })),
             };
 
-            self.req_tx.send(req);
+            self.submit(req);
 
             helpers::spawn(move|| {
                 res_rx.recv().unwrap()
@@ -198,6 +267,24 @@ impl Admin {
         self.async_dump_config_or_list_spaces("dump_config")
     }
 
+    /// The key and attributes of a space, read from `dump_config`.
+    pub fn space_schema(&self, space: &str) -> Result<SpaceSchema, HyperError> {
+        let config = try!(self.dump_config());
+        let spaces = try!(parse_config(&config).map_err(|msg| HyperError {
+            status: HYPERDEX_ADMIN_INTERNAL,
+            message: format!("cannot read the configuration: {}", msg),
+            location: String::new(),
+        }));
+        match spaces.into_iter().find(|s| s.name == space) {
+            Some(schema) => Ok(schema),
+            None => Err(HyperError {
+                status: HYPERDEX_ADMIN_NOTFOUND,
+                message: format!("space \"{}\" does not exist", space),
+                location: String::new(),
+            }),
+        }
+    }
+
     pub fn list_spaces(&self) -> Result<String, HyperError> {
         self.async_list_spaces().into_inner()
     }
@@ -240,7 +327,7 @@ impl Admin {
                 })),
             };
 
-            self.req_tx.send(req);
+            self.submit(req);
 
             helpers::spawn(move|| {
                 res_rx.recv().unwrap()
@@ -270,7 +357,7 @@ impl Admin {
                 })),
             };
 
-            self.req_tx.send(req);
+            self.submit(req);
 
             res_rx.recv().unwrap()
         }
@@ -298,7 +385,7 @@ impl Admin {
                 })),
             };
 
-            self.req_tx.send(req);
+            self.submit(req);
 
             res_rx.recv().unwrap()
         }
@@ -328,7 +415,7 @@ impl Admin {
                 })),
             };
 
-            self.req_tx.send(req);
+            self.submit(req);
 
             res_rx.recv().unwrap()
         }
@@ -376,7 +463,7 @@ impl Admin {
                 })),
             };
 
-            self.req_tx.send(req);
+            self.submit(req);
 
             res_rx.recv().unwrap()
         }
@@ -409,7 +496,7 @@ impl Admin {
                 })),
             };
 
-            self.req_tx.send(req);
+            self.submit(req);
 
             res_rx.recv().unwrap()
         }
@@ -436,7 +523,7 @@ impl Admin {
                 })),
             };
 
-            self.req_tx.send(req);
+            self.submit(req);
 
             res_rx.recv().unwrap()
         }
@@ -444,10 +531,8 @@ impl Admin {
 
 }
 
-// impl Drop for Admin {
-    // fn drop(&mut self) {
-        // unsafe {
-            // hyperdex_admin_destroy(self.ptr);
-        // }
-    // }
-// }
+impl Drop for Admin {
+    fn drop(&mut self) {
+        self.stop(Duration::from_secs(0));
+    }
+}
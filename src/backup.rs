@@ -0,0 +1,166 @@
+//! Logical backups of a space, as text that does not depend on the layout of the cluster.
+//!
+//! `export` writes every object of a space as one line of JSON, holding the object's key and
+//! its other attributes in the canonical form of the `json` module:
+//!
+//! ```text
+//! {"attributes":{"age":{"int":20},"first":{"string":"Derek"}},"key":{"string":"derek"}}
+//! ```
+//!
+//! `import` loads such lines back into a space, which may live on another cluster.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let mut out = File::create("contacts.jsonl").unwrap();
+//! backup::export(&mut client, "contacts", &mut out).unwrap();
+//!
+//! let input = BufReader::new(File::open("contacts.jsonl").unwrap());
+//! backup::import(&mut client, "contacts", input, ImportMode::Overwrite, 64, |progress| {
+//!     println!("{} objects written", progress.written);
+//! }).unwrap();
+//! ```
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter, Error};
+use std::io::{self, BufRead, Write};
+
+use eventual::Future;
+use serde_json::{self, Map, Value};
+
+use common::*;
+use client_types::*;
+use hyperdex_client::*;
+use helpers::FutureHelpers;
+use store::AsyncKeyValueStore;
+
+/// Why an export or import stopped.
+#[derive(Debug)]
+pub enum BackupError {
+    /// Reading or writing the backup failed.
+    Io(io::Error),
+    /// The store failed to search, or to write the object read from a line.
+    Store { line: u64, error: HyperError },
+    /// A line could not be read back, or an object could not be written out.
+    Record { line: u64, message: String },
+}
+
+impl Display for BackupError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            BackupError::Io(ref err) => write!(f, "{}", err),
+            BackupError::Store { line, ref error } => write!(f, "line {}: {}", line, error.message),
+            BackupError::Record { line, ref message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl From<io::Error> for BackupError {
+    fn from(err: io::Error) -> BackupError {
+        BackupError::Io(err)
+    }
+}
+
+/// What `import` does with objects that already exist.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Replace their attributes with the ones from the backup, using `put`.
+    Overwrite,
+    /// Leave them alone, using `put_if_not_exist`.
+    KeepExisting,
+}
+
+/// How far an import has got.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ImportProgress {
+    /// The objects read from the backup so far.
+    pub read: u64,
+    /// The objects written to the space.
+    pub written: u64,
+    /// The objects left alone because they already existed, with `ImportMode::KeepExisting`.
+    pub skipped: u64,
+}
+
+fn record(key: HyperValue, obj: &HyperObject) -> Value {
+    let mut map = Map::new();
+    map.insert("key".to_string(), key.to_json());
+    map.insert("attributes".to_string(), obj.to_json());
+    Value::Object(map)
+}
+
+fn parse_record(line: &str) -> Result<(HyperValue, HyperObject), String> {
+    let json: Value = try!(serde_json::from_str(line).map_err(|err| format!("invalid JSON: {}", err)));
+    let (key, attrs) = match (json.get("key"), json.get("attributes")) {
+        (Some(key), Some(attrs)) => (key, attrs),
+        _ => return Err("expected an object with \"key\" and \"attributes\"".to_string()),
+    };
+    let key = try!(HyperValue::from_json(key).map_err(|err| format!("key: {}", err)));
+    Ok((key, try!(HyperObject::from_json(attrs))))
+}
+
+/// Writes every object of `space` to `writer`, one per line, and returns how many there were.
+/// A `Client` must have been given the schema of `space` with `set_schema`, to tell the key
+/// apart from the other attributes.
+pub fn export<C, W>(store: &mut C, space: &str, mut writer: W) -> Result<u64, BackupError>
+    where C: AsyncKeyValueStore, W: Write {
+    let mut count = 0;
+    for res in store.search_keyed(space, Vec::new()).iter() {
+        count += 1;
+        let (key, obj) = try!(res.map_err(|err| BackupError::Store { line: count, error: err }));
+        try!(writeln!(writer, "{}", record(key.to_hyper(), &obj)));
+    }
+    try!(writer.flush());
+    Ok(count)
+}
+
+/// Loads the objects that `export` wrote into `space`, keeping at most `limit` writes in
+/// flight, and returns the final progress.
+///
+/// `progress` is called each time a write finishes.  The import stops at the first line that
+/// cannot be read or written; the lines before it may or may not have been written by then.
+pub fn import<C, R, F>(store: &mut C, space: &str, reader: R, mode: ImportMode, limit: usize, mut progress: F)
+    -> Result<ImportProgress, BackupError>
+    where C: AsyncKeyValueStore, R: BufRead, F: FnMut(&ImportProgress) {
+    let mut in_flight: VecDeque<(u64, Future<(), HyperError>)> = VecDeque::new();
+    let mut stats = ImportProgress::default();
+
+    let mut finish = |stats: &mut ImportProgress, line: u64, fut: Future<(), HyperError>| {
+        match fut.into_inner() {
+            Ok(()) => stats.written += 1,
+            Err(ref err) if mode == ImportMode::KeepExisting && err.status == HYPERDEX_CLIENT_CMPFAIL =>
+                stats.skipped += 1,
+            Err(err) => return Err(BackupError::Store { line: line, error: err }),
+        }
+        progress(stats);
+        Ok(())
+    };
+
+    for (i, line) in reader.lines().enumerate() {
+        let line_no = i as u64 + 1;
+        let line = try!(line);
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (key, obj) = try!(parse_record(&line).map_err(|msg| BackupError::Record {
+            line: line_no,
+            message: msg,
+        }));
+        stats.read += 1;
+
+        if in_flight.len() >= cmp::max(limit, 1) {
+            let (l, fut) = in_flight.pop_front().unwrap();
+            try!(finish(&mut stats, l, fut));
+        }
+        let fut = match mode {
+            ImportMode::Overwrite => store.async_put(space, key, obj),
+            ImportMode::KeepExisting => store.async_put_if_not_exist(space, key, obj),
+        };
+        in_flight.push_back((line_no, fut));
+    }
+    for (l, fut) in in_flight.into_iter() {
+        try!(finish(&mut stats, l, fut));
+    }
+
+    Ok(stats)
+}
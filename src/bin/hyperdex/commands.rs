@@ -0,0 +1,215 @@
+//! The commands shared by the command line and the shell.
+//!
+//! Objects are read and written in the canonical JSON form described in the `json` module of
+//! the library, where strings, ints and floats may also be given as bare JSON values.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use serde_json::{self, Value};
+
+use hyperdex::*;
+use hyperdex::HyperValue::*;
+use hyperdex::backup::{self, ImportMode, ImportProgress};
+use hyperdex::schema::Datatype;
+
+/// How many writes an import keeps in flight.
+const IMPORT_LIMIT: usize = 64;
+
+/// Every command, with the number of words it takes before its last argument, which may
+/// contain spaces (a query, an object or a space description), and the names of its arguments.
+pub const COMMANDS: [(&'static str, usize, &'static [&'static str]); 14] = [
+    ("get", 2, &["SPACE", "KEY"]),
+    ("put", 2, &["SPACE", "KEY", "OBJECT"]),
+    ("del", 2, &["SPACE", "KEY"]),
+    ("search", 1, &["SPACE", "[QUERY]"]),
+    ("count", 1, &["SPACE", "[QUERY]"]),
+    ("atomic-add", 2, &["SPACE", "KEY", "OBJECT"]),
+    ("export", 1, &["SPACE", "[FILE]"]),
+    ("import", 1, &["SPACE", "FILE"]),
+    ("import-new", 1, &["SPACE", "FILE"]),
+    ("import-csv", 1, &["SPACE", "FILE"]),
+    ("add-space", 0, &["DESCRIPTION"]),
+    ("rm-space", 1, &["SPACE"]),
+    ("list-spaces", 0, &[]),
+    ("dump-config", 0, &[]),
+];
+
+/// A connection to a cluster, made when a command first needs it.
+pub struct Session {
+    coordinator: SocketAddr,
+    key_type: Datatype,
+    client: Option<Client>,
+    admin: Option<Admin>,
+}
+
+impl Session {
+    /// A session whose keys are of `key_type`, which must be a string, an int or a float.
+    pub fn new(coordinator: SocketAddr, key_type: Datatype) -> Session {
+        Session {
+            coordinator: coordinator,
+            key_type: key_type,
+            client: None,
+            admin: None,
+        }
+    }
+
+    fn client(&mut self) -> Result<&mut Client, String> {
+        if self.client.is_none() {
+            self.client = Some(try!(Client::new(self.coordinator)));
+        }
+        Ok(self.client.as_mut().unwrap())
+    }
+
+    pub fn admin(&mut self) -> Result<&Admin, String> {
+        if self.admin.is_none() {
+            self.admin = Some(try!(Admin::new(self.coordinator)));
+        }
+        Ok(self.admin.as_ref().unwrap())
+    }
+
+    /// Reads a key given on the command line.
+    pub fn key(&self, key: &str) -> Result<HyperValue, String> {
+        match self.key_type {
+            Datatype::Int => i64::from_str(key).map(HyperInt).map_err(|_| format!("\"{}\" is not an int key", key)),
+            Datatype::Float => f64::from_str(key).map(HyperFloat).map_err(|_| format!("\"{}\" is not a float key", key)),
+            _ => Ok(HyperString(key.as_bytes().to_vec())),
+        }
+    }
+}
+
+pub fn object_from_json(text: &str) -> Result<HyperObject, String> {
+    let json: Value = try!(serde_json::from_str(text).map_err(|err| format!("invalid JSON: {}", err)));
+    HyperObject::from_json(&json)
+}
+
+pub fn usage(command: &str) -> String {
+    let names = COMMANDS.iter().find(|c| c.0 == command).map(|c| c.2).unwrap_or(&[]);
+    format!("usage: {} {}", command, names.join(" "))
+}
+
+pub fn check_args<'a>(command: &str, args: &'a [String], required: usize, optional: usize)
+    -> Result<Vec<&'a str>, String> {
+    if args.len() < required || args.len() > required + optional {
+        return Err(usage(command));
+    }
+    Ok(args.iter().map(|a| &a[..]).collect())
+}
+
+pub fn hyper<T>(res: Result<T, HyperError>) -> Result<T, String> {
+    res.map_err(|err| err.message)
+}
+
+/// Opens a file for reading, where '-' stands for stdin.
+fn open(path: &str) -> Result<Box<BufRead>, String> {
+    if path == "-" {
+        Ok(Box::new(BufReader::new(io::stdin())))
+    } else {
+        let file = try!(File::open(path).map_err(|err| format!("{}: {}", path, err)));
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+fn report(progress: &ImportProgress) {
+    let _ = write!(io::stderr(), "\r{} written, {} skipped", progress.written, progress.skipped);
+}
+
+/// Runs a command, handing the objects it reads to `print`.
+pub fn run<F>(session: &mut Session, command: &str, args: &[String], print: F) -> Result<(), String>
+    where F: Fn(HyperObject) {
+    match command {
+        "get" => {
+            let a = try!(check_args(command, args, 2, 0));
+            let key = try!(session.key(a[1]));
+            print(try!(hyper(try!(session.client()).get(a[0], key))));
+        },
+        "put" | "atomic-add" => {
+            let a = try!(check_args(command, args, 3, 0));
+            let key = try!(session.key(a[1]));
+            let obj = try!(object_from_json(a[2]));
+            let client = try!(session.client());
+            try!(hyper(if command == "put" { client.put(a[0], key, obj) } else { client.atomic_add(a[0], key, obj) }));
+        },
+        "del" => {
+            let a = try!(check_args(command, args, 2, 0));
+            let key = try!(session.key(a[1]));
+            try!(hyper(try!(session.client()).del(a[0], key)));
+        },
+        "search" | "count" => {
+            let a = try!(check_args(command, args, 1, 1));
+            let checks = try!(query::parse(a.get(1).cloned().unwrap_or("")).map_err(|err| format!("query {}", err)));
+            let client = try!(session.client());
+            if command == "count" {
+                println!("{}", try!(hyper(client.count(a[0], checks))));
+            } else {
+                for res in client.search(a[0], checks).iter() {
+                    print(try!(hyper(res)));
+                }
+            }
+        },
+        "export" => {
+            let a = try!(check_args(command, args, 1, 1));
+            // export takes each object's key out by name, which needs the space's schema
+            let schema = try!(hyper(try!(session.admin()).space_schema(a[0])));
+            let client = try!(session.client());
+            client.set_schema(schema);
+            let res = match a.get(1) {
+                Some(path) if *path != "-" => {
+                    let file = try!(File::create(path).map_err(|err| format!("{}: {}", path, err)));
+                    backup::export(client, a[0], file)
+                },
+                _ => backup::export(client, a[0], io::stdout()),
+            };
+            let count = try!(res.map_err(|err| err.to_string()));
+            let _ = writeln!(io::stderr(), "exported {} objects", count);
+        },
+        "import" | "import-new" => {
+            let a = try!(check_args(command, args, 2, 0));
+            let reader = try!(open(a[1]));
+            let mode = if command == "import" { ImportMode::Overwrite } else { ImportMode::KeepExisting };
+            let progress = try!(backup::import(try!(session.client()), a[0], reader, mode, IMPORT_LIMIT, |p| {
+                if (p.written + p.skipped) % 1000 == 0 {
+                    report(p);
+                }
+            }).map_err(|err| err.to_string()));
+            report(&progress);
+            let _ = writeln!(io::stderr(), "");
+        },
+        "import-csv" => {
+            let a = try!(check_args(command, args, 2, 0));
+            let reader = try!(open(a[1]));
+            let schema = try!(hyper(try!(session.admin()).space_schema(a[0])));
+            let report = try!(csv::CsvImporter::new(schema).import(try!(session.client()), reader)
+                                                          .map_err(|err| err.to_string()));
+            for err in report.errors.iter() {
+                let _ = writeln!(io::stderr(), "{}", err);
+            }
+            let _ = writeln!(io::stderr(), "{} rows written, {} rejected", report.written, report.errors.len());
+        },
+        "add-space" => {
+            let a = try!(check_args(command, args, 1, 0));
+            let mut desc = a[0].to_string();
+            if desc == "-" {
+                desc.clear();
+                try!(io::stdin().read_to_string(&mut desc).map_err(|err| err.to_string()));
+            }
+            try!(hyper(try!(session.admin()).add_space(&desc)));
+        },
+        "rm-space" => {
+            let a = try!(check_args(command, args, 1, 0));
+            try!(hyper(try!(session.admin()).remove_space(a[0])));
+        },
+        "list-spaces" => {
+            try!(check_args(command, args, 0, 0));
+            print!("{}", try!(hyper(try!(session.admin()).list_spaces())));
+        },
+        "dump-config" => {
+            try!(check_args(command, args, 0, 0));
+            print!("{}", try!(hyper(try!(session.admin()).dump_config())));
+        },
+        other => return Err(format!("unknown command \"{}\"", other)),
+    }
+    Ok(())
+}
@@ -0,0 +1,114 @@
+//! A command line client for HyperDex.
+//!
+//! Objects are printed in their canonical JSON form, one per line; `hyperdex shell` starts an
+//! interactive shell instead.
+
+extern crate hyperdex;
+extern crate rustyline;
+extern crate serde_json;
+
+mod commands;
+mod shell;
+#[cfg(test)]
+mod test;
+
+use std::env;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::process;
+use std::str::FromStr;
+
+use hyperdex::schema::Datatype;
+
+use commands::Session;
+
+const USAGE: &'static str = "\
+usage: hyperdex [-c HOST:PORT] [--int-key | --float-key] COMMAND [ARGS]
+
+commands:
+    get SPACE KEY                 print an object
+    put SPACE KEY OBJECT          store the attributes of a JSON object
+    del SPACE KEY                 delete an object
+    search SPACE [QUERY]          print the objects matching a query
+    count SPACE [QUERY]           count the objects matching a query
+    atomic-add SPACE KEY OBJECT   add numbers to the attributes of an object
+    export SPACE [FILE]           write every object of a space, one per line
+    import SPACE FILE             load the objects written by export, replacing existing ones
+    import-new SPACE FILE         load the objects written by export, keeping existing ones
+    import-csv SPACE FILE         load the rows of a CSV file whose header names the attributes
+    add-space DESCRIPTION         create a space; '-' reads the description from stdin
+    rm-space SPACE                remove a space
+    list-spaces                   print the name of every space
+    dump-config                   print the cluster configuration
+    shell                         start an interactive shell
+
+The coordinator defaults to $HYPERDEX_COORDINATOR, or 127.0.0.1:1982.  Keys are strings
+unless --int-key or --float-key is given, and a FILE of '-' stands for stdin or stdout.
+Queries use the syntax of the query module, e.g.
+    hyperdex search contacts 'age <= 25 AND first =~ \"^D\"'";
+
+#[derive(Debug)]
+struct Options {
+    coordinator: SocketAddr,
+    /// The type of the keys given on the command line: a string, an int or a float.
+    key_type: Datatype,
+    command: String,
+    args: Vec<String>,
+}
+
+/// Parses the arguments that follow the program name.  An empty error asks for the usage.
+fn parse_options<I>(mut args: I, coordinator: String) -> Result<Options, String>
+    where I: Iterator<Item=String> {
+    let mut coordinator = coordinator;
+    let mut key_type = Datatype::String;
+    let mut rest = Vec::new();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "-c" | "--coordinator" if rest.is_empty() => {
+                coordinator = try!(args.next().ok_or(format!("{} needs an address", arg)));
+            },
+            "--int-key" if rest.is_empty() => key_type = Datatype::Int,
+            "--float-key" if rest.is_empty() => key_type = Datatype::Float,
+            "-h" | "--help" if rest.is_empty() => return Err(String::new()),
+            _ => rest.push(arg),
+        }
+    }
+    if rest.is_empty() {
+        return Err(format!("no command given\n\n{}", USAGE));
+    }
+    let coordinator = try!(SocketAddr::from_str(&coordinator)
+                           .map_err(|_| format!("\"{}\" is not a HOST:PORT address", coordinator)));
+    let command = rest.remove(0);
+    Ok(Options {
+        coordinator: coordinator,
+        key_type: key_type,
+        command: command,
+        args: rest,
+    })
+}
+
+fn main() {
+    let coordinator = env::var("HYPERDEX_COORDINATOR").unwrap_or("127.0.0.1:1982".to_string());
+    let opts = match parse_options(env::args().skip(1), coordinator) {
+        Ok(opts) => opts,
+        Err(msg) => {
+            if msg.is_empty() {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            let _ = writeln!(io::stderr(), "hyperdex: {}", msg);
+            process::exit(2);
+        },
+    };
+    let mut session = Session::new(opts.coordinator, opts.key_type);
+    let res = if opts.command == "shell" {
+        shell::run(&mut session)
+    } else {
+        commands::run(&mut session, &opts.command, &opts.args,
+                      |obj| println!("{}", obj.to_json()))
+    };
+    if let Err(msg) = res {
+        let _ = writeln!(io::stderr(), "hyperdex: {}", msg);
+        process::exit(1);
+    }
+}
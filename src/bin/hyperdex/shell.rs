@@ -0,0 +1,307 @@
+//! An interactive shell, with history and completion of commands, spaces and attributes.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::hash::Hash;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::str;
+
+use rustyline::{self, Editor};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use serde_json;
+
+use hyperdex::*;
+use hyperdex::HyperValue::*;
+use hyperdex::schema::{self, SpaceSchema};
+
+use commands::{self, hyper, Session, COMMANDS};
+
+const HELP: &'static str = "\
+Commands take the same arguments as on the command line, except that the last one extends
+to the end of the line, so queries, objects and descriptions need no quoting. Keys with
+spaces may be given in double quotes, where \\" and \\\\ stand for a quote and a backslash:
+
+    get SPACE KEY                 put SPACE KEY OBJECT         del SPACE KEY
+    search SPACE [QUERY]          count SPACE [QUERY]          atomic-add SPACE KEY OBJECT
+    export SPACE [FILE]           import SPACE FILE            import-new SPACE FILE
+    import-csv SPACE FILE         add-space DESCRIPTION        rm-space SPACE
+    list-spaces                   dump-config                  describe SPACE
+    refresh                       help                         quit
+
+Tab completes commands, spaces and attribute names.";
+
+/// Commands that only exist in the shell.
+const SHELL_COMMANDS: [&'static str; 5] = ["describe", "refresh", "help", "quit", "exit"];
+
+/// The spaces of the cluster and the attributes of each, as last read from the coordinator.
+pub struct Schema {
+    pub spaces: Vec<String>,
+    pub schemas: HashMap<String, SpaceSchema>,
+}
+
+impl Schema {
+    fn load(admin: &Admin) -> Result<Schema, String> {
+        let spaces = try!(hyper(admin.list_spaces())).lines().map(|l| l.trim().to_string())
+                                                      .filter(|l| !l.is_empty()).collect();
+        let schemas = try!(schema::parse_config(&try!(hyper(admin.dump_config()))));
+        Ok(Schema {
+            spaces: spaces,
+            schemas: schemas.into_iter().map(|s| (s.name.clone(), s)).collect(),
+        })
+    }
+}
+
+pub struct ShellCompleter {
+    pub schema: Rc<RefCell<Schema>>,
+}
+
+fn matching<'a, I>(candidates: I, prefix: &str) -> Vec<String> where I: Iterator<Item=&'a str> {
+    let mut res: Vec<String> = candidates.filter(|c| c.starts_with(prefix)).map(|c| c.to_string()).collect();
+    res.sort();
+    res.dedup();
+    res
+}
+
+impl Completer for ShellCompleter {
+    fn complete(&self, line: &str, pos: usize) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.char_indices().rev()
+                        .find(|&(_, c)| !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.'))
+                        .map(|(i, c)| i + c.len_utf8()).unwrap_or(0);
+        let prefix = &line[start..];
+        let words: Vec<&str> = line[..start].split_whitespace().collect();
+        let schema = self.schema.borrow();
+
+        let candidates = match words.len() {
+            0 => matching(COMMANDS.iter().map(|c| c.0).chain(SHELL_COMMANDS.iter().cloned()), prefix),
+            1 if words[0] == "describe" || COMMANDS.iter().any(|c| c.0 == words[0] && c.1 >= 1) =>
+                matching(schema.spaces.iter().map(|s| &s[..]), prefix),
+            _ if words[0] == "search" || words[0] == "count" => match schema.schemas.get(words[1]) {
+                Some(s) => matching(Some(&s.key[..]).into_iter().chain(s.attributes.iter().map(|a| &a.0[..])), prefix),
+                None => Vec::new(),
+            },
+            _ => Vec::new(),
+        };
+        Ok((start, candidates))
+    }
+}
+
+/// Reads the word at the start of `line`, which may be double quoted so that it can hold
+/// spaces; inside quotes, `\"` and `\\` stand for a quote and a backslash. Returns the word
+/// and the rest of the line.
+fn next_word(line: &str) -> Result<(String, &str), String> {
+    if !line.starts_with('"') {
+        return Ok(match line.find(char::is_whitespace) {
+            Some(i) => (line[..i].to_string(), line[i..].trim_left()),
+            None => (line.to_string(), ""),
+        });
+    }
+    let mut word = String::new();
+    let mut chars = line.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((word, line[i + 1..].trim_left())),
+            '\\' => match chars.next() {
+                Some((_, c)) if c == '"' || c == '\\' => word.push(c),
+                Some((_, c)) => return Err(format!("unknown escape \\{} in {}", c, line)),
+                None => break,
+            },
+            c => word.push(c),
+        }
+    }
+    Err(format!("unterminated quote in {}", line))
+}
+
+/// Splits a line into its command and arguments, the last of which runs to the end of the line
+/// as it is. The words before it may be quoted, as in `get contacts "Derek Jones"`.
+pub fn split_line(line: &str) -> Result<(String, Vec<String>), String> {
+    let (command, mut rest) = try!(next_word(line.trim()));
+    let words = COMMANDS.iter().find(|c| c.0 == command).map(|c| c.1).unwrap_or(1);
+    let mut args = Vec::new();
+    while !rest.is_empty() && args.len() < words {
+        let (word, tail) = try!(next_word(rest));
+        args.push(word);
+        rest = tail;
+    }
+    if !rest.is_empty() {
+        args.push(rest.to_string());
+    }
+    Ok((command, args))
+}
+
+fn type_name(val: &HyperValue) -> &'static str {
+    match *val {
+        HyperString(_) => "string",
+        HyperInt(_) => "int",
+        HyperFloat(_) => "float",
+        HyperListString(_) => "list(string)",
+        HyperListInt(_) => "list(int)",
+        HyperListFloat(_) => "list(float)",
+        HyperSetString(_) => "set(string)",
+        HyperSetInt(_) => "set(int)",
+        HyperSetFloat(_) => "set(float)",
+        HyperMapStringString(_) => "map(string, string)",
+        HyperMapStringInt(_) => "map(string, int)",
+        HyperMapStringFloat(_) => "map(string, float)",
+        HyperMapIntString(_) => "map(int, string)",
+        HyperMapIntInt(_) => "map(int, int)",
+        HyperMapIntFloat(_) => "map(int, float)",
+        HyperMapFloatString(_) => "map(float, string)",
+        HyperMapFloatInt(_) => "map(float, int)",
+        HyperMapFloatFloat(_) => "map(float, float)",
+        HyperDocument(_) => "document",
+    }
+}
+
+fn pretty_string(s: &[u8]) -> String {
+    match str::from_utf8(s) {
+        Ok(s) => format!("{:?}", s),
+        Err(_) => format!("<{} bytes>", s.len()),
+    }
+}
+
+fn pretty_float(f: f64) -> String {
+    format!("{:?}", f)
+}
+
+fn int(i: &i64) -> String {
+    i.to_string()
+}
+
+fn float(f: &f64) -> String {
+    pretty_float(*f)
+}
+
+fn string(s: &Vec<u8>) -> String {
+    pretty_string(s)
+}
+
+fn float_key(f: &F64) -> String {
+    pretty_float(f.0)
+}
+
+fn pretty_map<K, V, FK, FV>(map: &HashMap<K, V>, fk: FK, fv: FV) -> String
+    where K: Ord + Eq + Hash, FK: Fn(&K) -> String, FV: Fn(&V) -> String {
+    let mut entries: Vec<(&K, &V)> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    let entries: Vec<String> = entries.into_iter().map(|(k, v)| format!("{}: {}", fk(k), fv(v))).collect();
+    format!("{{{}}}", entries.join(", "))
+}
+
+fn pretty_list<'a, I, T, F>(open: &str, elems: I, close: &str, f: F) -> String
+    where I: Iterator<Item=&'a T>, T: 'a, F: Fn(&T) -> String {
+    let elems: Vec<String> = elems.map(|e| f(e)).collect();
+    format!("{}{}{}", open, elems.join(", "), close)
+}
+
+/// Formats a value the way it would be written in a query: strings quoted, floats with a
+/// decimal point, lists in brackets and sets and maps in braces.
+pub fn pretty(val: &HyperValue) -> String {
+    match *val {
+        HyperString(ref s) => pretty_string(s),
+        HyperInt(i) => i.to_string(),
+        HyperFloat(f) => pretty_float(f),
+        HyperListString(ref l) => pretty_list("[", l.iter(), "]", string),
+        HyperListInt(ref l) => pretty_list("[", l.iter(), "]", int),
+        HyperListFloat(ref l) => pretty_list("[", l.iter(), "]", float),
+        HyperSetString(ref s) => pretty_list("{", s.iter(), "}", string),
+        HyperSetInt(ref s) => pretty_list("{", s.iter(), "}", int),
+        HyperSetFloat(ref s) => pretty_list("{", s.iter(), "}", float_key),
+        HyperMapStringString(ref m) => pretty_map(m, string, string),
+        HyperMapStringInt(ref m) => pretty_map(m, string, int),
+        HyperMapStringFloat(ref m) => pretty_map(m, string, float),
+        HyperMapIntString(ref m) => pretty_map(m, int, string),
+        HyperMapIntInt(ref m) => pretty_map(m, int, int),
+        HyperMapIntFloat(ref m) => pretty_map(m, int, float),
+        HyperMapFloatString(ref m) => pretty_map(m, float_key, string),
+        HyperMapFloatInt(ref m) => pretty_map(m, float_key, int),
+        HyperMapFloatFloat(ref m) => pretty_map(m, float_key, float),
+        HyperDocument(ref doc) => serde_json::to_string_pretty(doc).unwrap(),
+    }
+}
+
+fn print_object(obj: HyperObject) {
+    let mut attrs: Vec<(String, HyperValue)> = obj.map.into_iter().collect();
+    attrs.sort_by(|a, b| a.0.cmp(&b.0));
+    let width = attrs.iter().map(|a| a.0.len() + type_name(&a.1).len() + 3).max().unwrap_or(0);
+    for (attr, val) in attrs {
+        let label = format!("{} ({})", attr, type_name(&val));
+        let indent = format!("\n{:1$}", "", width + 2);
+        println!("{:2$}  {}", label, pretty(&val).replace("\n", &indent), width);
+    }
+    println!("");
+}
+
+fn history_file() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".hyperdex_history"))
+}
+
+pub fn run(session: &mut Session) -> Result<(), String> {
+    let schema = Rc::new(RefCell::new(try!(Schema::load(try!(session.admin())))));
+    let mut editor = Editor::new();
+    editor.set_completer(Some(ShellCompleter {
+        schema: schema.clone(),
+    }));
+    if let Some(ref path) = history_file() {
+        let _ = editor.load_history(path);
+    }
+
+    loop {
+        let line = match editor.readline("hyperdex> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.to_string()),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(&line);
+
+        let (command, args) = match split_line(&line) {
+            Ok(split) => split,
+            Err(msg) => {
+                println!("error: {}", msg);
+                continue;
+            },
+        };
+        let res = match &command[..] {
+            "quit" | "exit" => break,
+            "help" => {
+                println!("{}", HELP);
+                Ok(())
+            },
+            "refresh" => session.admin().and_then(Schema::load).map(|s| *schema.borrow_mut() = s),
+            "describe" => match args.get(0).and_then(|space| schema.borrow().schemas.get(space).cloned()) {
+                Some(s) => {
+                    println!("{} ({}, key)", s.key, s.key_type);
+                    for (attr, dt) in s.attributes {
+                        println!("{} ({})", attr, dt);
+                    }
+                    Ok(())
+                },
+                None => Err("usage: describe SPACE, for one of the spaces from list-spaces".to_string()),
+            },
+            _ => commands::run(session, &command, &args, print_object),
+        };
+        match res {
+            Ok(()) => {
+                // The schema changes along with the spaces
+                if command == "add-space" || command == "rm-space" {
+                    if let Ok(s) = session.admin().and_then(Schema::load) {
+                        *schema.borrow_mut() = s;
+                    }
+                }
+            },
+            Err(msg) => println!("error: {}", msg),
+        }
+    }
+
+    if let Some(ref path) = history_file() {
+        let _ = editor.save_history(path);
+    }
+    Ok(())
+}
@@ -0,0 +1,190 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use rustyline::completion::Completer;
+
+use serde_json::Value;
+
+use hyperdex::F64;
+use hyperdex::HyperValue::*;
+use hyperdex::schema::{Datatype, Primitive, SpaceSchema};
+
+use commands::{check_args, object_from_json, usage, Session};
+use shell::{pretty, split_line, Schema, ShellCompleter};
+use super::{parse_options, Options};
+
+const COORDINATOR: &'static str = "127.0.0.1:1982";
+
+fn parse(args: &[&str]) -> Result<Options, String> {
+    parse_options(args.iter().map(|a| a.to_string()), COORDINATOR.to_string())
+}
+
+fn session(key_type: Datatype) -> Session {
+    Session::new(SocketAddr::from_str(COORDINATOR).unwrap(), key_type)
+}
+
+#[test]
+fn test_parse_options() {
+    let opts = parse(&["get", "contacts", "derek"]).unwrap();
+    assert_eq!(opts.coordinator, SocketAddr::from_str(COORDINATOR).unwrap());
+    assert_eq!(opts.key_type, Datatype::String);
+    assert_eq!(opts.command, "get");
+    assert_eq!(opts.args, vec!("contacts".to_string(), "derek".to_string()));
+
+    let opts = parse(&["-c", "10.0.0.1:2000", "--int-key", "del", "counters", "7"]).unwrap();
+    assert_eq!(opts.coordinator, SocketAddr::from_str("10.0.0.1:2000").unwrap());
+    assert_eq!(opts.key_type, Datatype::Int);
+    assert_eq!(opts.command, "del");
+    assert_eq!(parse(&["--coordinator", "10.0.0.1:2000", "list-spaces"]).unwrap().coordinator,
+               SocketAddr::from_str("10.0.0.1:2000").unwrap());
+    assert_eq!(parse(&["--float-key", "get", "readings", "0.5"]).unwrap().key_type, Datatype::Float);
+
+    // Options are only read before the command, so arguments may look like them
+    let opts = parse(&["search", "contacts", "--int-key"]).unwrap();
+    assert_eq!(opts.key_type, Datatype::String);
+    assert_eq!(opts.args, vec!("contacts".to_string(), "--int-key".to_string()));
+
+    assert_eq!(parse(&["--help"]).unwrap_err(), "");
+    assert!(parse(&[]).unwrap_err().starts_with("no command given"));
+    assert_eq!(parse(&["-c"]).unwrap_err(), "-c needs an address");
+    assert_eq!(parse(&["-c", "localhost", "get"]).unwrap_err(), "\"localhost\" is not a HOST:PORT address");
+}
+
+#[test]
+fn test_check_args() {
+    let args = vec!("contacts".to_string(), "age <= 25".to_string());
+    assert_eq!(check_args("search", &args, 1, 1).unwrap(), vec!("contacts", "age <= 25"));
+    assert_eq!(check_args("search", &args[..1], 1, 1).unwrap(), vec!("contacts"));
+    assert_eq!(check_args("get", &args[..1], 2, 0).unwrap_err(), "usage: get SPACE KEY");
+    assert_eq!(check_args("list-spaces", &args, 0, 0).unwrap_err(), "usage: list-spaces ");
+    assert_eq!(usage("count"), "usage: count SPACE [QUERY]");
+}
+
+#[test]
+fn test_keys() {
+    assert_eq!(session(Datatype::String).key("7").unwrap(), HyperString("7".as_bytes().to_vec()));
+    assert_eq!(session(Datatype::Int).key("-7").unwrap(), HyperInt(-7));
+    assert_eq!(session(Datatype::Int).key("7.5").unwrap_err(), "\"7.5\" is not an int key");
+    assert_eq!(session(Datatype::Float).key("7.5").unwrap(), HyperFloat(7.5));
+    assert_eq!(session(Datatype::Float).key("7").unwrap(), HyperFloat(7.0));
+    assert_eq!(session(Datatype::Float).key("seven").unwrap_err(), "\"seven\" is not a float key");
+}
+
+#[test]
+fn test_object_from_json() {
+    let obj = object_from_json(r#"{"first": "Derek", "age": 20, "score": 1.5,
+                                   "tags": {"set(string)": ["a", "b"]},
+                                   "profile": {"document": {"visits": 1}}}"#).unwrap();
+    assert_eq!(obj.map.len(), 5);
+    assert_eq!(obj.map["first"], HyperString("Derek".as_bytes().to_vec()));
+    assert_eq!(obj.map["age"], HyperInt(20));
+    assert_eq!(obj.map["score"], HyperFloat(1.5));
+    assert_eq!(obj.map["tags"], HyperSetString(vec!("a".as_bytes().to_vec(), "b".as_bytes().to_vec())
+                                               .into_iter().collect()));
+    assert_eq!(obj.map["profile"], HyperDocument(Value::from_str(r#"{"visits": 1}"#).unwrap()));
+    assert_eq!(object_from_json("{}").unwrap().map.len(), 0);
+
+    assert!(object_from_json("{\"first\": ").unwrap_err().starts_with("invalid JSON: "));
+    assert_eq!(object_from_json("[1, 2]").unwrap_err(), "expected an object, found [1,2]");
+    assert_eq!(object_from_json(r#"{"tags": {"bag(int)": [1]}}"#).unwrap_err(),
+               "\"tags\": unknown type \"bag(int)\"");
+    assert!(object_from_json(r#"{"age": true}"#).unwrap_err().starts_with("\"age\": "));
+}
+
+fn split(line: &str) -> (String, Vec<String>) {
+    split_line(line).unwrap()
+}
+
+#[test]
+fn test_split_line() {
+    assert_eq!(split("  list-spaces  "), ("list-spaces".to_string(), vec!()));
+    assert_eq!(split("get contacts derek"), ("get".to_string(), vec!("contacts".to_string(), "derek".to_string())));
+    // The last argument runs to the end of the line as it is
+    assert_eq!(split("search contacts age <= 25 and first == \"Derek\"").1,
+               vec!("contacts".to_string(), "age <= 25 and first == \"Derek\"".to_string()));
+    assert_eq!(split("put contacts derek {\"first\": \"Derek\"}").1[2], "{\"first\": \"Derek\"}");
+    assert_eq!(split("describe contacts extra").1, vec!("contacts".to_string(), "extra".to_string()));
+
+    // The words before it may be quoted
+    assert_eq!(split("get contacts \"Derek Jones\"").1, vec!("contacts".to_string(), "Derek Jones".to_string()));
+    assert_eq!(split("put \"contacts\"   \"a b\" {}").1,
+               vec!("contacts".to_string(), "a b".to_string(), "{}".to_string()));
+    assert_eq!(split("get contacts \"\"").1, vec!("contacts".to_string(), "".to_string()));
+    assert_eq!(split(r#"del contacts "say \"hi\" \\ bye""#).1,
+               vec!("contacts".to_string(), r#"say "hi" \ bye"#.to_string()));
+    assert_eq!(split(r#"get contacts ab\"c"#).1, vec!("contacts".to_string(), r#"ab\"c"#.to_string()));
+
+    assert_eq!(split_line("get contacts \"derek").unwrap_err(), "unterminated quote in \"derek");
+    assert_eq!(split_line(r#"get contacts "derek\"#).unwrap_err(), r#"unterminated quote in "derek\"#);
+    assert_eq!(split_line(r#"get contacts "a\nb""#).unwrap_err(), r#"unknown escape \n in "a\nb""#);
+}
+
+fn completer() -> ShellCompleter {
+    let contacts = SpaceSchema {
+        name: "contacts".to_string(),
+        key: "username".to_string(),
+        key_type: Datatype::String,
+        attributes: vec!(("first".to_string(), Datatype::String),
+                         ("friends".to_string(), Datatype::Set(Primitive::String)),
+                         ("age".to_string(), Datatype::Int)),
+    };
+    let mut schemas = HashMap::new();
+    schemas.insert("contacts".to_string(), contacts);
+    ShellCompleter {
+        schema: Rc::new(RefCell::new(Schema {
+            spaces: vec!("contacts".to_string(), "counters".to_string(), "profiles".to_string()),
+            schemas: schemas,
+        })),
+    }
+}
+
+fn complete(line: &str) -> (usize, Vec<String>) {
+    completer().complete(line, line.len()).unwrap()
+}
+
+fn strings(words: &[&str]) -> Vec<String> {
+    words.iter().map(|w| w.to_string()).collect()
+}
+
+#[test]
+fn test_complete() {
+    assert_eq!(complete("ge"), (0, strings(&["get"])));
+    assert_eq!(complete("import"), (0, strings(&["import", "import-csv", "import-new"])));
+    assert_eq!(complete("d"), (0, strings(&["del", "describe", "dump-config"])));
+    assert_eq!(complete("q"), (0, strings(&["quit"])));
+    assert_eq!(complete("").1.len(), 19);
+
+    assert_eq!(complete("get co"), (4, strings(&["contacts", "counters"])));
+    assert_eq!(complete("describe pr"), (9, strings(&["profiles"])));
+    assert_eq!(complete("add-space co"), (10, vec!()));
+    assert_eq!(complete("get contacts de"), (13, vec!()));
+
+    assert_eq!(complete("search contacts "), (16, strings(&["age", "first", "friends", "username"])));
+    assert_eq!(complete("count contacts fri"), (15, strings(&["friends"])));
+    assert_eq!(complete("search contacts age <= 25 and fi"), (30, strings(&["first"])));
+    assert_eq!(complete("search people fi"), (14, vec!()));
+
+    // Only the text before the cursor counts
+    assert_eq!(completer().complete("get co contacts", 6).unwrap(), (4, strings(&["contacts", "counters"])));
+}
+
+#[test]
+fn test_pretty() {
+    assert_eq!(pretty(&HyperString("Derek".as_bytes().to_vec())), "\"Derek\"");
+    assert_eq!(pretty(&HyperString("say \"hi\"".as_bytes().to_vec())), "\"say \\\"hi\\\"\"");
+    assert_eq!(pretty(&HyperString(vec!(0xff, 0xfe))), "<2 bytes>");
+    assert_eq!(pretty(&HyperInt(-7)), "-7");
+    assert_eq!(pretty(&HyperFloat(2.0)), "2.0");
+    assert_eq!(pretty(&HyperListInt(vec!(3, 1, 2))), "[3, 1, 2]");
+    assert_eq!(pretty(&HyperListString(vec!())), "[]");
+    assert_eq!(pretty(&HyperSetFloat(vec!(F64(1.5), F64(0.5)).into_iter().collect())), "{0.5, 1.5}");
+
+    let mut map = HashMap::new();
+    map.insert("b".as_bytes().to_vec(), 2);
+    map.insert("a".as_bytes().to_vec(), 1);
+    assert_eq!(pretty(&HyperMapStringInt(map)), "{\"a\": 1, \"b\": 2}");
+    assert_eq!(pretty(&HyperDocument(Value::from_str("{\"visits\": 1}").unwrap())), "{\n  \"visits\": 1\n}");
+}
@@ -0,0 +1,183 @@
+use std::ffi::CStr;
+use std::marker::PhantomData;
+use std::slice;
+use std::str;
+use std::sync::mpsc::Receiver;
+
+use libc::*;
+
+use codec;
+use client::build_hyperobject;
+use common::*;
+use hyperdex::*;
+use hyperdex_client::*;
+use client_types::*;
+use client_types::HyperObjectKeyError::*;
+
+/// The encoded value of an attribute, borrowed from wherever the attribute points.
+pub unsafe fn attribute_value(attr: &Struct_hyperdex_client_attribute) -> &[u8] {
+    if attr.value.is_null() || attr.value_sz == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(attr.value as *const u8, attr.value_sz as usize)
+    }
+}
+
+/// An attribute array returned by libhyperdex, freed with `hyperdex_client_destroy_attrs` when
+/// dropped.
+///
+/// libhyperdex allocates the array, the attribute names and the values as a single block, so
+/// nothing inside it may be freed or taken over on its own: values are either copied out or
+/// borrowed for no longer than the buffer lives.
+pub struct AttributeBuffer {
+    attrs: *const Struct_hyperdex_client_attribute,
+    attrs_sz: size_t,
+}
+
+unsafe impl Send for AttributeBuffer {}
+
+impl AttributeBuffer {
+    /// Takes ownership of an attribute array that the client library handed out.
+    pub unsafe fn new(attrs: *const Struct_hyperdex_client_attribute, attrs_sz: size_t) -> AttributeBuffer {
+        AttributeBuffer {
+            attrs: attrs,
+            attrs_sz: attrs_sz,
+        }
+    }
+
+    fn attrs(&self) -> &[Struct_hyperdex_client_attribute] {
+        if self.attrs.is_null() {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.attrs, self.attrs_sz as usize) }
+        }
+    }
+}
+
+impl Drop for AttributeBuffer {
+    fn drop(&mut self) {
+        if !self.attrs.is_null() {
+            unsafe {
+                hyperdex_client_destroy_attrs(self.attrs, self.attrs_sz);
+            }
+        }
+    }
+}
+
+/// A read-only view of an object that decodes its attributes on demand.
+///
+/// Unlike `HyperObject`, nothing is copied out of the buffer libhyperdex returned: string
+/// attributes are handed out as slices into it, and other attributes are only decoded when they
+/// are asked for.  The buffer is released when the view is dropped, and the view cannot outlive
+/// the client that produced it.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut total = 0;
+/// for obj in client.search_borrowed(space_name, vec!()) {
+///     let obj = obj.unwrap();
+///     let age: i64 = obj.get("age").unwrap();
+///     total += age;
+/// }
+/// ```
+pub struct BorrowedObject<'a> {
+    buf: AttributeBuffer,
+    _client: PhantomData<&'a ()>,
+}
+
+impl<'a> BorrowedObject<'a> {
+    pub fn new(buf: AttributeBuffer) -> BorrowedObject<'a> {
+        BorrowedObject {
+            buf: buf,
+            _client: PhantomData,
+        }
+    }
+
+    fn find(&self, attr: &str) -> Option<&Struct_hyperdex_client_attribute> {
+        self.buf.attrs().iter().find(|a| unsafe { CStr::from_ptr(a.attr) }.to_bytes() == attr.as_bytes())
+    }
+
+    /// The number of attributes in the object.
+    pub fn len(&self) -> usize {
+        self.buf.attrs().len()
+    }
+
+    /// The names of the attributes in the object.
+    pub fn attribute_names(&self) -> Vec<&str> {
+        self.buf.attrs().iter().filter_map(|a| {
+            str::from_utf8(unsafe { CStr::from_ptr(a.attr) }.to_bytes()).ok()
+        }).collect()
+    }
+
+    /// The datatype of an attribute, as one of the `HYPERDATATYPE_*` codes.
+    pub fn datatype(&self, attr: &str) -> Option<Enum_hyperdatatype> {
+        self.find(attr).map(|a| a.datatype)
+    }
+
+    /// The encoded value of an attribute, in the format described in the `codec` module.
+    pub fn raw(&self, attr: &str) -> Option<&[u8]> {
+        self.find(attr).map(|a| unsafe { attribute_value(a) })
+    }
+
+    /// The value of a string attribute, without copying it.
+    pub fn get_bytes(&self, attr: &str) -> Result<&[u8], HyperObjectKeyError> {
+        match self.datatype(attr) {
+            Some(HYPERDATATYPE_STRING) => Ok(self.raw(attr).unwrap()),
+            Some(_) => Err(ObjectIsAnotherType),
+            None => Err(KeyDoesNotExist),
+        }
+    }
+
+    /// Like `get_bytes`, for strings that hold UTF-8 text.
+    pub fn get_str(&self, attr: &str) -> Result<&str, HyperObjectKeyError> {
+        self.get_bytes(attr).and_then(|b| str::from_utf8(b).map_err(|_| ObjectIsAnotherType))
+    }
+
+    /// Decodes a single attribute.
+    pub fn get_value(&self, attr: &str) -> Result<HyperValue, HyperObjectKeyError> {
+        match self.find(attr) {
+            Some(a) => codec::decode(a.datatype, self.raw(attr).unwrap()).map_err(|_| ObjectIsAnotherType),
+            None => Err(KeyDoesNotExist),
+        }
+    }
+
+    /// Decodes a single attribute into a Rust type, like `HyperObject::get`.
+    pub fn get<T>(&self, attr: &str) -> Result<T, HyperObjectKeyError> where T: FromHyperValue {
+        self.get_value(attr).and_then(FromHyperValue::from_hyper)
+    }
+
+    /// Decodes every attribute into an owned `HyperObject`.
+    pub fn to_object(&self) -> Result<HyperObject, HyperError> {
+        unsafe { build_hyperobject(self.buf.attrs, self.buf.attrs_sz) }.map_err(|msg| HyperError {
+            status: HYPERDEX_CLIENT_SERVERERROR,
+            message: msg,
+            location: String::new(),
+        })
+    }
+}
+
+/// The results of `Client::search_borrowed`, as an iterator of `BorrowedObject`s.
+///
+/// Buffers of results that are never consumed are released along with the iterator.
+pub struct BorrowedSearch<'a> {
+    res_rx: Receiver<Result<AttributeBuffer, HyperError>>,
+    _client: PhantomData<&'a ()>,
+}
+
+impl<'a> BorrowedSearch<'a> {
+    pub fn new(res_rx: Receiver<Result<AttributeBuffer, HyperError>>) -> BorrowedSearch<'a> {
+        BorrowedSearch {
+            res_rx: res_rx,
+            _client: PhantomData,
+        }
+    }
+}
+
+impl<'a> Iterator for BorrowedSearch<'a> {
+    type Item = Result<BorrowedObject<'a>, HyperError>;
+
+    fn next(&mut self) -> Option<Result<BorrowedObject<'a>, HyperError>> {
+        self.res_rx.recv().ok().map(|res| res.map(BorrowedObject::new))
+    }
+}
@@ -0,0 +1,305 @@
+//! A read-through cache in front of a store, for objects that are read far more often than they
+//! are written, such as configuration.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant, SystemTime};
+
+use codec;
+use common::*;
+use client::Client;
+use client_types::*;
+use hyperdex::Enum_hyperdatatype;
+use store::KeyValueStore;
+
+/// How often `get` was answered from the cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Where a `CachedClient` reads the time from when it decides whether an object has expired,
+/// and a `Lock` when it stamps and judges leases.
+pub trait Clock {
+    fn now(&self) -> Instant;
+    /// The wall-clock time, which unlike `now` can be compared between machines.
+    fn system_time(&self) -> SystemTime;
+}
+
+/// The system's clocks, which are used unless another `Clock` is given.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A space and a key, with the key encoded the way HyperDex stores it.
+type CacheKey = (Vec<u8>, Enum_hyperdatatype, Vec<u8>);
+
+struct Entry {
+    obj: HyperObject,
+    expires: Instant,
+    /// The position of the entry in `CachedClient::recent`.
+    used: u64,
+}
+
+/// Wraps a store, keeping the objects that `get` returns for up to `ttl` and evicting the least
+/// recently used ones beyond `capacity`.
+///
+/// Writes made through the cache drop the object they touch before they are sent, so a client
+/// always reads its own writes.  Conditional writes go straight to the store and are checked
+/// against the stored object, never the cached one; they drop the object too.  Writes made by
+/// other clients are only seen once the cached object expires.
+///
+/// `get_partial`, `get_many` and searches are not cached, and `get_latest` always reads the
+/// store, so that `KeyValueStore::update` never starts from a stale copy.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut client = CachedClient::new(Client::new(coord).unwrap(), 1000, Duration::from_secs(30));
+/// let settings = client.get("settings", "site").unwrap();
+/// println!("{} hits, {} misses", client.stats().hits, client.stats().misses);
+/// ```
+pub struct CachedClient<C = Client> {
+    store: C,
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<CacheKey, Entry>,
+    /// The cached keys, least recently used first.
+    recent: BTreeMap<u64, CacheKey>,
+    clock: u64,
+    stats: CacheStats,
+    time: Box<Clock + Send>,
+}
+
+impl<C: KeyValueStore> CachedClient<C> {
+    /// Caches at most `capacity` objects of `store`, for `ttl` each.
+    pub fn new(store: C, capacity: usize, ttl: Duration) -> CachedClient<C> {
+        CachedClient::with_clock(store, capacity, ttl, SystemClock)
+    }
+
+    /// Like `new`, but reads the time from `time`, so that a test can let objects expire
+    /// without waiting for them to.
+    pub fn with_clock<T>(store: C, capacity: usize, ttl: Duration, time: T) -> CachedClient<C>
+        where T: Clock + Send + 'static {
+        CachedClient {
+            store: store,
+            capacity: capacity,
+            ttl: ttl,
+            entries: HashMap::new(),
+            recent: BTreeMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+            time: Box::new(time),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// The number of objects in the cache, including expired ones that have not been evicted yet.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Drops every cached object.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recent.clear();
+    }
+
+    /// Drops the cached copy of an object, for instance after learning that another client
+    /// changed it.
+    pub fn invalidate<S, K>(&mut self, space: S, key: K) where S: ToCStr, K: ToHyperValue {
+        self.take(space, key);
+    }
+
+    /// The store itself.  Writes made through it are not seen until the cached copies of the
+    /// objects they touch expire.
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.store
+    }
+
+    /// Unwraps the store, dropping the cache.
+    pub fn into_inner(self) -> C {
+        self.store
+    }
+
+    /// Drops the cached copy of an object and hands back its space and key, ready to be passed on
+    /// to the store.
+    fn take<S, K>(&mut self, space: S, key: K) -> (Vec<u8>, HyperValue) where S: ToCStr, K: ToHyperValue {
+        let space = space.to_c_str().into_bytes();
+        let key = key.to_hyper();
+        let ck = (space.clone(), codec::datatype(&key), codec::encode(&key));
+        if let Some(entry) = self.entries.remove(&ck) {
+            self.recent.remove(&entry.used);
+        }
+        (space, key)
+    }
+
+    fn touch(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    pub fn get<S, K>(&mut self, space: S, key: K) -> Result<HyperObject, HyperError> where S: ToCStr, K: ToHyperValue {
+        let space = space.to_c_str().into_bytes();
+        let key = key.to_hyper();
+        let ck = (space.clone(), codec::datatype(&key), codec::encode(&key));
+
+        let now = self.time.now();
+        let used = self.touch();
+        if let Some(entry) = self.entries.get_mut(&ck) {
+            if entry.expires > now {
+                self.stats.hits += 1;
+                self.recent.remove(&entry.used);
+                self.recent.insert(used, ck.clone());
+                entry.used = used;
+                return Ok(entry.obj.clone());
+            }
+        }
+        self.stats.misses += 1;
+        if let Some(entry) = self.entries.remove(&ck) {
+            self.recent.remove(&entry.used);
+        }
+
+        let obj = try!(self.store.get(space, key));
+        if self.capacity > 0 {
+            while self.entries.len() >= self.capacity {
+                let oldest = *self.recent.keys().next().unwrap();
+                let old_key = self.recent.remove(&oldest).unwrap();
+                self.entries.remove(&old_key);
+            }
+            self.recent.insert(used, ck.clone());
+            self.entries.insert(ck, Entry {
+                obj: obj.clone(),
+                expires: now + self.ttl,
+                used: used,
+            });
+        }
+        Ok(obj)
+    }
+
+    /// Reads an object from the store, dropping the cached copy instead of returning it.
+    pub fn get_latest<S, K>(&mut self, space: S, key: K) -> Result<HyperObject, HyperError>
+        where S: ToCStr, K: ToHyperValue {
+        let (space, key) = self.take(space, key);
+        self.store.get(space, key)
+    }
+
+    pub fn get_partial<S, K, A>(&mut self, space: S, key: K, attrs: Vec<A>)
+        -> Result<HyperObject, HyperError> where S: ToCStr, K: ToHyperValue, A: ToString {
+        self.store.get_partial(space, key, attrs)
+    }
+
+    pub fn del<S, K>(&mut self, space: S, key: K) -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue {
+        let (space, key) = self.take(space, key);
+        self.store.del(space, key)
+    }
+
+    pub fn search<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr {
+        self.store.search(space, checks)
+    }
+
+    pub fn search_keyed<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Receiver<Result<(HyperKey, HyperObject), HyperError>> where S: ToCStr {
+        self.store.search_keyed(space, checks)
+    }
+
+    pub fn sorted_search<S, A>(&mut self, space: S, checks: Vec<HyperPredicate>, sort_by: A, limit: u64, maxmin: bool)
+        -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr, A: ToCStr {
+        self.store.sorted_search(space, checks, sort_by, limit, maxmin)
+    }
+
+    pub fn count<S>(&mut self, space: S, checks: Vec<HyperPredicate>) -> Result<u64, HyperError> where S: ToCStr {
+        self.store.count(space, checks)
+    }
+
+    pub fn get_many<S, K, I>(&mut self, space: S, keys: I)
+        -> HashMap<K, Result<HyperObject, HyperError>>
+        where S: ToCStr + Clone, K: ToHyperValue + Clone + Hash + Eq, I: IntoIterator<Item=K> {
+        self.store.get_many(space, keys)
+    }
+
+    pub fn get_partial_many<S, K, A, I>(&mut self, space: S, keys: I, attrs: Vec<A>)
+        -> HashMap<K, Result<HyperObject, HyperError>>
+        where S: ToCStr + Clone, K: ToHyperValue + Clone + Hash + Eq, A: ToString + Clone,
+              I: IntoIterator<Item=K> {
+        self.store.get_partial_many(space, keys, attrs)
+    }
+}
+
+/// Defines a write that drops the cached copy of the object before passing the write on.
+macro_rules! invalidating_fn(
+    ($fn_name: ident $(, $arg: ident: $arg_ty: ty)*) => (
+        impl<C: KeyValueStore> CachedClient<C> {
+            pub fn $fn_name<S, K>(&mut self, space: S, key: K $(, $arg: $arg_ty)*)
+                -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                let (space, key) = self.take(space, key);
+                self.store.$fn_name(space, key $(, $arg)*)
+            }
+        }
+    );
+);
+
+invalidating_fn!(cond_del, checks: Vec<HyperPredicate>);
+
+invalidating_fn!(put, value: HyperObject);
+invalidating_fn!(put_if_not_exist, value: HyperObject);
+invalidating_fn!(atomic_add, value: HyperObject);
+invalidating_fn!(atomic_sub, value: HyperObject);
+invalidating_fn!(atomic_mul, value: HyperObject);
+invalidating_fn!(atomic_div, value: HyperObject);
+invalidating_fn!(atomic_mod, value: HyperObject);
+invalidating_fn!(atomic_and, value: HyperObject);
+invalidating_fn!(atomic_or, value: HyperObject);
+invalidating_fn!(atomic_xor, value: HyperObject);
+invalidating_fn!(string_prepend, value: HyperObject);
+invalidating_fn!(string_append, value: HyperObject);
+invalidating_fn!(list_lpush, value: HyperObject);
+invalidating_fn!(list_rpush, value: HyperObject);
+invalidating_fn!(set_add, value: HyperObject);
+invalidating_fn!(set_remove, value: HyperObject);
+invalidating_fn!(set_intersect, value: HyperObject);
+invalidating_fn!(set_union, value: HyperObject);
+invalidating_fn!(map_remove, value: HyperObject);
+
+invalidating_fn!(cond_put, checks: Vec<HyperPredicate>, value: HyperObject);
+
+invalidating_fn!(map_add, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(map_atomic_add, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(map_atomic_sub, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(map_atomic_mul, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(map_atomic_div, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(map_atomic_mod, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(map_atomic_and, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(map_atomic_or, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(map_atomic_xor, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(map_string_prepend, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(map_string_append, mapattrs: Vec<HyperMapAttribute>);
+
+invalidating_fn!(cond_map_add, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(cond_map_atomic_add, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(cond_map_atomic_sub, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(cond_map_atomic_mul, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(cond_map_atomic_div, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(cond_map_atomic_mod, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(cond_map_atomic_and, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(cond_map_atomic_or, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(cond_map_atomic_xor, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(cond_map_string_prepend, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+invalidating_fn!(cond_map_string_append, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+
+impl_key_value_store!([C: KeyValueStore] CachedClient<C>, latest [get_latest]);
@@ -6,367 +6,73 @@ extern crate eventual;
 
 use std::net::SocketAddr;
 use std::sync::mpsc::TryRecvError;
-use std::collections::{HashMap, BTreeSet};
-use std::ffi::CString;
-use std::ptr::{null, null_mut, Unique};
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::{CStr, CString};
+use std::slice;
+use std::ptr::{null, null_mut};
 use std::mem::transmute;
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
 use std::sync::atomic;
 use std::sync::atomic::Ordering;
 use std::sync::atomic::AtomicUsize;
 use std::sync::{Arc, Mutex};
 use std::sync::mpsc::{channel, Sender, Receiver};
 use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use libc::*;
+use tracing::{self, Span};
 
-use rustc_serialize::json::Json;
-
-use eventual::{Future,Async,AsyncError};
+use eventual::{Future,Async,AsyncError,Complete};
 
 use common::*;
 use hyperdex::*;
 use hyperdex_client::*;
 use hyperdex_datastructures::*;
 use client_types::*;
-use client_types::HyperValue::*;
 use client_types::HyperState::*;
 
-use helpers;
-use helpers::FutureHelpers;
+use helpers::{FutureHelpers, Unique};
+use reactor::{Reactor, Waker};
+use borrowed::{attribute_value, AttributeBuffer, BorrowedObject, BorrowedSearch};
+use codec;
+use metrics::{Metrics, NoMetrics};
+use schema::SpaceSchema;
+use store::fetch_many;
 
-unsafe fn build_hyperobject(c_attrs: *const Struct_hyperdex_client_attribute, c_attrs_sz: size_t) -> Result<HyperObject, String> {
+/// Decodes an attribute array returned by the C client into an owned object.
+///
+/// The array and everything it points to stay owned by the caller, who remains responsible for
+/// releasing it (with `hyperdex_client_destroy_attrs` if it came from libhyperdex).  Names and
+/// values are copied out, so the object does not borrow from the array and the array may be
+/// freed as soon as this returns.  `c_attrs` may be null when `c_attrs_sz` is zero, and so may
+/// the value of an attribute whose `value_sz` is zero.
+pub unsafe fn build_hyperobject(c_attrs: *const Struct_hyperdex_client_attribute, c_attrs_sz: size_t) -> Result<HyperObject, String> {
     let mut attrs = HyperObject::new();
+    if c_attrs_sz == 0 {
+        return Ok(attrs);
+    }
 
-    for i in 0..c_attrs_sz {
-        let ref attr = *c_attrs.offset(i as isize);
-        let name = to_string(attr.attr);
-        match attr.datatype {
-            HYPERDATATYPE_STRING => {
-                attrs.insert(name,
-                             Vec::from_raw_parts(attr.value as *mut u8, attr.value_sz as usize, 1 as usize));
-            },
-            HYPERDATATYPE_INT64 => {
-                let mut cint = 0i64;
-                if hyperdex_ds_unpack_int(attr.value as *const i8, attr.value_sz, &mut cint) < 0 {
-                    return Err("Server sent a malformed int".to_string());
-                }
-                attrs.insert(name, cint);
-            },
-            HYPERDATATYPE_FLOAT => {
-                let mut cdouble = 0f64;
-                if hyperdex_ds_unpack_float(attr.value as *const i8,
-                                            attr.value_sz, &mut cdouble) < 0 {
-                    return Err("Server sent a malformed float".to_string());
-                }
-                attrs.insert(name, cdouble);
-            },
-
-            HYPERDATATYPE_LIST_STRING => {
-                let mut citer = Struct_hyperdex_ds_iterator::new();
-                hyperdex_ds_iterator_init(&mut citer, attr.datatype, attr.value, attr.value_sz);
-                let mut lst = Vec::new();
-                loop {
-                    let mut cstr = null();
-                    let mut cstr_sz = 0;
-                    let status =
-                        hyperdex_ds_iterate_list_string_next(&mut citer, &mut cstr, &mut cstr_sz);
-                    if status > 0 {
-                        lst.push(to_bytes_with_len(cstr, cstr_sz));
-                    } else if status < 0 {
-                        return Err("Server sent a corrupted list of strings".to_string());
-                    } else {
-                        break;
-                    }
-                }
-                attrs.insert(name, lst);
-            },
-            HYPERDATATYPE_LIST_INT64 => {
-                let mut citer = Struct_hyperdex_ds_iterator::new();
-                hyperdex_ds_iterator_init(&mut citer, attr.datatype, attr.value, attr.value_sz);
-                let mut lst = Vec::new();
-                loop {
-                    let mut num = 0i64;
-                    let status = hyperdex_ds_iterate_list_int_next(&mut citer, &mut num);
-                    if status > 0 {
-                        lst.push(num);
-                    } else if status < 0 {
-                        return Err("Server sent a corrupted list of integers".to_string());
-                    } else {
-                        break;
-                    }
-                }
-
-                attrs.insert(name, lst);
-            },
-            HYPERDATATYPE_LIST_FLOAT => {
-                let mut citer = Struct_hyperdex_ds_iterator::new();
-                hyperdex_ds_iterator_init(&mut citer, attr.datatype, attr.value, attr.value_sz);
-                let mut lst = Vec::new();
-                loop {
-                    let mut num = 0f64;
-                    let status = hyperdex_ds_iterate_list_float_next(&mut citer, &mut num);
-                    if status > 0 {
-                        lst.push(num);
-                    } else if status < 0 {
-                        return Err("Server sent a corrupted list of floats".to_string());
-                    } else {
-                        break;
-                    }
-                }
-
-                attrs.insert(name, lst);
-            },
-
-            HYPERDATATYPE_SET_STRING => {
-                let mut citer = Struct_hyperdex_ds_iterator::new();
-                hyperdex_ds_iterator_init(&mut citer, attr.datatype, attr.value, attr.value_sz);
-                let mut set = BTreeSet::new();
-                loop {
-                    let mut cstr = null();
-                    let mut cstr_sz = 0;
-                    let status =
-                        hyperdex_ds_iterate_set_string_next(&mut citer, &mut cstr, &mut cstr_sz);
-                    if status > 0 {
-                        set.insert(to_bytes_with_len(cstr, cstr_sz));
-                    } else if status < 0 {
-                        return Err("Server sent a corrupted set of strings".to_string());
-                    } else {
-                        break;
-                    }
-                }
-                attrs.insert(name, set);
-            },
-            HYPERDATATYPE_SET_INT64 => {
-                let mut citer = Struct_hyperdex_ds_iterator::new();
-                hyperdex_ds_iterator_init(&mut citer, attr.datatype, attr.value, attr.value_sz);
-                let mut set = BTreeSet::new();
-                loop {
-                    let mut num = 0i64;
-                    let status = hyperdex_ds_iterate_set_int_next(&mut citer, &mut num);
-                    if status > 0 {
-                        set.insert(num);
-                    } else if status < 0 {
-                        return Err("Server sent a corrupted set of integers".to_string());
-                    } else {
-                        break;
-                    }
-                }
-
-                attrs.insert(name, set);
-            },
-            HYPERDATATYPE_SET_FLOAT => {
-                let mut citer = Struct_hyperdex_ds_iterator::new();
-                hyperdex_ds_iterator_init(&mut citer, attr.datatype, attr.value, attr.value_sz);
-                let mut set = BTreeSet::new();
-                loop {
-                    let mut num = 0f64;
-                    let status = hyperdex_ds_iterate_set_float_next(&mut citer, &mut num);
-                    if status > 0 {
-                        set.insert(F64(num));
-                    } else if status < 0 {
-                        return Err("Server sent a corrupted set of floats".to_string());
-                    } else {
-                        break;
-                    }
-                }
-
-                attrs.insert(name, set);
-            },
-
-            HYPERDATATYPE_MAP_STRING_STRING => {
-                let mut citer = Struct_hyperdex_ds_iterator::new();
-                hyperdex_ds_iterator_init(&mut citer, attr.datatype, attr.value, attr.value_sz);
-                let mut map = HashMap::new();
-                loop {
-                    let mut ckey = null();
-                    let mut ckey_sz = 0;
-                    let mut cval = null();
-                    let mut cval_sz = 0;
-                    let status =
-                        hyperdex_ds_iterate_map_string_string_next(&mut citer, &mut ckey, &mut ckey_sz,
-                                                                   &mut cval, &mut cval_sz);
-                    if status > 0 {
-                        map.insert(to_bytes_with_len(ckey, ckey_sz),
-                                   to_bytes_with_len(cval, cval_sz));
-                    } else if status < 0 {
-                        return Err("Server sent a corrupted map of strings to strings".to_string());
-                    } else {
-                        break;
-                    }
-                }
-                attrs.insert(name, map);
-            },
-            HYPERDATATYPE_MAP_STRING_INT64 => {
-                let mut citer = Struct_hyperdex_ds_iterator::new();
-                hyperdex_ds_iterator_init(&mut citer, attr.datatype, attr.value, attr.value_sz);
-                let mut map = HashMap::new();
-                loop {
-                    let mut ckey = null();
-                    let mut ckey_sz = 0;
-                    let mut cval = 0;
-                    let status =
-                        hyperdex_ds_iterate_map_string_int_next(&mut citer, &mut ckey, &mut ckey_sz,
-                                                                &mut cval);
-                    if status > 0 {
-                        map.insert(to_bytes_with_len(ckey, ckey_sz), cval);
-                    } else if status < 0 {
-                        return Err("Server sent a corrupted map of strings to integers".to_string());
-                    } else {
-                        break;
-                    }
-                }
-                attrs.insert(name, map);
-            },
-            HYPERDATATYPE_MAP_STRING_FLOAT => {
-                let mut citer = Struct_hyperdex_ds_iterator::new();
-                hyperdex_ds_iterator_init(&mut citer, attr.datatype, attr.value, attr.value_sz);
-                let mut map = HashMap::new();
-                loop {
-                    let mut ckey = null();
-                    let mut ckey_sz = 0;
-                    let mut cval = 0f64;
-                    let status =
-                        hyperdex_ds_iterate_map_string_float_next(&mut citer, &mut ckey, &mut ckey_sz,
-                                                                  &mut cval);
-                    if status > 0 {
-                        map.insert(to_bytes_with_len(ckey, ckey_sz), cval);
-                    } else if status < 0 {
-                        return Err("Server sent a corrupted map of strings to floats".to_string());
-                    } else {
-                        break;
-                    }
-                }
-                attrs.insert(name, map);
-            },
-
-            HYPERDATATYPE_MAP_INT64_STRING => {
-                let mut citer = Struct_hyperdex_ds_iterator::new();
-                hyperdex_ds_iterator_init(&mut citer, attr.datatype, attr.value, attr.value_sz);
-                let mut map = HashMap::new();
-                loop {
-                    let mut ckey = 0;
-                    let mut cval = null();
-                    let mut cval_sz = 0;
-                    let status = hyperdex_ds_iterate_map_int_string_next(&mut citer, &mut ckey,
-                                                                         &mut cval, &mut cval_sz);
-                    if status > 0 {
-                        map.insert(ckey, to_bytes_with_len(cval, cval_sz));
-                    } else if status < 0 {
-                        return Err("Server sent a corrupted map of integers to strings".to_string());
-                    } else {
-                        break;
-                    }
-                }
-                attrs.insert(name, map);
-            },
-            HYPERDATATYPE_MAP_INT64_INT64 => {
-                let mut citer = Struct_hyperdex_ds_iterator::new();
-                hyperdex_ds_iterator_init(&mut citer, attr.datatype, attr.value, attr.value_sz);
-                let mut map = HashMap::new();
-                loop {
-                    let mut ckey = 0;
-                    let mut cval = 0;
-                    let status = hyperdex_ds_iterate_map_int_int_next(&mut citer, &mut ckey, &mut cval);
-                    if status > 0 {
-                        map.insert(ckey, cval);
-                    } else if status < 0 {
-                        return Err("Server sent a corrupted map of integers to integers".to_string());
-                    } else {
-                        break;
-                    }
-                }
-                attrs.insert(name, map);
-            },
-            HYPERDATATYPE_MAP_INT64_FLOAT => {
-                let mut citer = Struct_hyperdex_ds_iterator::new();
-                hyperdex_ds_iterator_init(&mut citer, attr.datatype, attr.value, attr.value_sz);
-                let mut map = HashMap::new();
-                loop {
-                    let mut ckey = 0;
-                    let mut cval = 0f64;
-                    let status =
-                        hyperdex_ds_iterate_map_int_float_next(&mut citer, &mut ckey, &mut cval);
-                    if status > 0 {
-                        map.insert(ckey, cval);
-                    } else if status < 0 {
-                        return Err("Server sent a corrupted map of integers to floats".to_string());
-                    } else {
-                        break;
-                    }
-                }
-                attrs.insert(name, map);
-            },
-
-            HYPERDATATYPE_MAP_FLOAT_STRING => {
-                let mut citer = Struct_hyperdex_ds_iterator::new();
-                hyperdex_ds_iterator_init(&mut citer, attr.datatype, attr.value, attr.value_sz);
-                let mut map = HashMap::new();
-                loop {
-                    let mut ckey = 0f64;
-                    let mut cval = null();
-                    let mut cval_sz = 0;
-                    let status = hyperdex_ds_iterate_map_float_string_next(&mut citer, &mut ckey,
-                                                                         &mut cval, &mut cval_sz);
-                    if status > 0 {
-                        map.insert(F64(ckey), to_bytes_with_len(cval, cval_sz));
-                    } else if status < 0 {
-                        return Err("Server sent a corrupted map of floats to strings".to_string());
-                    } else {
-                        break;
-                    }
-                }
-                attrs.insert(name, map);
-            },
-            HYPERDATATYPE_MAP_FLOAT_INT64 => {
-                let mut citer = Struct_hyperdex_ds_iterator::new();
-                hyperdex_ds_iterator_init(&mut citer, attr.datatype, attr.value, attr.value_sz);
-                let mut map = HashMap::new();
-                loop {
-                    let mut ckey = 0f64;
-                    let mut cval = 0;
-                    let status = hyperdex_ds_iterate_map_float_int_next(&mut citer, &mut ckey,
-                                                                        &mut cval);
-                    if status > 0 {
-                        map.insert(F64(ckey), cval);
-                    } else if status < 0 {
-                        return Err("Server sent a corrupted map of floats to integers".to_string());
-                    } else {
-                        break;
-                    }
-                }
-                attrs.insert(name, map);
-            },
-            HYPERDATATYPE_MAP_FLOAT_FLOAT => {
-                let mut citer = Struct_hyperdex_ds_iterator::new();
-                hyperdex_ds_iterator_init(&mut citer, attr.datatype, attr.value, attr.value_sz);
-                let mut map = HashMap::new();
-                loop {
-                    let mut ckey = 0f64;
-                    let mut cval = 0f64;
-                    let status =
-                        hyperdex_ds_iterate_map_float_float_next(&mut citer, &mut ckey, &mut cval);
-                    if status > 0 {
-                        map.insert(F64(ckey), cval);
-                    } else if status < 0 {
-                        return Err("Server sent a corrupted map of floats to floats".to_string());
-                    } else {
-                        break;
-                    }
-                }
-                attrs.insert(name, map);
-            },
-
-            HYPERDATATYPE_DOCUMENT => {
-                let s = String::from_raw_parts(attr.value as *mut u8, (attr.value_sz - 1) as usize, (attr.value_sz - 1) as usize);
-                attrs.insert(name, Json::from_str(s.as_str()).unwrap());
-            },
-
-            _ => { return Err(format!("Unrecognized datatype: {}", attr.datatype)); }
-        }
+    for attr in slice::from_raw_parts(c_attrs, c_attrs_sz as usize).iter() {
+        let name = String::from_utf8_lossy(CStr::from_ptr(attr.attr).to_bytes()).into_owned();
+        let val = try!(codec::decode(attr.datatype, attribute_value(attr)));
+        attrs.map.insert(name, val);
     }
 
-    return Ok(attrs);
+    Ok(attrs)
+}
+
+/// Decodes an object found by a search, taking out its key, which is the attribute named `key`.
+pub unsafe fn build_keyed_object(c_attrs: *const Struct_hyperdex_client_attribute, c_attrs_sz: size_t, key: &str)
+    -> Result<(HyperKey, HyperObject), String> {
+    let mut obj = try!(build_hyperobject(c_attrs, c_attrs_sz));
+    let key = match obj.map.remove(key) {
+        Some(val) => try!(HyperKey::from_hyper(val).map_err(|err| err.message)),
+        None => return Err(format!("the object has no key attribute \"{}\"", key)),
+    };
+    Ok((key, obj))
 }
 
 unsafe fn convert_map_attributes(arena: *mut Struct_hyperdex_ds_arena, mapattrs: Vec<HyperMapAttribute>)
@@ -401,598 +107,838 @@ unsafe fn convert_cstring(arena: *mut Struct_hyperdex_ds_arena, s: String) -> Re
     }
 }
 
-unsafe fn convert_type(arena: *mut Struct_hyperdex_ds_arena, val: HyperValue) -> Result<(*const i8, size_t, Enum_hyperdatatype), String> {
+/// Copies `val` into `arena`, packed by `codec::encode`, and returns it the way the C client
+/// takes values.  The arena only owns the bytes, so they live as long as the request needs them.
+pub unsafe fn convert_type(arena: *mut Struct_hyperdex_ds_arena, val: HyperValue) -> Result<(*const i8, size_t, Enum_hyperdatatype), String> {
+    let buf = codec::encode(&val);
     let mut status = 0;
     let mut cs = null();
     let mut sz = 0;
-    let mem_err = Err("failed to allocate memory".to_string());
-
-    match val {
-        HyperString(s) => {
-            let slen = s.len() as u64;
-            let cstr = s.to_c_str();
-            if hyperdex_ds_copy_string(arena, cstr.as_ptr() as *const i8, slen,
-                                       &mut status, &mut cs, &mut sz) < 0 {
-                mem_err
-            } else {
-                Ok((cs, sz, HYPERDATATYPE_STRING))
-            }
-        },
-        HyperInt(i) => {
-            if hyperdex_ds_copy_int(arena, i, &mut status, &mut cs, &mut sz) < 0 {
-                mem_err
-            } else {
-                Ok((cs, sz, HYPERDATATYPE_INT64))
-            }
-        },
-        HyperFloat(f) => {
-            if hyperdex_ds_copy_float(arena, f, &mut status, &mut cs, &mut sz) < 0 {
-                mem_err
-            } else {
-                Ok((cs, sz, HYPERDATATYPE_FLOAT))
-            }
-        },
-        HyperListString(ls) => {
-            let ds_lst = hyperdex_ds_allocate_list(arena);
-            if ds_lst.is_null() {
-                mem_err
-            } else {
-                for s in ls.into_iter() {
-                    let cstr = s.to_c_str();
-                    if hyperdex_ds_list_append_string(ds_lst, cstr.as_ptr(),
-                                                      cstr.as_bytes().len() as u64, &mut status) < 0 {
-                        return mem_err;
-                    }
-                }
-                let mut dt = 0;
+    if hyperdex_ds_copy_string(arena, buf.as_ptr() as *const i8, buf.len() as u64,
+                               &mut status, &mut cs, &mut sz) < 0 {
+        Err("failed to allocate memory".to_string())
+    } else {
+        Ok((cs, sz, codec::datatype(&val)))
+    }
+}
 
-                if hyperdex_ds_list_finalize(ds_lst, &mut status, &mut cs, &mut sz, &mut dt) < 0 {
-                    mem_err
-                } else {
-                    Ok((cs, sz, dt))
-                }
-            }
-        },
-        HyperListInt(ls) => {
-            let ds_lst = hyperdex_ds_allocate_list(arena);
-            if ds_lst.is_null() {
-                mem_err
-            } else {
-                for d in ls.into_iter() {
-                    if hyperdex_ds_list_append_int(ds_lst, d, &mut status) < 0 {
-                        return mem_err;
-                    }
-                }
-                let mut dt = 0;
+unsafe fn convert_attributenames(arena: *mut Struct_hyperdex_ds_arena, attrs: Vec<String>)
+    -> Result<Vec<*const i8>, String> {
+    let mut res = Vec::with_capacity(attrs.len());
+    for attr in attrs.into_iter() {
+        res.push(try!(convert_cstring(arena, attr)));
+    }
+    Ok(res)
+}
 
-                if hyperdex_ds_list_finalize(ds_lst, &mut status, &mut cs, &mut sz, &mut dt) < 0 {
-                    mem_err
-                } else {
-                    Ok((cs, sz, dt))
-                }
-            }
-        },
-        HyperListFloat(ls) => {
-            let ds_lst = hyperdex_ds_allocate_list(arena);
-            if ds_lst.is_null() {
-                mem_err
-            } else {
-                for f in ls.into_iter() {
-                    if hyperdex_ds_list_append_float(ds_lst, f, &mut status) < 0 {
-                        return mem_err;
-                    }
-                }
-                let mut dt = 0;
+pub unsafe fn convert_predicates(arena: *mut Struct_hyperdex_ds_arena, predicates: Vec<HyperPredicate>)
+    -> Result<Vec<Struct_hyperdex_client_attribute_check>, String> {
+    let mut res = Vec::with_capacity(predicates.len());
+    for p in predicates.into_iter() {
+        let attr = try!(convert_cstring(arena, p.attr));
+        let (val, val_sz, dt) = try!(convert_type(arena, p.value));
+        res.push(Struct_hyperdex_client_attribute_check {
+            attr: attr,
+            value: val,
+            value_sz: val_sz,
+            datatype: dt,
+            predicate: p.predicate as u32,
+        });
+    }
+    Ok(res)
+}
 
-                if hyperdex_ds_list_finalize(ds_lst, &mut status, &mut cs, &mut sz, &mut dt) < 0 {
-                    mem_err
-                } else {
-                    Ok((cs, sz, dt))
-                }
-            }
-        },
-        HyperSetString(set) => {
-            let ds_set = hyperdex_ds_allocate_set(arena);
-            if ds_set.is_null() {
-                mem_err
-            } else {
-                for s in set.into_iter() {
-                    let cstr = s.to_c_str();
-                    if hyperdex_ds_set_insert_string(ds_set, cstr.as_ptr(),
-                                                     cstr.as_bytes().len() as u64, &mut status) < 0 {
-                        return mem_err;
-                    }
-                }
-                let mut dt = 0;
+unsafe fn convert_hyperobject(arena: *mut Struct_hyperdex_ds_arena, obj: HyperObject) -> Result<Vec<Struct_hyperdex_client_attribute>, String> {
+    let mut attrs = Vec::new();
 
-                if hyperdex_ds_set_finalize(ds_set, &mut status, &mut cs, &mut sz, &mut dt) < 0 {
-                    mem_err
-                } else {
-                    Ok((cs, sz, dt))
-                }
-            }
-        },
-        HyperSetInt(set) => {
-            let ds_set = hyperdex_ds_allocate_set(arena);
-            if ds_set.is_null() {
-                mem_err
-            } else {
-                for d in set.into_iter() {
-                    if hyperdex_ds_set_insert_int(ds_set, d, &mut status) < 0 {
-                        return mem_err;
-                    }
-                }
-                let mut dt = 0;
+    for (key, val) in obj.map.into_iter() {
+        let mut ckey = try!(convert_cstring(arena, key));
+        let (cval, cval_sz, dt) = try!(convert_type(arena, val));
+        attrs.push(Struct_hyperdex_client_attribute {
+            attr: ckey,
+            value: cval,
+            value_sz: cval_sz,
+            datatype: dt,
+        });
+    }
 
-                if hyperdex_ds_set_finalize(ds_set, &mut status, &mut cs, &mut sz, &mut dt) < 0 {
-                    mem_err
-                } else {
-                    Ok((cs, sz, dt))
-                }
-            }
-        },
-        HyperSetFloat(set) => {
-            let ds_set = hyperdex_ds_allocate_set(arena);
-            if ds_set.is_null() {
-                mem_err
-            } else {
-                for F64(f) in set.into_iter() {
-                    if hyperdex_ds_set_insert_float(ds_set, f, &mut status) < 0 {
-                        return mem_err;
-                    }
-                }
-                let mut dt = 0;
+    Ok(attrs)
+}
 
-                if hyperdex_ds_set_finalize(ds_set, &mut status, &mut cs, &mut sz, &mut dt) < 0 {
-                    mem_err
-                } else {
-                    Ok((cs, sz, dt))
-                }
-            }
-        },
-        HyperMapStringString(map) => {
-            let ds_map = hyperdex_ds_allocate_map(arena);
-            if ds_map.is_null() {
-                mem_err
-            } else {
-                for (k, v) in map.into_iter() {
-                    let cstr = k.to_c_str();
-                    if hyperdex_ds_map_insert_key_string(ds_map,
-                                                         cstr.as_ptr(), cstr.as_bytes().len() as u64,
-                                                         &mut status) < 0 {
-                        return mem_err;
-                    }
-                    let cstr = v.to_c_str();
-                    if hyperdex_ds_map_insert_val_string(ds_map,
-                                                         cstr.as_ptr(), cstr.as_bytes().len() as u64,
-                                                         &mut status) < 0 {
-                        return mem_err;
-                    }
-                }
-                let mut dt = 0;
+#[macro_export]
+/** Creates a HyperDex object.
 
-                if hyperdex_ds_map_finalize(ds_map, &mut status, &mut cs, &mut sz, &mut dt) < 0 {
-                    mem_err
-                } else {
-                    Ok((cs, sz, dt))
-                }
-            }
-        },
-        HyperMapStringInt(map) => {
-            let ds_map = hyperdex_ds_allocate_map(arena);
-            if ds_map.is_null() {
-                mem_err
-            } else {
-                for (k, v) in map.into_iter() {
-                    let cstr = k.to_c_str();
-                    if hyperdex_ds_map_insert_key_string(ds_map,
-                                                         cstr.as_ptr(), cstr.as_bytes().len() as u64,
-                                                         &mut status) < 0 {
-                        return mem_err;
-                    }
-                    if hyperdex_ds_map_insert_val_int(ds_map, v, &mut status) < 0 {
-                        return mem_err;
-                    }
-                }
-                let mut dt = 0;
+# Examples
 
-                if hyperdex_ds_map_finalize(ds_map, &mut status, &mut cs, &mut sz, &mut dt) < 0 {
-                    mem_err
-                } else {
-                    Ok((cs, sz, dt))
-                }
-            }
-        },
-        HyperMapStringFloat(map) => {
-            let ds_map = hyperdex_ds_allocate_map(arena);
-            if ds_map.is_null() {
-                mem_err
-            } else {
-                for (k, v) in map.into_iter() {
-                    let cstr = k.to_c_str();
-                    if hyperdex_ds_map_insert_key_string(ds_map,
-                                                         cstr.as_ptr(), cstr.as_bytes().len() as u64,
-                                                         &mut status) < 0 {
-                        return mem_err;
-                    }
-                    if hyperdex_ds_map_insert_val_float(ds_map, v, &mut status) < 0 {
-                        return mem_err;
-                    }
-                }
-                let mut dt = 0;
+```
+let obj = NewHyperObject!(
+    "first", "Derek",
+    "last", "Chiang",
+    "age", 20,
+);
+client.put(space, key, obj);
+```
+**/
+macro_rules! NewHyperObject(
+    ($($key: expr, $value: expr,)*) => (
+        {
+            let mut obj = HyperObject::new();
+            $(
+                obj.insert($key, $value);
+            )*
+            obj
+        }
+    );
+);
+
+/// Specifies a key-value pair for a particular map attribute.
+#[macro_export]
+macro_rules! NewHyperMapAttribute(
+    ($attr: expr, $key: expr, $value: expr) => (
+        HyperMapAttribute {
+            attr: $attr.to_string(),
+            key: $key.to_hyper(),
+            value: $value.to_hyper(),
+        }
+    );
+);
+
+/// Owns a `hyperdex_client` handle.
+///
+/// Every clone of an `InnerClient` (including the ones captured by pending futures) holds a
+/// reference to the same handle, so the handle is only destroyed once nothing can touch it anymore.
+struct ClientHandle(*mut Struct_hyperdex_client);
+
+unsafe impl Send for ClientHandle {}
+unsafe impl Sync for ClientHandle {}
+
+impl Drop for ClientHandle {
+    fn drop(&mut self) {
+        unsafe {
+            hyperdex_client_destroy(self.0);
+        }
+    }
+}
+
+/// The schemas a client has been given through `set_schema`.  Keys and predicates on other
+/// spaces are not checked by the client; the server judges them instead.
+struct Schemas {
+    spaces: HashMap<String, SpaceSchema>,
+}
+
+impl Schemas {
+    fn new() -> Schemas {
+        Schemas {
+            spaces: HashMap::new(),
+        }
+    }
+
+    fn get(&self, space: &str) -> Option<&SpaceSchema> {
+        self.spaces.get(space)
+    }
+
+    fn insert(&mut self, schema: SpaceSchema) {
+        self.spaces.insert(schema.name.clone(), schema);
+    }
+}
+
+/// The requests of an `InnerClient` that are waiting on the server, by request id.  Their
+/// number is mirrored into `pending`, so that it can be read without taking the lock.
+struct Ops {
+    states: HashMap<int64_t, HyperState>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl Ops {
+    fn new(pending: Arc<AtomicUsize>) -> Ops {
+        Ops {
+            states: HashMap::new(),
+            pending: pending,
+        }
+    }
+
+    fn insert(&mut self, req_id: int64_t, state: HyperState) {
+        self.states.insert(req_id, state);
+        self.pending.store(self.states.len(), Ordering::Relaxed);
+    }
+
+    fn remove(&mut self, req_id: &int64_t) -> Option<HyperState> {
+        let state = self.states.remove(req_id);
+        self.pending.store(self.states.len(), Ordering::Relaxed);
+        state
+    }
+
+    fn drain(&mut self) -> Vec<HyperState> {
+        self.pending.store(0, Ordering::Relaxed);
+        self.states.drain().map(|(_, state)| state).collect()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+}
+
+pub struct InnerClient {
+    ptr: Unique<Struct_hyperdex_client>,
+    handle: Arc<ClientHandle>,
+    ops: Arc<Mutex<Ops>>,
+    /// The number of requests in `ops`.
+    pending: Arc<AtomicUsize>,
+    mutex: Arc<Mutex<()>>,
+    waker: Option<Arc<Waker>>,
+    schemas: Arc<Mutex<Schemas>>,
+    /// Fixed when the client is created, so that reporting to it takes no lock.
+    metrics: Arc<Metrics>,
+    /// The position of this client among the inner clients of a `Client`, for `Metrics::set_pending`.
+    id: usize,
+}
+
+impl Clone for InnerClient {
+    fn clone(&self) -> InnerClient {
+        return InnerClient {
+            ptr: unsafe { Unique::new(*self.ptr) },
+            handle: self.handle.clone(),
+            ops: self.ops.clone(),
+            pending: self.pending.clone(),
+            mutex: self.mutex.clone(),
+            waker: self.waker.clone(),
+            schemas: self.schemas.clone(),
+            metrics: self.metrics.clone(),
+            id: self.id,
+        }
+    }
+}
+
+/// Follows one operation from the moment it is issued until it completes, reporting it to the
+/// client's `Metrics` and to a `tracing` span that lives as long as the operation.  The span is
+/// entered while the operation is issued and while its outcome is recorded, which may happen
+/// on different threads.
+pub struct OpTrace {
+    op: &'static str,
+    space: String,
+    span: Span,
+    start: Instant,
+    status: Enum_hyperdex_client_returncode,
+    metrics: Arc<Metrics>,
+}
+
+impl OpTrace {
+    fn new(metrics: Arc<Metrics>, op: &'static str, space: &[u8], key: Option<&HyperValue>) -> OpTrace {
+        let space = String::from_utf8_lossy(space).into_owned();
+        let span = info_span!("hyperdex", space = &space[..], op = op,
+                              key_hash = tracing::field::Empty, status = tracing::field::Empty);
+        if let Some(key) = key {
+            let mut hasher = DefaultHasher::new();
+            codec::encode(key).hash(&mut hasher);
+            span.record("key_hash", &hasher.finish());
+        }
+        OpTrace {
+            op: op,
+            space: space,
+            span: span,
+            start: Instant::now(),
+            status: HYPERDEX_CLIENT_SUCCESS,
+            metrics: metrics,
+        }
+    }
+
+    /// Records a failure.  A search may fail more than once before it is done.
+    pub fn error(&mut self, err: &HyperError) {
+        self.status = err.status;
+        warn!(parent: &self.span, status = err.status, "{}", err.message);
+        self.metrics.record_error(self.op, &self.space, err);
+    }
+
+    /// Records the latency and final status of the operation, and closes its span.
+    pub fn finish(self) {
+        let _entered = self.span.enter();
+        self.span.record("status", &self.status);
+        self.metrics.record_latency(self.op, &self.space, self.start.elapsed());
+    }
+
+    fn fail(mut self, err: &HyperError) {
+        self.error(err);
+        self.finish();
+    }
+
+    /// Issues the operation with `submit` and hands back its future, which completes once the
+    /// outcome has been recorded.
+    fn run<T, F>(self, submit: F) -> Future<T, HyperError>
+        where T: Send + 'static, F: FnOnce() -> Future<T, HyperError> {
+        let (completer, future) = Future::pair();
+        let submitted = {
+            let _entered = self.span.enter();
+            submit()
+        };
+        submitted.receive(move |res| match res {
+            Ok(val) => {
+                self.finish();
+                completer.complete(val);
+            },
+            Err(AsyncError::Failed(err)) => {
+                self.fail(&err);
+                completer.fail(err);
+            },
+            // Dropping the completer aborts our future as well
+            Err(AsyncError::Aborted) => self.finish(),
+        });
+        future
+    }
+}
 
-                if hyperdex_ds_map_finalize(ds_map, &mut status, &mut cs, &mut sz, &mut dt) < 0 {
-                    mem_err
+/// Completions that arrived before their request made it into `ops`.
+type ReqBuf = Vec<(int64_t, Enum_hyperdex_client_returncode)>;
+
+/// Drives every inner client of a `Client` from a single thread.
+///
+/// The thread sleeps in epoll until one of the clients' `hyperdex_client_poll` descriptors
+/// becomes readable, or until it is woken up because a request was submitted or the client is
+/// shutting down.
+fn run_reactor(reactor: Reactor, clients: Vec<InnerClient>, shutdown_rx: Receiver<Instant>,
+               err_tx: Sender<HyperError>) {
+    let mut req_bufs: Vec<ReqBuf> = clients.iter().map(|_| Vec::new()).collect();
+    let mut ready = Vec::new();
+    let mut deadline = None;
+
+    loop {
+        let timeout = match deadline {
+            None => -1,
+            Some(d) => {
+                let now = Instant::now();
+                if now >= d {
+                    0
                 } else {
-                    Ok((cs, sz, dt))
-                }
-            }
-        },
-        HyperMapIntString(map) => {
-            let ds_map = hyperdex_ds_allocate_map(arena);
-            if ds_map.is_null() {
-                mem_err
-            } else {
-                for (k, v) in map.into_iter() {
-                    if hyperdex_ds_map_insert_key_int(ds_map, k, &mut status) < 0 {
-                        return mem_err;
-                    }
-                    let cstr = v.to_c_str();
-                    if hyperdex_ds_map_insert_val_string(ds_map,
-                                                         cstr.as_ptr(), cstr.as_bytes().len() as u64,
-                                                         &mut status) < 0 {
-                        return mem_err;
-                    }
+                    let left = d - now;
+                    (left.as_secs() * 1000 + (left.subsec_nanos() / 1000000) as u64 + 1) as i32
                 }
-                let mut dt = 0;
+            },
+        };
 
-                if hyperdex_ds_map_finalize(ds_map, &mut status, &mut cs, &mut sz, &mut dt) < 0 {
-                    mem_err
-                } else {
-                    Ok((cs, sz, dt))
+        let woken = match reactor.poll(&mut ready, timeout) {
+            Ok(woken) => woken,
+            Err(err) => {
+                err_tx.send(HyperError {
+                    status: HYPERDEX_CLIENT_POLLFAILED,
+                    message: format!("{}", err),
+                    location: String::new(),
+                });
+                deadline = Some(Instant::now());
+                true
+            },
+        };
+
+        if woken {
+            if deadline.is_none() {
+                match shutdown_rx.try_recv() {
+                    Ok(d) => deadline = Some(d),
+                    Err(TryRecvError::Empty) => (),
+                    // The client went away without saying goodbye
+                    Err(TryRecvError::Disconnected) => deadline = Some(Instant::now()),
                 }
             }
-        },
-        HyperMapIntInt(map) => {
-            let ds_map = hyperdex_ds_allocate_map(arena);
-            if ds_map.is_null() {
-                mem_err
-            } else {
-                for (k, v) in map.into_iter() {
-                    if hyperdex_ds_map_insert_key_int(ds_map, k, &mut status) < 0 {
-                        return mem_err;
-                    }
-                    if hyperdex_ds_map_insert_val_int(ds_map, v, &mut status) < 0 {
-                        return mem_err;
-                    }
-                }
-                let mut dt = 0;
-
-                if hyperdex_ds_map_finalize(ds_map, &mut status, &mut cs, &mut sz, &mut dt) < 0 {
-                    mem_err
-                } else {
-                    Ok((cs, sz, dt))
+            // We don't know which client the submission went to, so give all of them a turn.
+            for (client, req_buf) in clients.iter().zip(req_bufs.iter_mut()) {
+                if let Err(err) = client.process(req_buf) {
+                    err_tx.send(err);
                 }
             }
-        },
-        HyperMapIntFloat(map) => {
-            let ds_map = hyperdex_ds_allocate_map(arena);
-            if ds_map.is_null() {
-                mem_err
-            } else {
-                for (k, v) in map.into_iter() {
-                    if hyperdex_ds_map_insert_key_int(ds_map, k, &mut status) < 0 {
-                        return mem_err;
-                    }
-                    if hyperdex_ds_map_insert_val_float(ds_map, v, &mut status) < 0 {
-                        return mem_err;
-                    }
+        } else {
+            for &i in ready.iter() {
+                if let Err(err) = clients[i].process(&mut req_bufs[i]) {
+                    err_tx.send(err);
                 }
-                let mut dt = 0;
+            }
+        }
 
-                if hyperdex_ds_map_finalize(ds_map, &mut status, &mut cs, &mut sz, &mut dt) < 0 {
-                    mem_err
-                } else {
-                    Ok((cs, sz, dt))
+        if let Some(d) = deadline {
+            let drained = req_bufs.iter().all(|b| b.is_empty()) &&
+                clients.iter().all(|c| c.ops.lock().unwrap().is_empty());
+            if drained || Instant::now() >= d {
+                for client in clients.iter() {
+                    client.fail_pending();
                 }
+                return;
             }
-        },
-        HyperMapFloatString(map) => {
-            let ds_map = hyperdex_ds_allocate_map(arena);
-            if ds_map.is_null() {
-                mem_err
-            } else {
-                for (F64(k), v) in map.into_iter() {
-                    if hyperdex_ds_map_insert_key_float(ds_map, k, &mut status) < 0 {
-                        return mem_err;
-                    }
-                    let cstr = v.to_c_str();
-                    if hyperdex_ds_map_insert_val_string(ds_map,
-                                                         cstr.as_ptr(), cstr.as_bytes().len() as u64,
-                                                         &mut status) < 0 {
-                        return mem_err;
-                    }
-                }
-                let mut dt = 0;
+        }
+    }
+}
 
-                if hyperdex_ds_map_finalize(ds_map, &mut status, &mut cs, &mut sz, &mut dt) < 0 {
-                    mem_err
-                } else {
-                    Ok((cs, sz, dt))
-                }
+impl InnerClient {
+
+    /// Handles every response that is currently available without blocking, and returns how
+    /// many requests completed.  Fails if the event loop reports an error, once the completions that
+    /// came before it have been handled.
+    fn process(&self, req_buf: &mut ReqBuf) -> Result<usize, HyperError> {
+        let mut done = Vec::new();
+        let mut res = Ok(0);
+        unsafe {
+            let _lockhandle = self.mutex.lock();
+
+            let buffered: ReqBuf = req_buf.drain(..).collect();
+            for (reqid, loop_status) in buffered.into_iter() {
+                self.dispatch(reqid, loop_status, req_buf, &mut done);
             }
-        },
-        HyperMapFloatInt(map) => {
-            let ds_map = hyperdex_ds_allocate_map(arena);
-            if ds_map.is_null() {
-                mem_err
-            } else {
-                for (F64(k), v) in map.into_iter() {
-                    if hyperdex_ds_map_insert_key_float(ds_map, k, &mut status) < 0 {
-                        return mem_err;
-                    }
-                    if hyperdex_ds_map_insert_val_int(ds_map, v, &mut status) < 0 {
-                        return mem_err;
-                    }
-                }
-                let mut dt = 0;
 
-                if hyperdex_ds_map_finalize(ds_map, &mut status, &mut cs, &mut sz, &mut dt) < 0 {
-                    mem_err
+            loop {
+                let mut loop_status = 0;
+                let reqid = hyperdex_client_loop(*self.ptr, 0, &mut loop_status);
+
+                if reqid < 0 && loop_status == HYPERDEX_CLIENT_TIMEOUT {
+                    break;
+                } else if reqid < 0 && loop_status == HYPERDEX_CLIENT_NONEPENDING {
+                    break;
+                } else if reqid < 0 {
+                    res = Err(get_client_error(*self.ptr, loop_status));
+                    break;
                 } else {
-                    Ok((cs, sz, dt))
+                    self.dispatch(reqid, loop_status, req_buf, &mut done);
                 }
             }
-        },
-        HyperMapFloatFloat(map) => {
-            let ds_map = hyperdex_ds_allocate_map(arena);
-            if ds_map.is_null() {
-                mem_err
-            } else {
-                for (F64(k), v) in map.into_iter() {
-                    if hyperdex_ds_map_insert_key_float(ds_map, k, &mut status) < 0 {
-                        return mem_err;
-                    }
-                    if hyperdex_ds_map_insert_val_float(ds_map, v, &mut status) < 0 {
-                        return mem_err;
-                    }
-                }
-                let mut dt = 0;
+        }
+
+        // Both locks are released by now, so whatever is chained onto the futures is free to
+        // submit new requests.
+        let n = done.len();
+        for complete in done.into_iter() {
+            complete();
+        }
+        self.report_pending();
+        res.map(|_| n)
+    }
+
+    unsafe fn dispatch(&self, reqid: int64_t, loop_status: Enum_hyperdex_client_returncode,
+                       req_buf: &mut ReqBuf, done: &mut Vec<Completion>) {
+        let state = self.ops.lock().unwrap().remove(&reqid);
+        match state {
+            None => {
+                // This is a very rare race condition.  It happens when the request
+                // completes before the corresponding SearchState is inserted into
+                // the hashmap.
+                req_buf.push((reqid, loop_status));
+            },
+
+            Some(HyperStateOp(callback)) => {
+                done.push(callback(*self.ptr, get_client_error(*self.ptr, loop_status)));
+            },
 
-                if hyperdex_ds_map_finalize(ds_map, &mut status, &mut cs, &mut sz, &mut dt) < 0 {
-                    mem_err
+            Some(HyperStateSearch(mut state)) => {
+                if *state.status == HYPERDEX_CLIENT_SUCCESS {
+                    state.sink.send_attrs((*state.attrs).0, *state.attrs_sz);
+                } else if *state.status == HYPERDEX_CLIENT_SEARCHDONE {
+                    // Dropping the state closes the result stream
+                    state.trace.finish();
+                    return;
                 } else {
-                    Ok((cs, sz, dt))
+                    let err = get_client_error(*self.ptr, *state.status);
+                    state.trace.error(&err);
+                    state.sink.send_err(err);
                 }
-            }
-        },
-        HyperDocument(doc) => {
-            let s = format!("{}", doc);
-            let slen = s.len() as u64;
-            let cstr = s.to_c_str();
-            if hyperdex_ds_copy_string(arena, cstr.as_ptr() as *const i8, slen,
-                                       &mut status, &mut cs, &mut sz) < 0 {
-                mem_err
-            } else {
-                Ok((cs, sz, HYPERDATATYPE_DOCUMENT))
+                self.ops.lock().unwrap().insert(reqid, HyperStateSearch(state));
+            },
+        }
+    }
+
+    /// Copies a key into the arena, checking it against the key type of the space if its
+    /// schema was declared.
+    unsafe fn convert_key(&self, arena: *mut Struct_hyperdex_ds_arena, space: &CString, key: HyperValue)
+        -> Result<(*const i8, size_t), HyperError> {
+        let key = try!(HyperKey::from_hyper(key));
+        if let Some(schema) = self.schemas.lock().unwrap().get(&String::from_utf8_lossy(space.as_bytes())) {
+            try!(key.check(schema));
+        }
+        match convert_type(arena, key.to_hyper()) {
+            Ok((key_str, key_sz, _)) => Ok((key_str, key_sz)),
+            Err(err) => Err(HyperError {
+                status: HYPERDEX_CLIENT_NOMEM,
+                message: err,
+                location: String::new(),
+            }),
+        }
+    }
+
+    /// Validates `checks`, and checks them against the schema of `space` if it was declared.
+    fn check_predicates(&self, space: &[u8], checks: &[HyperPredicate]) -> Result<(), HyperError> {
+        match self.schemas.lock().unwrap().get(&String::from_utf8_lossy(space)) {
+            Some(schema) => check_predicates(checks, schema),
+            None => validate_predicates(checks),
+        }
+    }
+
+    /// Wakes up whoever drives this client's event loop, if anyone.
+    fn notify(&self) {
+        self.report_pending();
+        match self.waker {
+            Some(ref waker) => waker.wake(),
+            None => (),
+        }
+    }
+
+    fn report_pending(&self) {
+        self.metrics.set_pending(self.id, self.pending.load(Ordering::Relaxed));
+    }
+
+    /// Starts following an operation on `space`, which is about to be issued.
+    fn trace(&self, op: &'static str, space: &[u8], key: Option<&HyperValue>) -> OpTrace {
+        OpTrace::new(self.metrics.clone(), op, space, key)
+    }
+
+    /// Fails every operation that is still waiting on the server.
+    fn fail_pending(&self) {
+        let pending = self.ops.lock().unwrap().drain();
+        for state in pending.into_iter() {
+            let err = HyperError {
+                status: HYPERDEX_CLIENT_INTERRUPTED,
+                message: "the client was shut down before the operation completed".to_string(),
+                location: String::new(),
+            };
+            match state {
+                HyperStateOp(callback) => {
+                    callback(*self.ptr, err)();
+                },
+                HyperStateSearch(mut state) => {
+                    state.trace.error(&err);
+                    state.sink.send_err(err);
+                    state.trace.finish();
+                },
             }
         }
+        self.report_pending();
     }
-}
 
-unsafe fn convert_attributenames(arena: *mut Struct_hyperdex_ds_arena, attrs: Vec<String>)
-    -> Result<Vec<*const i8>, String> {
-    let mut res = Vec::with_capacity(attrs.len());
-    for attr in attrs.into_iter() {
-        res.push(try!(convert_cstring(arena, attr)));
+    fn search<S>(&self, space: S, checks: Vec<HyperPredicate>, sink: SearchSink) where S: ToCStr {
+        let space_str = space.to_c_str();
+        let trace = self.trace("search", space_str.as_bytes(), None);
+        self.start_search(space_str.as_bytes(), checks, sink, trace, |ptr, c_checks, status, attrs, attrs_sz| unsafe {
+            hyperdex_client_search(ptr,
+                                   space_str.as_ptr() as *const i8,
+                                   c_checks.as_ptr(),
+                                   c_checks.len() as u64,
+                                   status, attrs, attrs_sz)
+        })
     }
-    Ok(res)
-}
 
-unsafe fn convert_predicates(arena: *mut Struct_hyperdex_ds_arena, predicates: Vec<HyperPredicate>)
-    -> Result<Vec<Struct_hyperdex_client_attribute_check>, String> {
-    let mut res = Vec::with_capacity(predicates.len());
-    for p in predicates.into_iter() {
-        let attr = try!(convert_cstring(arena, p.attr));
-        let (val, val_sz, dt) = try!(convert_type(arena, p.value));
-        res.push(Struct_hyperdex_client_attribute_check {
-            attr: attr,
-            value: val,
-            value_sz: val_sz,
-            datatype: dt,
-            predicate: p.predicate as u32,
-        });
+    /// Like `search`, but takes the key out of each object, which needs the declared schema of the
+    /// space to tell which attribute the key is.
+    fn search_keyed<S>(&self, space: S, checks: Vec<HyperPredicate>,
+                       res_tx: Sender<Result<(HyperKey, HyperObject), HyperError>>) where S: ToCStr {
+        let space_str = space.to_c_str();
+        let space = String::from_utf8_lossy(space_str.as_bytes()).into_owned();
+        let key = self.schemas.lock().unwrap().get(&space).map(|schema| schema.key.clone());
+        match key {
+            Some(key) => self.search(space_str.as_bytes().to_vec(), checks, SearchSink::Keyed(res_tx, key)),
+            None => {
+                res_tx.send(Err(HyperError {
+                    status: HYPERDEX_CLIENT_UNKNOWNSPACE,
+                    message: format!("the schema of space \"{}\" is unknown; declare it with set_schema", space),
+                    location: String::new(),
+                }));
+            },
+        }
     }
-    Ok(res)
-}
-
-unsafe fn convert_hyperobject(arena: *mut Struct_hyperdex_ds_arena, obj: HyperObject) -> Result<Vec<Struct_hyperdex_client_attribute>, String> {
-    let mut attrs = Vec::new();
 
-    for (key, val) in obj.map.into_iter() {
-        let mut ckey = try!(convert_cstring(arena, key));
-        let (cval, cval_sz, dt) = try!(convert_type(arena, val));
-        attrs.push(Struct_hyperdex_client_attribute {
-            attr: ckey,
-            value: cval,
-            value_sz: cval_sz,
-            datatype: dt,
-        });
+    fn sorted_search<S, A>(&self, space: S, checks: Vec<HyperPredicate>, sort_by: A, limit: u64, maxmin: bool,
+                           sink: SearchSink) where S: ToCStr, A: ToCStr {
+        let space_str = space.to_c_str();
+        let sort_by_str = sort_by.to_c_str();
+        let trace = self.trace("sorted_search", space_str.as_bytes(), None);
+        self.start_search(space_str.as_bytes(), checks, sink, trace, |ptr, c_checks, status, attrs, attrs_sz| unsafe {
+            hyperdex_client_sorted_search(ptr,
+                                          space_str.as_ptr() as *const i8,
+                                          c_checks.as_ptr(),
+                                          c_checks.len() as u64,
+                                          sort_by_str.as_ptr() as *const i8,
+                                          limit,
+                                          if maxmin { 1 } else { 0 },
+                                          status, attrs, attrs_sz)
+        })
     }
 
-    Ok(attrs)
-}
-
-#[macro_export]
-/** Creates a HyperDex object.
-
-# Examples
+    /// Issues a request that streams back objects into `sink`; `call` makes the actual C call.
+    fn start_search<F>(&self, space: &[u8], checks: Vec<HyperPredicate>, sink: SearchSink, trace: OpTrace, call: F)
+        where F: FnOnce(*mut Struct_hyperdex_client, &[Struct_hyperdex_client_attribute_check],
+                        &mut Enum_hyperdex_client_returncode,
+                        &mut *const Struct_hyperdex_client_attribute, &mut size_t) -> int64_t { unsafe {
+            let span = trace.span.clone();
+            let _entered = span.enter();
+            if let Err(err) = self.check_predicates(space, &checks) {
+                trace.fail(&err);
+                sink.send_err(err);
+                return;
+            }
+            let arena = hyperdex_ds_arena_create();
+            let c_checks = match convert_predicates(arena, checks) {
+                Ok(x) => x,
+                Err(err) => {
+                    hyperdex_ds_arena_destroy(arena);
+                    let err = HyperError {
+                        status: 0,
+                        message: err,
+                        location: String::new(),
+                    };
+                    trace.fail(&err);
+                    sink.send_err(err);
+                    return;
+                },
+            };
 
-```
-let obj = NewHyperObject!(
-    "first", "Derek",
-    "last", "Chiang",
-    "age", 20,
-);
-client.put(space, key, obj);
-```
-**/
-macro_rules! NewHyperObject(
-    ($($key: expr, $value: expr,)*) => (
-        {
-            let mut obj = HyperObject::new();
-            $(
-                obj.insert($key, $value);
-            )*
-            obj
-        }
-    );
-);
+            let mut status_ptr = box 0u32;
+            let mut attrs_ptr = box AttributePtr(null());
+            let mut attrs_sz_ptr = box 0u64;
 
-/// Specifies a key-value pair for a particular map attribute.
-#[macro_export]
-macro_rules! NewHyperMapAttribute(
-    ($attr: expr, $key: expr, $value: expr) => (
-        HyperMapAttribute {
-            attr: $attr.to_string(),
-            key: $key.to_hyper(),
-            value: $value.to_hyper(),
-        }
-    );
-);
+            let _lockhandle = self.mutex.lock();
+            let mut ops_mutex = self.ops.clone();
+            {
+                let mut ops = &mut*ops_mutex.lock().unwrap();
+                let req_id = call(*self.ptr, &c_checks[..], &mut *status_ptr,
+                                  &mut (*attrs_ptr).0, &mut *attrs_sz_ptr);
+                if req_id < 0 {
+                    hyperdex_ds_arena_destroy(arena);
+                    let err = get_client_error(*self.ptr, 0);
+                    trace.fail(&err);
+                    sink.send_err(err);
+                    return;
+                }
 
-pub struct InnerClient {
-    ptr: Unique<Struct_hyperdex_client>,
-    ops: Arc<Mutex<HashMap<int64_t, HyperState>>>,
-    err_tx: Sender<HyperError>,
-    mutex: Arc<Mutex<()>>,
-}
+                let mut state = SearchState {
+                    status: status_ptr,
+                    attrs: attrs_ptr,
+                    attrs_sz: attrs_sz_ptr,
+                    sink: sink,
+                    trace: trace,
+                };
 
-impl Clone for InnerClient {
-    fn clone(&self) -> InnerClient {
-        return InnerClient {
-            ptr: unsafe { Unique::new(*self.ptr) },
-            ops: self.ops.clone(),
-            err_tx: self.err_tx.clone(),
-            mutex: self.mutex.clone(),
+                ops.insert(req_id, HyperStateSearch(state));
+            }
+            self.notify();
+            hyperdex_ds_arena_destroy(arena);
         }
     }
-}
 
-// impl Drop for InnerClient {
-    // fn drop(&mut self) {
-        // unsafe {
-            // hyperdex_client_destroy(self.ptr);
-        // }
-    // }
-// }
+    /// Like `get`, but hands back the attribute array undecoded.
+    fn get_borrowed<S, K>(&self, space: S, key: K)
+        -> Future<AttributeBuffer, HyperError> where S: ToCStr, K: ToHyperValue {
+        let space_str = space.to_c_str();
+        let key = key.to_hyper();
+        self.trace("get_borrowed", space_str.as_bytes(), Some(&key)).run(move || unsafe {
+            let arena = hyperdex_ds_arena_create();
+            let (key_str, key_sz) = match self.convert_key(arena, &space_str, key) {
+                Ok(k) => k,
+                Err(err) => {
+                    hyperdex_ds_arena_destroy(arena);
+                    return Future::error(err);
+                },
+            };
 
-impl InnerClient {
+            let mut status = box 0u32;
+            let mut attrs = box AttributePtr(null());
+            let mut attrs_sz = box 0u64;
+            let (completer, future) = Future::pair();
 
-    fn run_forever(&mut self, shutdown_rx: Receiver<()>) {
-        unsafe {
-            let mut req_buf = Vec::new();
-            loop {
-                match shutdown_rx.try_recv() {
-                    Err(TryRecvError::Empty) => (),
-                    // Otherwise, the client has been dropped
-                    _ => {
-                        hyperdex_client_destroy(*self.ptr);
-                        return;
-                    }
+            {
+                let _lockhandle = self.mutex.lock();
+                let mut ops_mutex = self.ops.clone();
+                let mut ops = &mut*ops_mutex.lock().unwrap();
+                let req_id = hyperdex_client_get(*self.ptr,
+                                                 space_str.as_ptr() as *const i8,
+                                                 key_str, key_sz,
+                                                 &mut *status,
+                                                 &mut (*attrs).0, &mut *attrs_sz);
+                if req_id < 0 {
+                    hyperdex_ds_arena_destroy(arena);
+                    return Future::error(get_client_error(*self.ptr, 0));
                 }
+                ops.insert(req_id, HyperStateOp(Box::new(move |ptr: *mut Struct_hyperdex_client, err: HyperError| {
+                    completion(completer, if err.status != HYPERDEX_CLIENT_SUCCESS {
+                        Err(err)
+                    } else if *status != HYPERDEX_CLIENT_SUCCESS {
+                        Err(get_client_error(ptr, *status))
+                    } else {
+                        Ok(AttributeBuffer::new((*attrs).0, *attrs_sz))
+                    })
+                })));
+            }
+            self.notify();
+            hyperdex_ds_arena_destroy(arena);
+            future
+        })
+    }
 
-                hyperdex_client_block(*self.ptr, 250);  // prevent busy spinning
+    fn count<S>(&self, space: S, checks: Vec<HyperPredicate>)
+        -> Future<u64, HyperError> where S: ToCStr {
+        let space_str = space.to_c_str();
+        self.trace("count", space_str.as_bytes(), None).run(move || unsafe {
+            if let Err(err) = self.check_predicates(space_str.as_bytes(), &checks) {
+                return Future::error(err);
+            }
+            let arena = hyperdex_ds_arena_create();
+            let c_checks = match convert_predicates(arena, checks) {
+                Ok(x) => x,
+                Err(err) => {
+                    hyperdex_ds_arena_destroy(arena);
+                    return Future::error(HyperError {
+                        status: 0,
+                        message: err,
+                        location: String::new(),
+                    });
+                },
+            };
 
-                let mut reqid = 0;
-                let mut loop_status = 0;
-                let _lockhandle = self.mutex.lock();
+            let mut status = box 0u32;
+            let mut count = box 0u64;
+            let (completer, future) = Future::pair();
 
-                match req_buf.pop() {
-                    Some((r, l)) => {
-                        reqid = r;
-                        loop_status = l;
-                    },
-                    None => {
-                        reqid = hyperdex_client_loop(*self.ptr, 0, &mut loop_status);
-                    },
+            {
+                let _lockhandle = self.mutex.lock();
+                let mut ops_mutex = self.ops.clone();
+                let mut ops = &mut*ops_mutex.lock().unwrap();
+                let req_id = hyperdex_client_count(*self.ptr,
+                                                   space_str.as_ptr() as *const i8,
+                                                   c_checks.as_ptr(),
+                                                   c_checks.len() as u64,
+                                                   &mut *status, &mut *count);
+                if req_id < 0 {
+                    hyperdex_ds_arena_destroy(arena);
+                    return Future::error(get_client_error(*self.ptr, 0));
                 }
+                ops.insert(req_id, HyperStateOp(Box::new(move |ptr: *mut Struct_hyperdex_client, err: HyperError| {
+                    completion(completer, if err.status != HYPERDEX_CLIENT_SUCCESS {
+                        Err(err)
+                    } else if *status != HYPERDEX_CLIENT_SUCCESS {
+                        Err(get_client_error(ptr, *status))
+                    } else {
+                        Ok(*count)
+                    })
+                })));
+            }
+            self.notify();
+            hyperdex_ds_arena_destroy(arena);
+            future
+        })
+    }
+}
 
-                if reqid < 0 && loop_status == HYPERDEX_CLIENT_TIMEOUT {
-                    // pass
-                } else if reqid < 0 && loop_status == HYPERDEX_CLIENT_NONEPENDING {
-                    // pass
-                } else if reqid < 0 {
-                    self.err_tx.send(get_client_error(*self.ptr, loop_status));
-                } else {
-                    let mut ops = &mut*self.ops.lock().unwrap();
-                    let mut remove_req = false;
-                    match ops.get(&reqid) {
-                        None => {
-                            // This is a very rare race condition.  It happens when the request
-                            // completes before the corresponding SearchState is inserted into
-                            // the hashmap.
-                            req_buf.push((reqid, loop_status));
-                        },
-
-                        Some(&HyperStateOp(ref op_tx)) => {
-                            op_tx.send(get_client_error(*self.ptr, loop_status));
-                            remove_req = true;
-                        },
+impl SearchSink {
+    fn send_err(&self, err: HyperError) {
+        match *self {
+            SearchSink::Objects(ref res_tx) => {
+                res_tx.send(Err(err));
+            },
+            SearchSink::Borrowed(ref res_tx) => {
+                res_tx.send(Err(err));
+            },
+            SearchSink::Keyed(ref res_tx, _) => {
+                res_tx.send(Err(err));
+            },
+        }
+    }
 
-                        Some(&HyperStateSearch(ref state)) => {
-                            if *state.status == HYPERDEX_CLIENT_SUCCESS {
-                                match build_hyperobject((*state.attrs).0, *state.attrs_sz) {
-                                    Ok(attrs) => {
-                                        state.res_tx.send(Ok(attrs));
-                                    },
-                                    Err(err) => {
-                                        let herr = HyperError {
-                                            status: HYPERDEX_CLIENT_SERVERERROR,
-                                            message: err,
-                                            location: String::new(),
-                                        };
-                                        state.res_tx.send(Err(herr));
-                                    }
-                                }
-                                hyperdex_client_destroy_attrs((*state.attrs).0, *state.attrs_sz);
-                            } else if *state.status == HYPERDEX_CLIENT_SEARCHDONE {
-                                remove_req = true;
-                                // this seems to be a bug in Rust... state.res_tx sometimes
-                                // doesn't get dropped properly
-                            } else {
-                                state.res_tx.send(Err(get_client_error(*self.ptr, *state.status)));
-                            }
-                        },
-                    }
-                    if remove_req {
-                        ops.remove(&reqid);
+    /// Delivers one object found by a search.  The sink takes over `attrs` and frees it.
+    unsafe fn send_attrs(&self, attrs: *const Struct_hyperdex_client_attribute, attrs_sz: size_t) {
+        match *self {
+            SearchSink::Objects(ref res_tx) => {
+                match build_hyperobject(attrs, attrs_sz) {
+                    Ok(obj) => {
+                        res_tx.send(Ok(obj));
+                    },
+                    Err(err) => {
+                        res_tx.send(Err(HyperError {
+                            status: HYPERDEX_CLIENT_SERVERERROR,
+                            message: err,
+                            location: String::new(),
+                        }));
                     }
                 }
-            }
+                hyperdex_client_destroy_attrs(attrs, attrs_sz);
+            },
+            SearchSink::Borrowed(ref res_tx) => {
+                res_tx.send(Ok(AttributeBuffer::new(attrs, attrs_sz)));
+            },
+            SearchSink::Keyed(ref res_tx, ref key) => {
+                res_tx.send(build_keyed_object(attrs, attrs_sz, key).map_err(|err| HyperError {
+                    status: HYPERDEX_CLIENT_SERVERERROR,
+                    message: err,
+                    location: String::new(),
+                }));
+                hyperdex_client_destroy_attrs(attrs, attrs_sz);
+            },
         }
     }
 }
 
+/// Defers completing `completer` with `res` until the client is unlocked.
+fn completion<T: Send + 'static>(completer: Complete<T, HyperError>, res: Result<T, HyperError>) -> Completion {
+    Box::new(move || match res {
+        Ok(val) => completer.complete(val),
+        Err(err) => completer.fail(err),
+    })
+}
+
+/// Builds the completion callback for requests that only report a status.
+fn status_callback(completer: Complete<(), HyperError>,
+                   status: Box<Enum_hyperdex_client_returncode>) -> HyperState {
+    HyperStateOp(Box::new(move |ptr: *mut Struct_hyperdex_client, err: HyperError| {
+        completion(completer, if err.status != HYPERDEX_CLIENT_SUCCESS {
+            Err(err)
+        } else if *status != HYPERDEX_CLIENT_SUCCESS {
+            Err(get_client_error(ptr, *status))
+        } else {
+            Ok(())
+        })
+    }))
+}
+
+/// Builds the completion callback for requests that return an object.
+fn object_callback(completer: Complete<HyperObject, HyperError>,
+                   status: Box<Enum_hyperdex_client_returncode>,
+                   attrs: Box<AttributePtr>, attrs_sz: Box<size_t>) -> HyperState {
+    HyperStateOp(Box::new(move |ptr: *mut Struct_hyperdex_client, err: HyperError| {
+        completion(completer, if err.status != HYPERDEX_CLIENT_SUCCESS {
+            Err(err)
+        } else if *status != HYPERDEX_CLIENT_SUCCESS {
+            Err(get_client_error(ptr, *status))
+        } else {
+            unsafe {
+                let res = build_hyperobject((*attrs).0, *attrs_sz).map_err(|msg| HyperError {
+                    status: HYPERDEX_CLIENT_SERVERERROR,
+                    message: msg,
+                    location: String::new(),
+                });
+                hyperdex_client_destroy_attrs((*attrs).0, *attrs_sz);
+                res
+            }
+        })
+    }))
+}
+
 macro_rules! make_fn_spacename_key_status_attributes(
     ($fn_name: ident, $async_name: ident) => (
-        impl Client {
+        make_fn_spacename_key_status_attributes!(Client, $fn_name, $async_name);
+        make_fn_spacename_key_status_attributes!(LocalClient, $fn_name, $async_name);
+    );
+    ($client: ident, $fn_name: ident, $async_name: ident) => (
+        impl $client {
         pub fn $async_name<S, K>(&mut self, space: S, key: K)
             -> Future<HyperObject, HyperError> where S: ToCStr, K: ToHyperValue {
-            unsafe {
-            // TODO: Is "Relaxed" good enough?
-            let inner_client =
-                self.inner_clients[self.counter.fetch_add(1, Ordering::Relaxed) as usize % self.inner_clients.len()].clone();
+            let space = space.to_c_str().into_bytes();
+            let key = key.to_hyper();
+            self.trace(stringify!($fn_name), &space, Some(&key)).run(move || unsafe {
+            let inner_client = self.next_inner_client();
 
             let arena = hyperdex_ds_arena_create();
-            let (key_str, key_sz, _) = convert_type(arena, key.to_hyper()).unwrap();
             let space_str = space.to_c_str();
+            let (key_str, key_sz) = match inner_client.convert_key(arena, &space_str, key) {
+                Ok(k) => k,
+                Err(err) => {
+                    hyperdex_ds_arena_destroy(arena);
+                    return Future::error(err);
+                },
+            };
 
             let mut status = box 0u32;
             let mut attrs = box AttributePtr(null());
             let mut attrs_sz = box 0u64;
 
-            let (err_tx, err_rx) = channel();
+            let (completer, future) = Future::pair();
 
             {
                 let _lockhandle = inner_client.mutex.lock();
@@ -1008,34 +954,13 @@ macro_rules! make_fn_spacename_key_status_attributes(
                 if req_id < 0 {
                     return Future::error(get_client_error(*inner_client.ptr, 0));
                 }
-                ops.insert(req_id, HyperStateOp(err_tx));
+                ops.insert(req_id, object_callback(completer, status, attrs, attrs_sz));
             }
+            inner_client.notify();
             hyperdex_ds_arena_destroy(arena);
 
-            helpers::spawn(move || {
-                let err = err_rx.recv().unwrap();
-                if err.status != HYPERDEX_CLIENT_SUCCESS {
-                    Err(err)
-                } else if *status != HYPERDEX_CLIENT_SUCCESS {
-                    Err(get_client_error(*inner_client.ptr, *status))
-                } else {
-                    let res = match build_hyperobject((*attrs).0, *attrs_sz) {
-                        Ok(obj) => {
-                            Ok(obj)
-                        },
-                        Err(msg) => {
-                            Err(HyperError {
-                                status: HYPERDEX_CLIENT_SERVERERROR,
-                                message: msg,
-                                location: String::new(),
-                            })
-                        }
-                    };
-                    hyperdex_client_destroy_attrs((*attrs).0, *attrs_sz);
-                    res
-                }
+            future
             })
-            }
         }
 
         pub fn $fn_name<S, K>(&mut self, space: S, key: K)
@@ -1048,19 +973,30 @@ macro_rules! make_fn_spacename_key_status_attributes(
 
 macro_rules! make_fn_spacename_key_status(
     ($fn_name: ident, $async_name: ident) => (
-        impl Client {
+        make_fn_spacename_key_status!(Client, $fn_name, $async_name);
+        make_fn_spacename_key_status!(LocalClient, $fn_name, $async_name);
+    );
+    ($client: ident, $fn_name: ident, $async_name: ident) => (
+        impl $client {
         pub fn $async_name<S, K>(&mut self, space: S, key: K)
             -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue {
-            unsafe {
-            let inner_client =
-                self.inner_clients[self.counter.fetch_add(1, Ordering::Relaxed) as usize % self.inner_clients.len()].clone();
+            let space = space.to_c_str().into_bytes();
+            let key = key.to_hyper();
+            self.trace(stringify!($fn_name), &space, Some(&key)).run(move || unsafe {
+            let inner_client = self.next_inner_client();
 
             let arena = hyperdex_ds_arena_create();
-            let (key_str, key_sz, _) = convert_type(arena, key.to_hyper()).unwrap();
             let space_str = space.to_c_str();
+            let (key_str, key_sz) = match inner_client.convert_key(arena, &space_str, key) {
+                Ok(k) => k,
+                Err(err) => {
+                    hyperdex_ds_arena_destroy(arena);
+                    return Future::error(err);
+                },
+            };
             let mut status = box 0u32;
 
-            let (err_tx, err_rx) = channel();
+            let (completer, future) = Future::pair();
 
             {
                 let _lockhandle = inner_client.mutex.lock();
@@ -1074,21 +1010,13 @@ macro_rules! make_fn_spacename_key_status(
                 if req_id < 0 {
                     return Future::error(get_client_error(*inner_client.ptr, 0));
                 }
-                ops.insert(req_id, HyperStateOp(err_tx));
+                ops.insert(req_id, status_callback(completer, status));
             }
+            inner_client.notify();
             hyperdex_ds_arena_destroy(arena);
 
-            helpers::spawn(move|| {
-                let err = err_rx.recv().unwrap();
-                if err.status != HYPERDEX_CLIENT_SUCCESS {
-                    Err(err)
-                } else if *status != HYPERDEX_CLIENT_SUCCESS {
-                    Err(get_client_error(*inner_client.ptr, *status))
-                } else {
-                    Ok(())
-                }
+            future
             })
-            }
         }
 
         pub fn $fn_name<S, K>(&mut self, space: S, key: K)
@@ -1101,16 +1029,27 @@ macro_rules! make_fn_spacename_key_status(
 
 macro_rules! make_fn_spacename_key_attributenames_status_attributes(
     ($fn_name: ident, $async_name: ident) => (
-        impl Client {
+        make_fn_spacename_key_attributenames_status_attributes!(Client, $fn_name, $async_name);
+        make_fn_spacename_key_attributenames_status_attributes!(LocalClient, $fn_name, $async_name);
+    );
+    ($client: ident, $fn_name: ident, $async_name: ident) => (
+        impl $client {
         pub fn $async_name<S, K, A>(&mut self, space: S, key: K, attrs: Vec<A>)
             -> Future<HyperObject, HyperError> where S: ToCStr, K: ToHyperValue, A: ToString {
-            unsafe {
-            // TODO: Is "Relaxed" good enough?
-            let inner_client =
-                self.inner_clients[self.counter.fetch_add(1, Ordering::Relaxed) as usize % self.inner_clients.len()].clone();
+            let space = space.to_c_str().into_bytes();
+            let key = key.to_hyper();
+            self.trace(stringify!($fn_name), &space, Some(&key)).run(move || unsafe {
+            let inner_client = self.next_inner_client();
 
             let arena = hyperdex_ds_arena_create();
-            let (key_str, key_sz, _) = convert_type(arena, key.to_hyper()).unwrap();
+            let space_str = space.to_c_str();
+            let (key_str, key_sz) = match inner_client.convert_key(arena, &space_str, key) {
+                Ok(k) => k,
+                Err(err) => {
+                    hyperdex_ds_arena_destroy(arena);
+                    return Future::error(err);
+                },
+            };
 
             let mut status_ptr = box 0u32;
             let mut attrs_ptr = box AttributePtr(null());
@@ -1128,8 +1067,7 @@ macro_rules! make_fn_spacename_key_attributenames_status_attributes(
                 }),
             };
 
-            let (err_tx, err_rx) = channel();
-            let space_str = space.to_c_str();
+            let (completer, future) = Future::pair();
 
             {
                 let _lockhandle = inner_client.mutex.lock();
@@ -1146,34 +1084,13 @@ macro_rules! make_fn_spacename_key_attributenames_status_attributes(
                 if req_id < 0 {
                     return Future::error(get_client_error(*inner_client.ptr, 0));
                 }
-                ops.insert(req_id, HyperStateOp(err_tx));
+                ops.insert(req_id, object_callback(completer, status_ptr, attrs_ptr, attrs_sz_ptr));
             }
+            inner_client.notify();
             hyperdex_ds_arena_destroy(arena);
 
-            helpers::spawn(move|| {
-                let err = err_rx.recv().unwrap();
-                if err.status != HYPERDEX_CLIENT_SUCCESS {
-                    Err(err)
-                } else if *status_ptr != HYPERDEX_CLIENT_SUCCESS {
-                    Err(get_client_error(*inner_client.ptr, *status_ptr))
-                } else {
-                    let res = match build_hyperobject((*attrs_ptr).0, *attrs_sz_ptr) {
-                        Ok(obj) => {
-                            Ok(obj)
-                        },
-                        Err(msg) => {
-                            Err(HyperError {
-                                status: HYPERDEX_CLIENT_SERVERERROR,
-                                message: msg,
-                                location: String::new(),
-                            })
-                        }
-                    };
-                    hyperdex_client_destroy_attrs((*attrs_ptr).0, *attrs_sz_ptr);
-                    res
-                }
+            future
             })
-            }
         }
 
         pub fn $fn_name<S, K, A>(&mut self, space: S, key: K, attrs: Vec<A>)
@@ -1186,16 +1103,28 @@ macro_rules! make_fn_spacename_key_attributenames_status_attributes(
 
 macro_rules! make_fn_spacename_key_attributes_status(
     ($fn_name: ident, $async_name: ident) => (
-        impl Client {
+        make_fn_spacename_key_attributes_status!(Client, $fn_name, $async_name);
+        make_fn_spacename_key_attributes_status!(LocalClient, $fn_name, $async_name);
+    );
+    ($client: ident, $fn_name: ident, $async_name: ident) => (
+        impl $client {
         pub fn $async_name<S, K>(&mut self, space: S, key: K, value: HyperObject)
-            -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue { unsafe {
-            let inner_client =
-                self.inner_clients[self.counter.fetch_add(1, Ordering::Relaxed) as usize % self.inner_clients.len()].clone();
+            -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue {
+            let space = space.to_c_str().into_bytes();
+            let key = key.to_hyper();
+            self.trace(stringify!($fn_name), &space, Some(&key)).run(move || unsafe {
+            let inner_client = self.next_inner_client();
 
             let arena = hyperdex_ds_arena_create();
 
             let space_str = space.to_c_str();
-            let (key_str, key_sz, _) = convert_type(arena, key.to_hyper()).unwrap();
+            let (key_str, key_sz) = match inner_client.convert_key(arena, &space_str, key) {
+                Ok(k) => k,
+                Err(err) => {
+                    hyperdex_ds_arena_destroy(arena);
+                    return Future::error(err);
+                },
+            };
 
             let mut status_ptr = box 0u32;
 
@@ -1204,7 +1133,7 @@ macro_rules! make_fn_spacename_key_attributes_status(
                 Err(err) => panic!(err),
             };
 
-            let (err_tx, err_rx) = channel();
+            let (completer, future) = Future::pair();
 
             {
                 let _lockhandle = inner_client.mutex.lock();
@@ -1219,21 +1148,14 @@ macro_rules! make_fn_spacename_key_attributes_status(
                 if req_id < 0 {
                     return Future::error(get_client_error(*inner_client.ptr, 0));
                 }
-                ops.insert(req_id, HyperStateOp(err_tx));
+                ops.insert(req_id, status_callback(completer, status_ptr));
             }
+            inner_client.notify();
 
-            hyperdex_ds_arena_destroy(arena);
-            helpers::spawn(move|| {
-                let err = err_rx.recv().unwrap();
-                if err.status != HYPERDEX_CLIENT_SUCCESS {
-                    Err(err)
-                } else if *status_ptr != HYPERDEX_CLIENT_SUCCESS {
-                    Err(get_client_error(*inner_client.ptr, *status_ptr))
-                } else {
-                    Ok(())
-                }
-            })
-        }}
+            hyperdex_ds_arena_destroy(arena);
+            future
+        })
+        }
 
         pub fn $fn_name<S, K>(&mut self, space: S, key: K, value: HyperObject)
             -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue {
@@ -1245,15 +1167,27 @@ macro_rules! make_fn_spacename_key_attributes_status(
 
 macro_rules! make_fn_spacename_key_mapattributes_status(
     ($fn_name: ident, $async_name: ident) => (
-        impl Client {
+        make_fn_spacename_key_mapattributes_status!(Client, $fn_name, $async_name);
+        make_fn_spacename_key_mapattributes_status!(LocalClient, $fn_name, $async_name);
+    );
+    ($client: ident, $fn_name: ident, $async_name: ident) => (
+        impl $client {
             pub fn $async_name<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
-                -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue { unsafe {
-                let inner_client =
-                    self.inner_clients[self.counter.fetch_add(1, Ordering::Relaxed) as usize % self.inner_clients.len()].clone();
+                -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                let space = space.to_c_str().into_bytes();
+                let key = key.to_hyper();
+                self.trace(stringify!($fn_name), &space, Some(&key)).run(move || unsafe {
+                let inner_client = self.next_inner_client();
 
                 let arena = hyperdex_ds_arena_create();
-                let (key_str, key_sz, _) = convert_type(arena, key.to_hyper()).unwrap();
                 let space_str = space.to_c_str();
+                let (key_str, key_sz) = match inner_client.convert_key(arena, &space_str, key) {
+                    Ok(k) => k,
+                    Err(err) => {
+                        hyperdex_ds_arena_destroy(arena);
+                        return Future::error(err);
+                    },
+                };
 
                 let mut status_ptr = box 0u32;
 
@@ -1262,7 +1196,7 @@ macro_rules! make_fn_spacename_key_mapattributes_status(
                     Err(err) => panic!(err),
                 };
 
-                let (err_tx, err_rx) = channel();
+                let (completer, future) = Future::pair();
 
                 {
                     let _lockhandle = inner_client.mutex.lock();
@@ -1277,20 +1211,13 @@ macro_rules! make_fn_spacename_key_mapattributes_status(
                     if req_id < 0 {
                         return Future::error(get_client_error(*inner_client.ptr, 0));
                     }
-                    ops.insert(req_id, HyperStateOp(err_tx));
+                    ops.insert(req_id, status_callback(completer, status_ptr));
                 }
+                inner_client.notify();
 
                 hyperdex_ds_arena_destroy(arena);
-                helpers::spawn(move|| {
-                    let err = err_rx.recv().unwrap();
-                    if err.status != HYPERDEX_CLIENT_SUCCESS {
-                        Err(err)
-                    } else if *status_ptr != HYPERDEX_CLIENT_SUCCESS {
-                        Err(get_client_error(*inner_client.ptr, *status_ptr))
-                    } else {
-                        Ok(())
-                    }
-                })}
+                future
+            })
             }
 
             pub fn $fn_name<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
@@ -1301,15 +1228,99 @@ macro_rules! make_fn_spacename_key_mapattributes_status(
     )
 );
 
+macro_rules! make_fn_spacename_key_predicates_status(
+    ($fn_name: ident, $async_name: ident) => (
+        make_fn_spacename_key_predicates_status!(Client, $fn_name, $async_name);
+        make_fn_spacename_key_predicates_status!(LocalClient, $fn_name, $async_name);
+    );
+    ($client: ident, $fn_name: ident, $async_name: ident) => (
+        impl $client {
+            pub fn $async_name<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>)
+                -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                let space = space.to_c_str().into_bytes();
+                let key = key.to_hyper();
+                self.trace(stringify!($fn_name), &space, Some(&key)).run(move || unsafe {
+                    let inner_client = self.next_inner_client();
+                    if let Err(err) = inner_client.check_predicates(&space, &checks) {
+                        return Future::error(err);
+                    }
+
+                    let (completer, future) = Future::pair();
+
+                    let arena = hyperdex_ds_arena_create();
+                    let c_checks = match convert_predicates(arena, checks) {
+                        Ok(x) => x,
+                        Err(err) => {
+                            hyperdex_ds_arena_destroy(arena);
+                            return Future::error(HyperError {
+                                status: 0,
+                                message: err,
+                                location: String::new(),
+                            });
+                        },
+                    };
+
+                    let mut status_ptr = box 0u32;
+
+                    let space_str = space.to_c_str();
+                    let (key_str, key_sz) = match inner_client.convert_key(arena, &space_str, key) {
+                        Ok(k) => k,
+                        Err(err) => {
+                            hyperdex_ds_arena_destroy(arena);
+                            return Future::error(err);
+                        },
+                    };
+
+                    {
+                        let _lockhandle = inner_client.mutex.lock();
+                        let mut ops_mutex = inner_client.ops.clone();
+                        let mut ops = &mut*ops_mutex.lock().unwrap();
+                        let req_id =
+                            concat_idents!(hyperdex_client_, $fn_name)(
+                                *inner_client.ptr,
+                                space_str.as_ptr() as *const i8,
+                                key_str, key_sz,
+                                c_checks.as_ptr(),
+                                c_checks.len() as u64,
+                                &mut *status_ptr);
+                        if req_id < 0 {
+                            hyperdex_ds_arena_destroy(arena);
+                            return Future::error(get_client_error(*inner_client.ptr, 0));
+                        }
+                        ops.insert(req_id, status_callback(completer, status_ptr));
+                    }
+                    inner_client.notify();
+                    hyperdex_ds_arena_destroy(arena);
+                    future
+                })
+            }
+
+            pub fn $fn_name<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>)
+                -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                self.$async_name(space, key, checks).into_inner()
+            }
+        }
+    )
+);
+
 macro_rules! make_fn_spacename_key_predicates_attributes_status(
     ($fn_name: ident, $async_name: ident) => (
-        impl Client {
+        make_fn_spacename_key_predicates_attributes_status!(Client, $fn_name, $async_name);
+        make_fn_spacename_key_predicates_attributes_status!(LocalClient, $fn_name, $async_name);
+    );
+    ($client: ident, $fn_name: ident, $async_name: ident) => (
+        impl $client {
             pub fn $async_name<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, value: HyperObject)
-                -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue { unsafe {
-                    let inner_client =
-                        self.inner_clients[self.counter.fetch_add(1, Ordering::Relaxed) as usize % self.inner_clients.len()].clone();
+                -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                let space = space.to_c_str().into_bytes();
+                let key = key.to_hyper();
+                self.trace(stringify!($fn_name), &space, Some(&key)).run(move || unsafe {
+                    let inner_client = self.next_inner_client();
+                    if let Err(err) = inner_client.check_predicates(&space, &checks) {
+                        return Future::error(err);
+                    }
 
-                    let (res_tx, res_rx) = channel();
+                    let (completer, future) = Future::pair();
 
                     let arena = hyperdex_ds_arena_create();
                     let c_checks = match convert_predicates(arena, checks) {
@@ -1332,7 +1343,13 @@ macro_rules! make_fn_spacename_key_predicates_attributes_status(
                     let mut status_ptr = box 0u32;
 
                     let space_str = space.to_c_str();
-                    let (key_str, key_sz, _) = convert_type(arena, key.to_hyper()).unwrap();
+                    let (key_str, key_sz) = match inner_client.convert_key(arena, &space_str, key) {
+                        Ok(k) => k,
+                        Err(err) => {
+                            hyperdex_ds_arena_destroy(arena);
+                            return Future::error(err);
+                        },
+                    };
 
                     {
                         let _lockhandle = inner_client.mutex.lock();
@@ -1351,20 +1368,12 @@ macro_rules! make_fn_spacename_key_predicates_attributes_status(
                         if req_id < 0 {
                             return Future::error(get_client_error(*inner_client.ptr, 0));
                         }
-                        ops.insert(req_id, HyperStateOp(res_tx));
+                        ops.insert(req_id, status_callback(completer, status_ptr));
                     }
+                    inner_client.notify();
                     hyperdex_ds_arena_destroy(arena);
-                    helpers::spawn(move|| {
-                        let err = res_rx.recv().unwrap();
-                        if err.status != HYPERDEX_CLIENT_SUCCESS {
-                            Err(err)
-                        } else if *status_ptr != HYPERDEX_CLIENT_SUCCESS {
-                            Err(get_client_error(*inner_client.ptr, *status_ptr))
-                        } else {
-                            Ok(())
-                        }
-                    })
-                }
+                    future
+                })
             }
 
             pub fn $fn_name<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, value: HyperObject)
@@ -1377,16 +1386,31 @@ macro_rules! make_fn_spacename_key_predicates_attributes_status(
 
 macro_rules! make_fn_spacename_key_predicates_mapattributes_status(
     ($fn_name: ident, $async_name: ident) => (
-        impl Client {
+        make_fn_spacename_key_predicates_mapattributes_status!(Client, $fn_name, $async_name);
+        make_fn_spacename_key_predicates_mapattributes_status!(LocalClient, $fn_name, $async_name);
+    );
+    ($client: ident, $fn_name: ident, $async_name: ident) => (
+        impl $client {
             pub fn $async_name<S, K>(&mut self, space: S, key: K,
                                      checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
-                -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue { unsafe {
-                let inner_client =
-                    self.inner_clients[self.counter.fetch_add(1, Ordering::Relaxed) as usize % self.inner_clients.len()].clone();
+                -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                let space = space.to_c_str().into_bytes();
+                let key = key.to_hyper();
+                self.trace(stringify!($fn_name), &space, Some(&key)).run(move || unsafe {
+                let inner_client = self.next_inner_client();
+                if let Err(err) = inner_client.check_predicates(&space, &checks) {
+                    return Future::error(err);
+                }
 
                 let arena = hyperdex_ds_arena_create();
-                let (key_str, key_sz, _) = convert_type(arena, key.to_hyper()).unwrap();
                 let space_str = space.to_c_str();
+                let (key_str, key_sz) = match inner_client.convert_key(arena, &space_str, key) {
+                    Ok(k) => k,
+                    Err(err) => {
+                        hyperdex_ds_arena_destroy(arena);
+                        return Future::error(err);
+                    },
+                };
 
                 let mut status_ptr = box 0u32;
 
@@ -1411,7 +1435,7 @@ macro_rules! make_fn_spacename_key_predicates_mapattributes_status(
                     },
                 };
 
-                let (err_tx, err_rx) = channel();
+                let (completer, future) = Future::pair();
 
                 {
                     let _lockhandle = inner_client.mutex.lock();
@@ -1427,20 +1451,13 @@ macro_rules! make_fn_spacename_key_predicates_mapattributes_status(
                     if req_id < 0 {
                         return Future::error(get_client_error(*inner_client.ptr, 0));
                     }
-                    ops.insert(req_id, HyperStateOp(err_tx));
+                    ops.insert(req_id, status_callback(completer, status_ptr));
                 }
+                inner_client.notify();
 
                 hyperdex_ds_arena_destroy(arena);
-                helpers::spawn(move|| {
-                    let err = err_rx.recv().unwrap();
-                    if err.status != HYPERDEX_CLIENT_SUCCESS {
-                        Err(err)
-                    } else if *status_ptr != HYPERDEX_CLIENT_SUCCESS {
-                        Err(get_client_error(*inner_client.ptr, *status_ptr))
-                    } else {
-                        Ok(())
-                    }
-                })}
+                future
+            })
             }
 
             pub fn $fn_name<S, K>(&mut self, space: S, key: K,
@@ -1452,111 +1469,449 @@ macro_rules! make_fn_spacename_key_predicates_mapattributes_status(
     )
 );
 
+/// The signature shared by every C call that takes a key and a list of attributes.
+type AttributesFn = unsafe extern "C" fn(*mut Struct_hyperdex_client, *const ::libc::c_char,
+                                         *const ::libc::c_char, size_t,
+                                         *const Struct_hyperdex_client_attribute, size_t,
+                                         *mut Enum_hyperdex_client_returncode) -> int64_t;
+
+/// A request queued in a `Batch`.
+enum BatchOp {
+    Get(CString, HyperValue),
+    Del(CString, HyperValue),
+    Attributes(AttributesFn, CString, HyperValue, HyperObject),
+}
+
+impl BatchOp {
+    /// Picks one of `n` connections for the operation.  Every operation on the same object
+    /// gets the same connection.
+    fn connection(&self, n: usize) -> usize {
+        let (space, key) = match *self {
+            BatchOp::Get(ref space, ref key) | BatchOp::Del(ref space, ref key) => (space, key),
+            BatchOp::Attributes(_, ref space, ref key, _) => (space, key),
+        };
+        let mut hasher = DefaultHasher::new();
+        space.as_bytes().hash(&mut hasher);
+        codec::encode(key).hash(&mut hasher);
+        (hasher.finish() % n as u64) as usize
+    }
+}
+
+/// A `BatchOp` whose key and attributes have been checked and copied into an arena, so that
+/// submitting it only calls into the client.
+enum PreparedOp {
+    Get(CString, *const i8, size_t),
+    Del(CString, *const i8, size_t),
+    Attributes(AttributesFn, CString, *const i8, size_t, Vec<Struct_hyperdex_client_attribute>),
+}
+
+impl InnerClient {
+
+    /// Submits a series of requests while holding the client lock only once.  Keys are checked
+    /// and converted before the lock is taken.
+    fn submit_batch(&self, batch: Vec<BatchOp>) -> Vec<Future<Option<HyperObject>, HyperError>> {
+        unsafe {
+            let arena = hyperdex_ds_arena_create();
+            let prepared: Vec<Result<PreparedOp, HyperError>> =
+                batch.into_iter().map(|op| self.prepare_batch_op(arena, op)).collect();
+            let mut futures = Vec::with_capacity(prepared.len());
+            {
+                let _lockhandle = self.mutex.lock();
+                let mut ops_mutex = self.ops.clone();
+                let mut ops = &mut*ops_mutex.lock().unwrap();
+                for op in prepared.into_iter() {
+                    futures.push(match op {
+                        Ok(op) => self.submit_batch_op(ops, op),
+                        Err(err) => Future::error(err),
+                    });
+                }
+            }
+            self.notify();
+            hyperdex_ds_arena_destroy(arena);
+            futures
+        }
+    }
+
+    unsafe fn prepare_batch_op(&self, arena: *mut Struct_hyperdex_ds_arena, op: BatchOp)
+        -> Result<PreparedOp, HyperError> {
+        match op {
+            BatchOp::Get(space_str, key) => {
+                let (key_str, key_sz) = try!(self.convert_key(arena, &space_str, key));
+                Ok(PreparedOp::Get(space_str, key_str, key_sz))
+            },
+            BatchOp::Del(space_str, key) => {
+                let (key_str, key_sz) = try!(self.convert_key(arena, &space_str, key));
+                Ok(PreparedOp::Del(space_str, key_str, key_sz))
+            },
+            BatchOp::Attributes(func, space_str, key, value) => {
+                let (key_str, key_sz) = try!(self.convert_key(arena, &space_str, key));
+                let obj = try!(convert_hyperobject(arena, value).map_err(|err| HyperError {
+                    status: 0,
+                    message: err,
+                    location: String::new(),
+                }));
+                Ok(PreparedOp::Attributes(func, space_str, key_str, key_sz, obj))
+            },
+        }
+    }
+
+    unsafe fn submit_batch_op(&self, ops: &mut Ops, op: PreparedOp)
+        -> Future<Option<HyperObject>, HyperError> {
+        match op {
+            PreparedOp::Get(space_str, key_str, key_sz) => {
+                let mut status = box 0u32;
+                let mut attrs = box AttributePtr(null());
+                let mut attrs_sz = box 0u64;
+                let (completer, future) = Future::pair();
+
+                let req_id = hyperdex_client_get(*self.ptr, space_str.as_ptr() as *const i8,
+                                                 key_str, key_sz, &mut *status,
+                                                 &mut (*attrs).0, &mut *attrs_sz);
+                if req_id < 0 {
+                    return Future::error(get_client_error(*self.ptr, 0));
+                }
+                ops.insert(req_id, object_callback(completer, status, attrs, attrs_sz));
+                future.map(|obj| Some(obj))
+            },
+            PreparedOp::Del(space_str, key_str, key_sz) => {
+                let mut status = box 0u32;
+                let (completer, future) = Future::pair();
+
+                let req_id = hyperdex_client_del(*self.ptr, space_str.as_ptr() as *const i8,
+                                                 key_str, key_sz, &mut *status);
+                if req_id < 0 {
+                    return Future::error(get_client_error(*self.ptr, 0));
+                }
+                ops.insert(req_id, status_callback(completer, status));
+                future.map(|()| None)
+            },
+            PreparedOp::Attributes(func, space_str, key_str, key_sz, obj) => {
+                let mut status = box 0u32;
+                let (completer, future) = Future::pair();
+
+                let req_id = func(*self.ptr, space_str.as_ptr() as *const i8,
+                                  key_str, key_sz, obj.as_ptr(), obj.len() as u64, &mut *status);
+                if req_id < 0 {
+                    return Future::error(get_client_error(*self.ptr, 0));
+                }
+                ops.insert(req_id, status_callback(completer, status));
+                future.map(|()| None)
+            },
+        }
+    }
+}
+
+/// A series of operations that are submitted together.
+///
+/// Queued operations are spread over the client's connections by object, and each connection's
+/// share is submitted under a single lock acquisition.  All the operations on one object go
+/// over the same connection in the order they were queued, so they are applied in that order;
+/// operations on different objects may be applied in any order.  Results come back in the order
+/// the operations were queued: `Some(object)` for gets and `None` for everything else.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut batch = client.batch();
+/// batch.put(space_name, "derek", NewHyperObject!("first", "Derek",))
+///      .atomic_add(space_name, "derek", NewHyperObject!("age", 1,))
+///      .get(space_name, "derek");
+/// for res in batch.execute().into_iter() {
+///     println!("{:?}", res);
+/// }
+/// ```
+pub struct Batch<'a> {
+    client: &'a mut Client,
+    ops: Vec<BatchOp>,
+}
+
+macro_rules! make_batch_fn_spacename_key_attributes(
+    ($fn_name: ident) => (
+        impl<'a> Batch<'a> {
+            pub fn $fn_name<S, K>(&mut self, space: S, key: K, value: HyperObject)
+                -> &mut Batch<'a> where S: ToCStr, K: ToHyperValue {
+                self.ops.push(BatchOp::Attributes(concat_idents!(hyperdex_client_, $fn_name),
+                                                  space.to_c_str(), key.to_hyper(), value));
+                self
+            }
+        }
+    );
+);
+
+impl<'a> Batch<'a> {
+    pub fn get<S, K>(&mut self, space: S, key: K) -> &mut Batch<'a> where S: ToCStr, K: ToHyperValue {
+        self.ops.push(BatchOp::Get(space.to_c_str(), key.to_hyper()));
+        self
+    }
+
+    pub fn del<S, K>(&mut self, space: S, key: K) -> &mut Batch<'a> where S: ToCStr, K: ToHyperValue {
+        self.ops.push(BatchOp::Del(space.to_c_str(), key.to_hyper()));
+        self
+    }
+
+    /// The number of queued operations.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Submits every queued operation and returns one future per operation, in queue order.
+    pub fn async_execute(self) -> Vec<Future<Option<HyperObject>, HyperError>> {
+        let n = self.client.inner_clients.len();
+        let mut futures: Vec<Option<Future<Option<HyperObject>, HyperError>>> =
+            self.ops.iter().map(|_| None).collect();
+
+        // The share of each connection, with the position of every operation in the queue
+        let mut shares: Vec<(Vec<usize>, Vec<BatchOp>)> = (0..n).map(|_| (Vec::new(), Vec::new())).collect();
+        for (i, op) in self.ops.into_iter().enumerate() {
+            let share = &mut shares[op.connection(n)];
+            share.0.push(i);
+            share.1.push(op);
+        }
+
+        for (client, (positions, ops)) in self.client.inner_clients.iter().zip(shares.into_iter()) {
+            if ops.is_empty() {
+                continue;
+            }
+            for (i, fut) in positions.into_iter().zip(client.submit_batch(ops).into_iter()) {
+                futures[i] = Some(fut);
+            }
+        }
+        futures.into_iter().map(|fut| fut.unwrap()).collect()
+    }
+
+    /// Submits every queued operation and waits for all of them to complete.
+    pub fn execute(self) -> Vec<Result<Option<HyperObject>, HyperError>> {
+        self.async_execute().into_iter().map(|fut| fut.into_inner()).collect()
+    }
+}
+
+make_batch_fn_spacename_key_attributes!(put);
+make_batch_fn_spacename_key_attributes!(put_if_not_exist);
+make_batch_fn_spacename_key_attributes!(atomic_add);
+make_batch_fn_spacename_key_attributes!(atomic_sub);
+make_batch_fn_spacename_key_attributes!(atomic_mul);
+make_batch_fn_spacename_key_attributes!(atomic_div);
+make_batch_fn_spacename_key_attributes!(atomic_mod);
+make_batch_fn_spacename_key_attributes!(atomic_and);
+make_batch_fn_spacename_key_attributes!(atomic_or);
+make_batch_fn_spacename_key_attributes!(atomic_xor);
+make_batch_fn_spacename_key_attributes!(string_prepend);
+make_batch_fn_spacename_key_attributes!(string_append);
+make_batch_fn_spacename_key_attributes!(list_lpush);
+make_batch_fn_spacename_key_attributes!(list_rpush);
+make_batch_fn_spacename_key_attributes!(set_add);
+make_batch_fn_spacename_key_attributes!(set_remove);
+make_batch_fn_spacename_key_attributes!(set_intersect);
+make_batch_fn_spacename_key_attributes!(set_union);
+make_batch_fn_spacename_key_attributes!(map_remove);
+
+/// How many requests `get_many` and `get_partial_many` keep outstanding per connection.
+pub const MANY_IN_FLIGHT_PER_CLIENT: usize = 256;
+
 /// A HyperDex client, used for common operations like getting and putting objects.
 ///
 /// The functions implemented by this client correspond 1-to-1 to those in the C API.
 /// Please refer to [HyperDex's official documentation](http://hyperdex.org/doc/latest/CClientAPI/#chap:api:c-client) for details:
 pub struct Client {
     counter: AtomicUsize,
-    shutdown_txs: Vec<Sender<()>>,
     inner_clients: Vec<InnerClient>,
+    waker: Arc<Waker>,
+    shutdown_tx: Option<Sender<Instant>>,
+    thread: Option<JoinHandle<()>>,
 }
 
 impl Client {
 
     /// Creates a new client.
     pub fn new(coordinator: SocketAddr) -> Result<Client, String> {
+        Client::with_metrics(coordinator, Arc::new(NoMetrics))
+    }
+
+    /// Creates a new client that reports every operation to `metrics`, as described in the
+    /// `metrics` module.
+    pub fn with_metrics(coordinator: SocketAddr, metrics: Arc<Metrics>) -> Result<Client, String> {
         let ip_str = format!("{}", coordinator.ip()).to_c_str();
 
         let (err_tx, err_rx) = channel();
 
+        let reactor = match Reactor::new() {
+            Ok(r) => r,
+            Err(err) => return Err(format!("Unable to create the event loop: {}", err)),
+        };
+
+        let schemas = Arc::new(Mutex::new(Schemas::new()));
         let mut inner_clients = Vec::new();
-        let mut shutdown_txs = Vec::new();
-        for _ in 0..num_cpus::get() {
+        for i in 0..num_cpus::get() {
             let ptr = unsafe { hyperdex_client_create(ip_str.as_ptr(), coordinator.port()) };
             if ptr.is_null() {
                 return Err(format!("Unable to create client.  errno is: {}", errno::errno()));
             } else {
-                let ops = Arc::new(Mutex::new(HashMap::new()));
-                let (shutdown_tx, shutdown_rx) = channel();
-                let mut inner_client = InnerClient {
+                let pending = Arc::new(AtomicUsize::new(0));
+                let inner_client = InnerClient {
                     ptr: unsafe { Unique::new(ptr) },
-                    ops: ops.clone(),
-                    err_tx: err_tx.clone(),
+                    handle: Arc::new(ClientHandle(ptr)),
+                    ops: Arc::new(Mutex::new(Ops::new(pending.clone()))),
+                    pending: pending,
                     mutex: Arc::new(Mutex::new(())),
+                    waker: Some(reactor.waker()),
+                    schemas: schemas.clone(),
+                    metrics: metrics.clone(),
+                    id: i,
                 };
-                let mut ic_clone = inner_client.clone();
-                thread::spawn(move|| {
-                    ic_clone.run_forever(shutdown_rx);
-                });
+                let fd = unsafe { hyperdex_client_poll(ptr) };
+                if let Err(err) = reactor.register(fd, i) {
+                    return Err(format!("Unable to watch the client's socket: {}", err));
+                }
                 inner_clients.push(inner_client);
-                shutdown_txs.push(shutdown_tx);
             }
         };
 
+        let (shutdown_tx, shutdown_rx) = channel();
+        let waker = reactor.waker();
+        let clients = inner_clients.clone();
+        let thread = thread::spawn(move|| {
+            run_reactor(reactor, clients, shutdown_rx, err_tx);
+        });
+
         Ok(Client {
             counter: AtomicUsize::new(0),
             inner_clients: inner_clients,
-            shutdown_txs: shutdown_txs,
+            waker: waker,
+            shutdown_tx: Some(shutdown_tx),
+            thread: Some(thread),
         })
     }
 
+    /// Shuts the client down.
+    ///
+    /// Operations that are already in flight get until `timeout` elapses to complete; whatever
+    /// is still pending after that fails with `HYPERDEX_CLIENT_INTERRUPTED`.  Returns once the
+    /// background thread has exited.  The underlying C handles are destroyed as soon as the
+    /// last future referring to them has resolved.
+    ///
+    /// Dropping a `Client` is equivalent to shutting it down with a zero timeout.
+    pub fn shutdown(mut self, timeout: Duration) {
+        self.stop(timeout);
+    }
+
+    fn stop(&mut self, timeout: Duration) {
+        match self.shutdown_tx.take() {
+            Some(shutdown_tx) => {
+                shutdown_tx.send(Instant::now() + timeout);
+                self.waker.wake();
+            },
+            None => return,
+        }
+        match self.thread.take() {
+            Some(t) => {
+                t.join();
+            },
+            None => (),
+        }
+    }
+
     pub fn search<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
-        -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr { unsafe {
-            let inner_client =
-                self.inner_clients[self.counter.fetch_add(1, Ordering::Relaxed) as usize % self.inner_clients.len()].clone();
+        -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr {
+        let (res_tx, res_rx) = channel();
+        self.next_inner_client().search(space, checks, SearchSink::Objects(res_tx));
+        res_rx
+    }
 
-            let (res_tx, res_rx) = channel();
+    /// Like `search`, but returns at most `limit` objects ordered by `sort_by`: largest first
+    /// if `maxmin` is set, smallest first otherwise.
+    pub fn sorted_search<S, A>(&mut self, space: S, checks: Vec<HyperPredicate>, sort_by: A, limit: u64, maxmin: bool)
+        -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr, A: ToCStr {
+        let (res_tx, res_rx) = channel();
+        self.next_inner_client().sorted_search(space, checks, sort_by, limit, maxmin, SearchSink::Objects(res_tx));
+        res_rx
+    }
 
-            let arena = hyperdex_ds_arena_create();
-            let c_checks = match convert_predicates(arena, checks) {
-                Ok(x) => x,
-                Err(err) => {
-                    res_tx.send(Err(HyperError {
-                        status: 0,
-                        message: err,
-                        location: String::new(),
-                    }));
-                    return res_rx;
-                },
-            };
+    /// Like `search`, but hands back the key of each object separately from its attributes.
+    /// The schema of the space must have been declared with `set_schema`.
+    pub fn search_keyed<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Receiver<Result<(HyperKey, HyperObject), HyperError>> where S: ToCStr {
+        let (res_tx, res_rx) = channel();
+        self.next_inner_client().search_keyed(space, checks, res_tx);
+        res_rx
+    }
 
-            let mut status_ptr = box 0u32;
-            let mut attrs_ptr = box AttributePtr(null());
-            let mut attrs_sz_ptr = box 0u64;
-            let space_str = space.to_c_str();
+    pub fn async_count<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Future<u64, HyperError> where S: ToCStr {
+        self.next_inner_client().count(space, checks)
+    }
 
-            let _lockhandle = inner_client.mutex.lock();
-            let mut ops_mutex = inner_client.ops.clone();
-            {
-                let mut ops = &mut*ops_mutex.lock().unwrap();
-                let req_id =
-                    hyperdex_client_search(*inner_client.ptr,
-                                           space_str.as_ptr() as *const i8,
-                                           c_checks.as_ptr(),
-                                           c_checks.len() as u64,
-                                           &mut *status_ptr,
-                                           &mut (*attrs_ptr).0,
-                                           &mut *attrs_sz_ptr);
-                if req_id < 0 {
-                    res_tx.send(Err(get_client_error(*inner_client.ptr, 0)));
-                    return res_rx;
-                }
+    /// Declares the key and attributes of a space, as returned by `Admin::space_schema`.
+    ///
+    /// Keys and predicates given for a space are checked against its schema, and the operation
+    /// fails with `HYPERDEX_CLIENT_WRONGTYPE` instead of sending the server a key it would
+    /// misread.  The client never reads schemas on its own: keys and predicates for spaces that
+    /// were not declared are sent unchecked and left for the server to judge.  Declaring a space
+    /// again replaces its schema, for instance after fetching it anew from an `Admin`.
+    pub fn set_schema(&mut self, schema: SpaceSchema) {
+        self.inner_clients[0].schemas.lock().unwrap().insert(schema);
+    }
 
-                let mut state = SearchState {
-                    status: status_ptr,
-                    attrs: attrs_ptr,
-                    attrs_sz: attrs_sz_ptr,
-                    res_tx: res_tx,
-                };
+    fn trace(&self, op: &'static str, space: &[u8], key: Option<&HyperValue>) -> OpTrace {
+        self.inner_clients[0].trace(op, space, key)
+    }
 
-                ops.insert(req_id, HyperStateSearch(state));
-            }
-            hyperdex_ds_arena_destroy(arena);
-            return res_rx;
+    /// Like `search`, but yields views that decode attributes only when they are read, instead
+    /// of copying every object into a `HyperObject`.
+    pub fn search_borrowed<'a, S>(&'a self, space: S, checks: Vec<HyperPredicate>)
+        -> BorrowedSearch<'a> where S: ToCStr {
+        let (res_tx, res_rx) = channel();
+        self.next_inner_client().search(space, checks, SearchSink::Borrowed(res_tx));
+        BorrowedSearch::new(res_rx)
+    }
+
+    /// Like `get`, but returns a view that decodes attributes only when they are read.
+    pub fn get_borrowed<'a, S, K>(&'a self, space: S, key: K)
+        -> Result<BorrowedObject<'a>, HyperError> where S: ToCStr, K: ToHyperValue {
+        self.next_inner_client().get_borrowed(space, key).into_inner().map(BorrowedObject::new)
+    }
+
+    /// Counts the objects that match `checks`.
+    pub fn count<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Result<u64, HyperError> where S: ToCStr {
+        self.async_count(space, checks).into_inner()
+    }
+
+    /// Fetches many objects at once.
+    ///
+    /// The requests are spread over all connections, with at most `MANY_IN_FLIGHT_PER_CLIENT`
+    /// outstanding per connection.  Every key gets its own result, so a missing object shows up
+    /// as an error with status `HYPERDEX_CLIENT_NOTFOUND` for that key alone.
+    pub fn get_many<S, K, I>(&mut self, space: S, keys: I)
+        -> HashMap<K, Result<HyperObject, HyperError>>
+        where S: ToCStr + Clone, K: ToHyperValue + Clone + Hash + Eq, I: IntoIterator<Item=K> {
+        let limit = MANY_IN_FLIGHT_PER_CLIENT * self.inner_clients.len();
+        fetch_many(self, limit, keys, |client: &mut Client, key| client.async_get(space.clone(), key))
+    }
+
+    /// Like `get_many`, but only fetches the given attributes of each object.
+    pub fn get_partial_many<S, K, A, I>(&mut self, space: S, keys: I, attrs: Vec<A>)
+        -> HashMap<K, Result<HyperObject, HyperError>>
+        where S: ToCStr + Clone, K: ToHyperValue + Clone + Hash + Eq, A: ToString + Clone,
+              I: IntoIterator<Item=K> {
+        let limit = MANY_IN_FLIGHT_PER_CLIENT * self.inner_clients.len();
+        fetch_many(self, limit, keys, |client: &mut Client, key| {
+            client.async_get_partial(space.clone(), key, attrs.clone())
+        })
+    }
+
+    /// Starts a batch of operations that will be submitted together.
+    pub fn batch(&mut self) -> Batch {
+        Batch {
+            client: self,
+            ops: Vec::new(),
         }
     }
 
+    fn next_inner_client(&self) -> InnerClient {
+        // TODO: Is "Relaxed" good enough?
+        self.inner_clients[self.counter.fetch_add(1, Ordering::Relaxed) as usize % self.inner_clients.len()].clone()
+    }
+
     // pub fn new_from_conn_str(conn: String) -> Result<Client, String> {
         // let conn_str = conn.to_c_str().as_ptr();
         // let ptr = unsafe { hyperdex_client_create_conn_str(conn_str) };
@@ -1572,7 +1927,142 @@ impl Client {
     // }
 }
 
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.stop(Duration::from_secs(0));
+    }
+}
+
+/// A HyperDex client that runs no threads of its own.
+///
+/// `LocalClient` offers the same operations as `Client`, but nothing happens in the background:
+/// the caller registers `poll_fd()` with its own event loop and calls `process_ready()` whenever
+/// the descriptor becomes readable.  That call completes the futures and search streams whose
+/// requests have finished.
+///
+/// Futures returned by a `LocalClient` never resolve unless `process_ready` is called, so
+/// blocking on one (including through the synchronous methods) from the thread that drives the
+/// event loop will deadlock.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut client = LocalClient::new(coordinator).unwrap();
+/// let fut = client.async_get(space_name, "derek");
+/// // ... once client.poll_fd() is readable:
+/// client.process_ready().unwrap();
+/// ```
+pub struct LocalClient {
+    inner: InnerClient,
+    req_buf: ReqBuf,
+}
+
+impl LocalClient {
+
+    /// Creates a new client.
+    pub fn new(coordinator: SocketAddr) -> Result<LocalClient, String> {
+        LocalClient::with_metrics(coordinator, Arc::new(NoMetrics))
+    }
+
+    /// Creates a new client that reports every operation to `metrics`, like
+    /// `Client::with_metrics`.
+    pub fn with_metrics(coordinator: SocketAddr, metrics: Arc<Metrics>) -> Result<LocalClient, String> {
+        let ip_str = format!("{}", coordinator.ip()).to_c_str();
+
+        let ptr = unsafe { hyperdex_client_create(ip_str.as_ptr(), coordinator.port()) };
+        if ptr.is_null() {
+            return Err(format!("Unable to create client.  errno is: {}", errno::errno()));
+        }
+
+        let pending = Arc::new(AtomicUsize::new(0));
+        Ok(LocalClient {
+            inner: InnerClient {
+                ptr: unsafe { Unique::new(ptr) },
+                handle: Arc::new(ClientHandle(ptr)),
+                ops: Arc::new(Mutex::new(Ops::new(pending.clone()))),
+                pending: pending,
+                mutex: Arc::new(Mutex::new(())),
+                waker: None,
+                schemas: Arc::new(Mutex::new(Schemas::new())),
+                metrics: metrics,
+                id: 0,
+            },
+            req_buf: Vec::new(),
+        })
+    }
+
+    /// The descriptor to watch for readability.  It stays valid for the lifetime of the client.
+    pub fn poll_fd(&self) -> c_int {
+        unsafe { hyperdex_client_poll(*self.inner.ptr) }
+    }
+
+    /// Completes every request whose response has arrived, and returns how many requests
+    /// completed.  Never blocks.
+    ///
+    /// Fails if the event loop reports an error; the requests that completed before it are
+    /// still completed.
+    pub fn process_ready(&mut self) -> Result<usize, HyperError> {
+        self.inner.process(&mut self.req_buf)
+    }
+
+    /// The number of requests that are still waiting on the server.
+    pub fn pending(&self) -> usize {
+        self.inner.pending.load(Ordering::Relaxed)
+    }
+
+    pub fn search<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr {
+        let (res_tx, res_rx) = channel();
+        self.inner.search(space, checks, SearchSink::Objects(res_tx));
+        res_rx
+    }
+
+    pub fn sorted_search<S, A>(&mut self, space: S, checks: Vec<HyperPredicate>, sort_by: A, limit: u64, maxmin: bool)
+        -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr, A: ToCStr {
+        let (res_tx, res_rx) = channel();
+        self.inner.sorted_search(space, checks, sort_by, limit, maxmin, SearchSink::Objects(res_tx));
+        res_rx
+    }
+
+    /// Like `search`, but hands back the key of each object separately from its attributes.
+    /// The schema of the space must have been declared with `set_schema`.
+    pub fn search_keyed<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Receiver<Result<(HyperKey, HyperObject), HyperError>> where S: ToCStr {
+        let (res_tx, res_rx) = channel();
+        self.inner.search_keyed(space, checks, res_tx);
+        res_rx
+    }
+
+    pub fn async_count<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Future<u64, HyperError> where S: ToCStr {
+        self.inner.count(space, checks)
+    }
+
+    /// Declares the key and attributes of a space, like `Client::set_schema`.
+    ///
+    /// Like a `Client`, a `LocalClient` never reads schemas on its own, so it never starts a
+    /// thread or waits on the coordinator for one.
+    pub fn set_schema(&mut self, schema: SpaceSchema) {
+        self.inner.schemas.lock().unwrap().insert(schema);
+    }
+
+    fn trace(&self, op: &'static str, space: &[u8], key: Option<&HyperValue>) -> OpTrace {
+        self.inner.trace(op, space, key)
+    }
+
+    fn next_inner_client(&self) -> InnerClient {
+        self.inner.clone()
+    }
+}
+
+impl Drop for LocalClient {
+    fn drop(&mut self) {
+        self.inner.fail_pending();
+    }
+}
+
 make_fn_spacename_key_status!(del, async_del);
+make_fn_spacename_key_predicates_status!(cond_del, async_cond_del);
 make_fn_spacename_key_status_attributes!(get, async_get);
 
 make_fn_spacename_key_attributenames_status_attributes!(get_partial, async_get_partial);
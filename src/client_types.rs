@@ -4,15 +4,19 @@ use std::hash::{Hash, Hasher};
 use std::sync::mpsc::{channel, Sender, Receiver};
 use std::iter::FromIterator;
 use std::cmp::Ordering;
-use std::ptr::Unique;
 use std::hash;
 use std::fmt::Debug;
 
-use rustc_serialize::json::Json;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
 
 use libc::*;
 
 use common::*;
+use borrowed::AttributeBuffer;
+use client::OpTrace;
+use schema::{Datatype, Primitive, SpaceSchema};
 
 use hyperdex_client::*;
 use hyperdex_datastructures::*;
@@ -49,19 +53,33 @@ pub enum HyperValue {
     HyperMapFloatInt(HashMap<F64, i64>),
     HyperMapFloatFloat(HashMap<F64, f64>),
 
-    HyperDocument(Json)
+    HyperDocument(Value)
+}
+
+/// Where the objects found by a search are delivered.
+pub enum SearchSink {
+    Objects(Sender<Result<HyperObject, HyperError>>),   // decoded into owned objects
+    Borrowed(Sender<Result<AttributeBuffer, HyperError>>),  // handed over as returned by the C client
+    Keyed(Sender<Result<(HyperKey, HyperObject), HyperError>>, String),  // decoded, with the named key taken out
 }
 
 pub struct SearchState {
     pub status: Box<Enum_hyperdex_client_returncode>,
     pub attrs: Box<AttributePtr>,
     pub attrs_sz: Box<size_t>,
-    pub res_tx: Sender<Result<HyperObject, HyperError>>,
+    pub sink: SearchSink,
+    pub trace: OpTrace,
 }
 
+/// Completes the future of a request.  Completing a future runs whatever is chained onto it,
+/// which may submit new requests, so completions run once the client's locks are released.
+pub type Completion = Box<FnOnce() + Send>;
+
 pub enum HyperState {
-    HyperStateOp(Sender<HyperError>),  // for calls that don't return values
-    HyperStateSearch(SearchState),  // for calls that do return values
+    // for calls that return at most once; invoked with the loop's status when the call completes,
+    // while the client is still locked, and returns what is left to do once it is unlocked
+    HyperStateOp(Box<FnOnce(*mut Struct_hyperdex_client, HyperError) -> Completion + Send>),
+    HyperStateSearch(SearchState),  // for calls that return a stream of values
 }
 
 pub struct Request {
@@ -70,18 +88,49 @@ pub struct Request {
 }
 
 /// Predicates that HyperDex supports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum HyperPredicateType {
-    FAIL = HYPERPREDICATE_FAIL as isize,
-    EQUALS = HYPERPREDICATE_EQUALS as isize,
-    LESS_THAN = HYPERPREDICATE_LESS_THAN as isize,
-    LESS_EQUAL = HYPERPREDICATE_LESS_EQUAL as isize,
-    GREATER_EQUAL = HYPERPREDICATE_GREATER_EQUAL as isize,
-    GREATER_THAN = HYPERPREDICATE_GREATER_THAN as isize,
-    REGEX = HYPERPREDICATE_REGEX as isize,
-    LENGTH_EQUALS = HYPERPREDICATE_LENGTH_EQUALS as isize,
-    LENGTH_LESS_EQUAL = HYPERPREDICATE_LENGTH_LESS_EQUAL as isize,
-    LENGTH_GREATER_EQUAL = HYPERPREDICATE_LENGTH_GREATER_EQUAL as isize,
-    CONTAINS = HYPERPREDICATE_CONTAINS as isize,
+    Fail = HYPERPREDICATE_FAIL as isize,
+    Equals = HYPERPREDICATE_EQUALS as isize,
+    LessThan = HYPERPREDICATE_LESS_THAN as isize,
+    LessEqual = HYPERPREDICATE_LESS_EQUAL as isize,
+    GreaterEqual = HYPERPREDICATE_GREATER_EQUAL as isize,
+    GreaterThan = HYPERPREDICATE_GREATER_THAN as isize,
+    ContainsLessThan = HYPERPREDICATE_CONTAINS_LESS_THAN as isize,
+    Regex = HYPERPREDICATE_REGEX as isize,
+    LengthEquals = HYPERPREDICATE_LENGTH_EQUALS as isize,
+    LengthLessEqual = HYPERPREDICATE_LENGTH_LESS_EQUAL as isize,
+    LengthGreaterEqual = HYPERPREDICATE_LENGTH_GREATER_EQUAL as isize,
+    Contains = HYPERPREDICATE_CONTAINS as isize,
+}
+
+/// The names the predicates had before they followed the naming conventions, so that code
+/// spelling out `HyperPredicateType::EQUALS` keeps building.
+impl HyperPredicateType {
+    #[deprecated(note = "use HyperPredicateType::Fail")]
+    pub const FAIL: HyperPredicateType = HyperPredicateType::Fail;
+    #[deprecated(note = "use HyperPredicateType::Equals")]
+    pub const EQUALS: HyperPredicateType = HyperPredicateType::Equals;
+    #[deprecated(note = "use HyperPredicateType::LessThan")]
+    pub const LESS_THAN: HyperPredicateType = HyperPredicateType::LessThan;
+    #[deprecated(note = "use HyperPredicateType::LessEqual")]
+    pub const LESS_EQUAL: HyperPredicateType = HyperPredicateType::LessEqual;
+    #[deprecated(note = "use HyperPredicateType::GreaterEqual")]
+    pub const GREATER_EQUAL: HyperPredicateType = HyperPredicateType::GreaterEqual;
+    #[deprecated(note = "use HyperPredicateType::GreaterThan")]
+    pub const GREATER_THAN: HyperPredicateType = HyperPredicateType::GreaterThan;
+    #[deprecated(note = "use HyperPredicateType::ContainsLessThan")]
+    pub const CONTAINS_LESS_THAN: HyperPredicateType = HyperPredicateType::ContainsLessThan;
+    #[deprecated(note = "use HyperPredicateType::Regex")]
+    pub const REGEX: HyperPredicateType = HyperPredicateType::Regex;
+    #[deprecated(note = "use HyperPredicateType::LengthEquals")]
+    pub const LENGTH_EQUALS: HyperPredicateType = HyperPredicateType::LengthEquals;
+    #[deprecated(note = "use HyperPredicateType::LengthLessEqual")]
+    pub const LENGTH_LESS_EQUAL: HyperPredicateType = HyperPredicateType::LengthLessEqual;
+    #[deprecated(note = "use HyperPredicateType::LengthGreaterEqual")]
+    pub const LENGTH_GREATER_EQUAL: HyperPredicateType = HyperPredicateType::LengthGreaterEqual;
+    #[deprecated(note = "use HyperPredicateType::Contains")]
+    pub const CONTAINS: HyperPredicateType = HyperPredicateType::Contains;
 }
 
 /// A predicate used for search.
@@ -89,9 +138,10 @@ pub enum HyperPredicateType {
 /// # Examples
 /// 
 /// ```
-/// let predicates = vec!(HyperPredicate::new("age", LESS_EQUAL, 25));
+/// let predicates = vec!(HyperPredicate::new("age", LessEqual, 25));
 /// let res = client.search(space_name, predicates);
 /// ```
+#[derive(Clone, Debug, PartialEq)]
 pub struct HyperPredicate {
     pub attr: String,
     pub value: HyperValue,
@@ -107,6 +157,181 @@ impl HyperPredicate {
             predicate: predicate,
         }
     }
+
+    /// Checks that the value is one the predicate can be applied to.
+    ///
+    /// The length predicates take an int, `Regex` a string, and the ordering and containment
+    /// predicates a single string, int or float; only `Equals` and `Fail` accept any value.
+    pub fn validate(&self) -> Result<(), HyperError> {
+        let ok = match (self.predicate, &self.value) {
+            (HyperPredicateType::Fail, _) | (HyperPredicateType::Equals, _) => true,
+            (HyperPredicateType::LengthEquals, &HyperInt(_)) |
+            (HyperPredicateType::LengthLessEqual, &HyperInt(_)) |
+            (HyperPredicateType::LengthGreaterEqual, &HyperInt(_)) => true,
+            (HyperPredicateType::Regex, &HyperString(_)) => true,
+            (HyperPredicateType::LessThan, v) |
+            (HyperPredicateType::LessEqual, v) |
+            (HyperPredicateType::GreaterEqual, v) |
+            (HyperPredicateType::GreaterThan, v) |
+            (HyperPredicateType::ContainsLessThan, v) |
+            (HyperPredicateType::Contains, v) => match *v {
+                HyperString(_) | HyperInt(_) | HyperFloat(_) => true,
+                _ => false,
+            },
+            _ => false,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(HyperError {
+                status: HYPERDEX_CLIENT_WRONGTYPE,
+                message: format!("{} cannot be applied to {:?} on \"{}\"", self.predicate, self.value, self.attr),
+                location: String::new(),
+            })
+        }
+    }
+
+    /// Checks, on top of `validate`, that the predicate can be applied to the attribute it names
+    /// in `schema`: `Equals` and the ordering predicates need a value of the attribute's type (and
+    /// the latter a string, int or float attribute), `Regex` a string attribute, the length
+    /// predicates a string or container, and the containment predicates a container whose
+    /// elements (or map keys) have the value's type.  Fields of documents have no declared type,
+    /// so a path into a document is only checked to start at a document attribute.
+    pub fn check(&self, schema: &SpaceSchema) -> Result<(), HyperError> {
+        try!(self.validate());
+        let attr = self.attr.split('.').next().unwrap();
+        let dt = match schema.datatype(attr) {
+            Some(dt) => dt,
+            None => return Err(HyperError {
+                status: HYPERDEX_CLIENT_UNKNOWNATTR,
+                message: format!("space \"{}\" has no attribute \"{}\"", schema.name, attr),
+                location: String::new(),
+            }),
+        };
+        if attr.len() < self.attr.len() {
+            return if dt == Datatype::Document {
+                Ok(())
+            } else {
+                Err(HyperError {
+                    status: HYPERDEX_CLIENT_UNKNOWNATTR,
+                    message: format!("\"{}\" is a {}, not a document", attr, dt),
+                    location: String::new(),
+                })
+            };
+        }
+
+        let ok = match (self.predicate, dt) {
+            (HyperPredicateType::Fail, _) => true,
+            (HyperPredicateType::Equals, dt) => fits(dt, &self.value),
+            (HyperPredicateType::LessThan, dt) |
+            (HyperPredicateType::LessEqual, dt) |
+            (HyperPredicateType::GreaterEqual, dt) |
+            (HyperPredicateType::GreaterThan, dt) => match dt {
+                Datatype::String | Datatype::Int | Datatype::Float => fits(dt, &self.value),
+                _ => false,
+            },
+            (HyperPredicateType::Regex, Datatype::String) => true,
+            (HyperPredicateType::LengthEquals, dt) |
+            (HyperPredicateType::LengthLessEqual, dt) |
+            (HyperPredicateType::LengthGreaterEqual, dt) => match dt {
+                Datatype::String | Datatype::List(_) | Datatype::Set(_) | Datatype::Map(_, _) => true,
+                _ => false,
+            },
+            (HyperPredicateType::Contains, Datatype::List(elem)) |
+            (HyperPredicateType::Contains, Datatype::Set(elem)) |
+            (HyperPredicateType::Contains, Datatype::Map(elem, _)) |
+            (HyperPredicateType::ContainsLessThan, Datatype::List(elem)) |
+            (HyperPredicateType::ContainsLessThan, Datatype::Set(elem)) |
+            (HyperPredicateType::ContainsLessThan, Datatype::Map(elem, _)) => fits(scalar(elem), &self.value),
+            _ => false,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(HyperError {
+                status: HYPERDEX_CLIENT_WRONGTYPE,
+                message: format!("{} cannot be applied to {:?} on the {} attribute \"{}\"",
+                                 self.predicate, self.value, dt, self.attr),
+                location: String::new(),
+            })
+        }
+    }
+}
+
+impl Display for HyperPredicateType {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        Display::fmt(match *self {
+            HyperPredicateType::Fail => "FAIL",
+            HyperPredicateType::Equals => "EQUALS",
+            HyperPredicateType::LessThan => "LESS_THAN",
+            HyperPredicateType::LessEqual => "LESS_EQUAL",
+            HyperPredicateType::GreaterEqual => "GREATER_EQUAL",
+            HyperPredicateType::GreaterThan => "GREATER_THAN",
+            HyperPredicateType::ContainsLessThan => "CONTAINS_LESS_THAN",
+            HyperPredicateType::Regex => "REGEX",
+            HyperPredicateType::LengthEquals => "LENGTH_EQUALS",
+            HyperPredicateType::LengthLessEqual => "LENGTH_LESS_EQUAL",
+            HyperPredicateType::LengthGreaterEqual => "LENGTH_GREATER_EQUAL",
+            HyperPredicateType::Contains => "CONTAINS",
+        }, f)
+    }
+}
+
+/// Validates every predicate, as done before a search or conditional operation is submitted.
+pub fn validate_predicates(checks: &[HyperPredicate]) -> Result<(), HyperError> {
+    for check in checks.iter() {
+        try!(check.validate());
+    }
+    Ok(())
+}
+
+/// Checks every predicate against the schema of the space it is about to be applied to.
+pub fn check_predicates(checks: &[HyperPredicate], schema: &SpaceSchema) -> Result<(), HyperError> {
+    for check in checks.iter() {
+        try!(check.check(schema));
+    }
+    Ok(())
+}
+
+fn scalar(p: Primitive) -> Datatype {
+    match p {
+        Primitive::String => Datatype::String,
+        Primitive::Int => Datatype::Int,
+        Primitive::Float => Datatype::Float,
+    }
+}
+
+/// Whether `val` can be compared with an attribute of type `dt`.  Like the server, an empty
+/// container fits any container of the same kind.
+fn fits(dt: Datatype, val: &HyperValue) -> bool {
+    let (s, i, f) = (Primitive::String, Primitive::Int, Primitive::Float);
+    let (vdt, empty) = match *val {
+        HyperString(_) => (Datatype::String, false),
+        HyperInt(_) => (Datatype::Int, false),
+        HyperFloat(_) => (Datatype::Float, false),
+        HyperDocument(_) => (Datatype::Document, false),
+        HyperListString(ref l) => (Datatype::List(s), l.is_empty()),
+        HyperListInt(ref l) => (Datatype::List(i), l.is_empty()),
+        HyperListFloat(ref l) => (Datatype::List(f), l.is_empty()),
+        HyperSetString(ref l) => (Datatype::Set(s), l.is_empty()),
+        HyperSetInt(ref l) => (Datatype::Set(i), l.is_empty()),
+        HyperSetFloat(ref l) => (Datatype::Set(f), l.is_empty()),
+        HyperMapStringString(ref m) => (Datatype::Map(s, s), m.is_empty()),
+        HyperMapStringInt(ref m) => (Datatype::Map(s, i), m.is_empty()),
+        HyperMapStringFloat(ref m) => (Datatype::Map(s, f), m.is_empty()),
+        HyperMapIntString(ref m) => (Datatype::Map(i, s), m.is_empty()),
+        HyperMapIntInt(ref m) => (Datatype::Map(i, i), m.is_empty()),
+        HyperMapIntFloat(ref m) => (Datatype::Map(i, f), m.is_empty()),
+        HyperMapFloatString(ref m) => (Datatype::Map(f, s), m.is_empty()),
+        HyperMapFloatInt(ref m) => (Datatype::Map(f, i), m.is_empty()),
+        HyperMapFloatFloat(ref m) => (Datatype::Map(f, f), m.is_empty()),
+    };
+    vdt == dt || (empty && match (vdt, dt) {
+        (Datatype::List(_), Datatype::List(_)) |
+        (Datatype::Set(_), Datatype::Set(_)) |
+        (Datatype::Map(_, _), Datatype::Map(_, _)) => true,
+        _ => false,
+    })
 }
 
 /// A key-value pair associated with a specific map attribute
@@ -124,6 +349,9 @@ pub enum HyperObjectKeyError {
 
     /// The key does exist, but the value is not the type that you think it is.
     ObjectIsAnotherType,
+
+    /// The key holds a document that does not decode into the type that you asked for.
+    MalformedDocument(String),
 }
 
 use std::fmt::{Display, Formatter, Error};
@@ -132,7 +360,8 @@ impl Display for HyperObjectKeyError {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         Display::fmt(match *self {
             HyperObjectKeyError::KeyDoesNotExist => "the key does not exist",
-            HyperObjectKeyError::ObjectIsAnotherType => "the object is of another type"
+            HyperObjectKeyError::ObjectIsAnotherType => "the object is of another type",
+            HyperObjectKeyError::MalformedDocument(ref msg) => &msg[..],
         }, f)
     }
 }
@@ -180,7 +409,61 @@ from_hypervalue_impl!(HashMap<F64, Vec<u8>>, HyperMapFloatString);
 from_hypervalue_impl!(HashMap<F64, i64>, HyperMapFloatInt);
 from_hypervalue_impl!(HashMap<F64, f64>, HyperMapFloatFloat);
 
-from_hypervalue_impl!(Json, HyperDocument);
+from_hypervalue_impl!(Value, HyperDocument);
+
+/// Any serde type, stored as a document attribute.
+///
+/// Not every serde type has a JSON form (a map whose keys are not strings has none), so a
+/// `Document` is converted explicitly with `encode`, which fails instead of storing garbage.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct Profile { name: String, visits: i64 }
+///
+/// let mut obj = HyperObject::new();
+/// obj.insert("profile", try!(Document(Profile { name: "Derek".to_string(), visits: 1 }).encode()));
+/// client.put(space_name, "derek", obj).unwrap();
+///
+/// let Document(profile): Document<Profile> = client.get(space_name, "derek").unwrap().get("profile").unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Document<T>(pub T);
+
+impl<T> Document<T> where T: Serialize {
+    /// Converts the document into a `HyperDocument`.  Fails with `HYPERDEX_CLIENT_WRONGTYPE`,
+    /// carrying serde's message, if it has no JSON form.
+    pub fn encode(&self) -> Result<HyperValue, HyperError> {
+        serde_json::to_value(&self.0).map(HyperDocument).map_err(|err| HyperError {
+            status: HYPERDEX_CLIENT_WRONGTYPE,
+            message: format!("cannot store value as a document: {}", err),
+            location: String::new(),
+        })
+    }
+}
+
+impl<T> Document<T> where T: DeserializeOwned {
+    /// Converts a `HyperDocument` back into a `Document`.  Fails with `HYPERDEX_CLIENT_WRONGTYPE`
+    /// if `val` is not a document, or carrying serde's message if it does not fit `T`.
+    pub fn decode(val: HyperValue) -> Result<Document<T>, HyperError> {
+        Document::from_hyper(val).map_err(|err| HyperError {
+            status: HYPERDEX_CLIENT_WRONGTYPE,
+            message: format!("cannot read value as a document: {}", err),
+            location: String::new(),
+        })
+    }
+}
+
+impl<T> FromHyperValue for Document<T> where T: DeserializeOwned {
+    fn from_hyper(val: HyperValue) -> Result<Document<T>, HyperObjectKeyError> {
+        match val {
+            HyperDocument(doc) => serde_json::from_value(doc).map(Document)
+                                                             .map_err(|err| MalformedDocument(err.to_string())),
+            _ => Err(ObjectIsAnotherType),
+        }
+    }
+}
 
 /// A HyperDex object.
 ///
@@ -205,7 +488,7 @@ from_hypervalue_impl!(Json, HyperDocument);
 ///     Err(err) => panic!(err),
 /// }
 /// ```
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct HyperObject {
     pub map: HashMap<String, HyperValue>,
 }
@@ -444,12 +727,112 @@ impl ToHyperValue for HashMap<F64, f64> {
     }
 }
 
-impl ToHyperValue for Json {
+impl ToHyperValue for HyperValue {
+    fn to_hyper(self) -> HyperValue {
+        self
+    }
+}
+
+impl ToHyperValue for Value {
     fn to_hyper(self) -> HyperValue {
         HyperDocument(self)
     }
 }
 
+/// The key of an object.
+///
+/// HyperDex keys are strings, ints or floats, and every space declares which of the three its
+/// keys are.  Strings that are not valid UTF-8 are `Bytes`.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum HyperKey {
+    String(String),
+    Bytes(Vec<u8>),
+    Int(i64),
+    Float(f64),
+}
+
+impl HyperKey {
+    /// Fails with `HYPERDEX_CLIENT_WRONGTYPE` for values that cannot be keys.
+    pub fn from_hyper(val: HyperValue) -> Result<HyperKey, HyperError> {
+        match val {
+            HyperString(s) => Ok(match String::from_utf8(s) {
+                Ok(s) => HyperKey::String(s),
+                Err(err) => HyperKey::Bytes(err.into_bytes()),
+            }),
+            HyperInt(i) => Ok(HyperKey::Int(i)),
+            HyperFloat(f) => Ok(HyperKey::Float(f)),
+            _ => Err(HyperError {
+                status: HYPERDEX_CLIENT_WRONGTYPE,
+                message: "a key must be a string, an int or a float".to_string(),
+                location: String::new(),
+            }),
+        }
+    }
+
+    pub fn datatype(&self) -> Datatype {
+        match *self {
+            HyperKey::String(_) | HyperKey::Bytes(_) => Datatype::String,
+            HyperKey::Int(_) => Datatype::Int,
+            HyperKey::Float(_) => Datatype::Float,
+        }
+    }
+
+    /// Fails with `HYPERDEX_CLIENT_WRONGTYPE` unless the key has the type the space declares.
+    pub fn check(&self, schema: &SpaceSchema) -> Result<(), HyperError> {
+        if self.datatype() == schema.key_type {
+            Ok(())
+        } else {
+            Err(HyperError {
+                status: HYPERDEX_CLIENT_WRONGTYPE,
+                message: format!("the keys of space \"{}\" are {}s, not {}s",
+                                 schema.name, schema.key_type, self.datatype()),
+                location: String::new(),
+            })
+        }
+    }
+}
+
+impl ToHyperValue for HyperKey {
+    fn to_hyper(self) -> HyperValue {
+        match self {
+            HyperKey::String(s) => HyperString(s.into_bytes()),
+            HyperKey::Bytes(b) => HyperString(b),
+            HyperKey::Int(i) => HyperInt(i),
+            HyperKey::Float(f) => HyperFloat(f),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for HyperKey {
+    fn from(s: &'a str) -> HyperKey {
+        HyperKey::String(s.to_string())
+    }
+}
+
+impl From<String> for HyperKey {
+    fn from(s: String) -> HyperKey {
+        HyperKey::String(s)
+    }
+}
+
+impl From<Vec<u8>> for HyperKey {
+    fn from(b: Vec<u8>) -> HyperKey {
+        HyperKey::Bytes(b)
+    }
+}
+
+impl From<i64> for HyperKey {
+    fn from(i: i64) -> HyperKey {
+        HyperKey::Int(i)
+    }
+}
+
+impl From<f64> for HyperKey {
+    fn from(f: f64) -> HyperKey {
+        HyperKey::Float(f)
+    }
+}
+
 /// A wrapper around f64.
 /// 
 /// Unfortunately f64 does not implement Ord nor Eq, so we have to do it manually
@@ -0,0 +1,261 @@
+//! Packing and unpacking of HyperDex values in pure Rust.
+//!
+//! These are the encodings that `libhyperdex` uses on the wire and that the `hyperdex_ds_*`
+//! functions produce:
+//!
+//! * strings are their raw bytes, documents their JSON text;
+//! * ints and floats are 8 bytes, little endian;
+//! * lists are their elements back to back, where every string element is prefixed by its
+//!   length as a 4 byte little endian integer;
+//! * sets are encoded like lists, with the elements sorted in ascending order;
+//! * maps are their entries sorted by key, each entry being the key followed by the value,
+//!   both encoded like list elements.
+//!
+//! Empty containers carry the generic container datatype (e.g. `HYPERDATATYPE_LIST_GENERIC`),
+//! since there is no element to infer the type from.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem::transmute;
+
+use serde_json::{self, Map, Value};
+
+use hyperdex::*;
+use client_types::*;
+use client_types::HyperValue::*;
+
+/// Returns the datatype libhyperdex reports for `val`.
+pub fn datatype(val: &HyperValue) -> Enum_hyperdatatype {
+    match *val {
+        HyperString(_) => HYPERDATATYPE_STRING,
+        HyperInt(_) => HYPERDATATYPE_INT64,
+        HyperFloat(_) => HYPERDATATYPE_FLOAT,
+        HyperDocument(_) => HYPERDATATYPE_DOCUMENT,
+
+        HyperListString(ref l) if l.is_empty() => HYPERDATATYPE_LIST_GENERIC,
+        HyperListInt(ref l) if l.is_empty() => HYPERDATATYPE_LIST_GENERIC,
+        HyperListFloat(ref l) if l.is_empty() => HYPERDATATYPE_LIST_GENERIC,
+        HyperListString(_) => HYPERDATATYPE_LIST_STRING,
+        HyperListInt(_) => HYPERDATATYPE_LIST_INT64,
+        HyperListFloat(_) => HYPERDATATYPE_LIST_FLOAT,
+
+        HyperSetString(ref s) if s.is_empty() => HYPERDATATYPE_SET_GENERIC,
+        HyperSetInt(ref s) if s.is_empty() => HYPERDATATYPE_SET_GENERIC,
+        HyperSetFloat(ref s) if s.is_empty() => HYPERDATATYPE_SET_GENERIC,
+        HyperSetString(_) => HYPERDATATYPE_SET_STRING,
+        HyperSetInt(_) => HYPERDATATYPE_SET_INT64,
+        HyperSetFloat(_) => HYPERDATATYPE_SET_FLOAT,
+
+        HyperMapStringString(ref m) if m.is_empty() => HYPERDATATYPE_MAP_GENERIC,
+        HyperMapStringInt(ref m) if m.is_empty() => HYPERDATATYPE_MAP_GENERIC,
+        HyperMapStringFloat(ref m) if m.is_empty() => HYPERDATATYPE_MAP_GENERIC,
+        HyperMapIntString(ref m) if m.is_empty() => HYPERDATATYPE_MAP_GENERIC,
+        HyperMapIntInt(ref m) if m.is_empty() => HYPERDATATYPE_MAP_GENERIC,
+        HyperMapIntFloat(ref m) if m.is_empty() => HYPERDATATYPE_MAP_GENERIC,
+        HyperMapFloatString(ref m) if m.is_empty() => HYPERDATATYPE_MAP_GENERIC,
+        HyperMapFloatInt(ref m) if m.is_empty() => HYPERDATATYPE_MAP_GENERIC,
+        HyperMapFloatFloat(ref m) if m.is_empty() => HYPERDATATYPE_MAP_GENERIC,
+        HyperMapStringString(_) => HYPERDATATYPE_MAP_STRING_STRING,
+        HyperMapStringInt(_) => HYPERDATATYPE_MAP_STRING_INT64,
+        HyperMapStringFloat(_) => HYPERDATATYPE_MAP_STRING_FLOAT,
+        HyperMapIntString(_) => HYPERDATATYPE_MAP_INT64_STRING,
+        HyperMapIntInt(_) => HYPERDATATYPE_MAP_INT64_INT64,
+        HyperMapIntFloat(_) => HYPERDATATYPE_MAP_INT64_FLOAT,
+        HyperMapFloatString(_) => HYPERDATATYPE_MAP_FLOAT_STRING,
+        HyperMapFloatInt(_) => HYPERDATATYPE_MAP_FLOAT_INT64,
+        HyperMapFloatFloat(_) => HYPERDATATYPE_MAP_FLOAT_FLOAT,
+    }
+}
+
+/* Encoding */
+
+fn put_int(buf: &mut Vec<u8>, num: i64) {
+    for i in 0..8 {
+        buf.push((num >> (8 * i)) as u8);
+    }
+}
+
+fn put_float(buf: &mut Vec<u8>, num: f64) {
+    put_int(buf, unsafe { transmute::<f64, i64>(num) });
+}
+
+fn put_string(buf: &mut Vec<u8>, s: &[u8]) {
+    let len = s.len() as u32;
+    for i in 0..4 {
+        buf.push((len >> (8 * i)) as u8);
+    }
+    buf.extend(s.iter().cloned());
+}
+
+fn put_map<K, V, FK, FV>(buf: &mut Vec<u8>, map: &HashMap<K, V>, put_key: FK, put_val: FV)
+    where K: Ord + Hash + Eq, FK: Fn(&mut Vec<u8>, &K), FV: Fn(&mut Vec<u8>, &V) {
+    let mut entries: Vec<(&K, &V)> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    for (k, v) in entries.into_iter() {
+        put_key(buf, k);
+        put_val(buf, v);
+    }
+}
+
+/// Packs `val` the same way libhyperdex does.
+pub fn encode(val: &HyperValue) -> Vec<u8> {
+    let mut buf = Vec::new();
+    match *val {
+        HyperString(ref s) => buf.extend(s.iter().cloned()),
+        HyperInt(i) => put_int(&mut buf, i),
+        HyperFloat(f) => put_float(&mut buf, f),
+        HyperDocument(ref doc) => buf.extend(format!("{}", doc).into_bytes().into_iter()),
+
+        HyperListString(ref l) => for s in l.iter() { put_string(&mut buf, s) },
+        HyperListInt(ref l) => for &i in l.iter() { put_int(&mut buf, i) },
+        HyperListFloat(ref l) => for &f in l.iter() { put_float(&mut buf, f) },
+
+        HyperSetString(ref set) => for s in set.iter() { put_string(&mut buf, s) },
+        HyperSetInt(ref set) => for &i in set.iter() { put_int(&mut buf, i) },
+        HyperSetFloat(ref set) => for &F64(f) in set.iter() { put_float(&mut buf, f) },
+
+        HyperMapStringString(ref m) => put_map(&mut buf, m, |b, k| put_string(b, k), |b, v| put_string(b, v)),
+        HyperMapStringInt(ref m) => put_map(&mut buf, m, |b, k| put_string(b, k), |b, v| put_int(b, *v)),
+        HyperMapStringFloat(ref m) => put_map(&mut buf, m, |b, k| put_string(b, k), |b, v| put_float(b, *v)),
+        HyperMapIntString(ref m) => put_map(&mut buf, m, |b, k| put_int(b, *k), |b, v| put_string(b, v)),
+        HyperMapIntInt(ref m) => put_map(&mut buf, m, |b, k| put_int(b, *k), |b, v| put_int(b, *v)),
+        HyperMapIntFloat(ref m) => put_map(&mut buf, m, |b, k| put_int(b, *k), |b, v| put_float(b, *v)),
+        HyperMapFloatString(ref m) => put_map(&mut buf, m, |b, k| put_float(b, k.0), |b, v| put_string(b, v)),
+        HyperMapFloatInt(ref m) => put_map(&mut buf, m, |b, k| put_float(b, k.0), |b, v| put_int(b, *v)),
+        HyperMapFloatFloat(ref m) => put_map(&mut buf, m, |b, k| put_float(b, k.0), |b, v| put_float(b, *v)),
+    }
+    buf
+}
+
+/* Decoding */
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn take(&mut self, n: usize, what: &str) -> Result<&'a [u8], String> {
+        if self.buf.len() < n {
+            return Err(format!("truncated {}: needed {} bytes but only {} are left", what, n, self.buf.len()));
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn int(&mut self) -> Result<i64, String> {
+        let bytes = try!(self.take(8, "int"));
+        Ok(bytes.iter().rev().fold(0i64, |acc, &b| (acc << 8) | b as i64))
+    }
+
+    fn float(&mut self) -> Result<f64, String> {
+        let bits = try!(self.int());
+        Ok(unsafe { transmute::<i64, f64>(bits) })
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>, String> {
+        let len = try!(self.take(4, "string length"));
+        let len = len.iter().rev().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+        Ok(try!(self.take(len, "string")).to_vec())
+    }
+}
+
+fn elements<T, F>(buf: &[u8], mut elem: F) -> Result<Vec<T>, String>
+    where F: FnMut(&mut Reader) -> Result<T, String> {
+    let mut r = Reader { buf: buf };
+    let mut res = Vec::new();
+    while !r.is_empty() {
+        res.push(try!(elem(&mut r)));
+    }
+    Ok(res)
+}
+
+fn entries<K, V, FK, FV>(buf: &[u8], mut key: FK, mut val: FV) -> Result<Vec<(K, V)>, String>
+    where FK: FnMut(&mut Reader) -> Result<K, String>, FV: FnMut(&mut Reader) -> Result<V, String> {
+    let mut r = Reader { buf: buf };
+    let mut res = Vec::new();
+    while !r.is_empty() {
+        let k = try!(key(&mut r));
+        let v = try!(val(&mut r));
+        res.push((k, v));
+    }
+    Ok(res)
+}
+
+fn scalar<T, F>(buf: &[u8], what: &str, f: F) -> Result<T, String>
+    where F: FnOnce(&mut Reader) -> Result<T, String> {
+    let mut r = Reader { buf: buf };
+    let res = try!(f(&mut r));
+    if !r.is_empty() {
+        return Err(format!("malformed {}: {} trailing bytes", what, r.buf.len()));
+    }
+    Ok(res)
+}
+
+/// Unpacks a value of type `dt`, as produced by libhyperdex or `encode`.
+///
+/// An empty buffer decodes to the default value of its type, which is how HyperDex stores
+/// attributes that were never set.  Empty containers of the generic container types decode to
+/// the string flavour of that container.
+pub fn decode(dt: Enum_hyperdatatype, buf: &[u8]) -> Result<HyperValue, String> {
+    let empty_generic = |what: &str| if buf.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("a generic {} must be empty", what))
+    };
+
+    match dt {
+        HYPERDATATYPE_STRING => Ok(HyperString(buf.to_vec())),
+        HYPERDATATYPE_INT64 if buf.is_empty() => Ok(HyperInt(0)),
+        HYPERDATATYPE_INT64 => scalar(buf, "int", |r| r.int()).map(HyperInt),
+        HYPERDATATYPE_FLOAT if buf.is_empty() => Ok(HyperFloat(0.0)),
+        HYPERDATATYPE_FLOAT => scalar(buf, "float", |r| r.float()).map(HyperFloat),
+        HYPERDATATYPE_DOCUMENT => {
+            // The client library hands documents out with their terminating NUL.
+            let text = match buf.last() {
+                Some(&0) => &buf[..buf.len() - 1],
+                _ => buf,
+            };
+            if text.is_empty() {
+                return Ok(HyperDocument(Value::Object(Map::new())));
+            }
+            serde_json::from_slice(text).map(HyperDocument).map_err(|err| format!("malformed document: {}", err))
+        },
+
+        HYPERDATATYPE_LIST_GENERIC => empty_generic("list").map(|_| HyperListString(Vec::new())),
+        HYPERDATATYPE_LIST_STRING => elements(buf, |r| r.string()).map(HyperListString),
+        HYPERDATATYPE_LIST_INT64 => elements(buf, |r| r.int()).map(HyperListInt),
+        HYPERDATATYPE_LIST_FLOAT => elements(buf, |r| r.float()).map(HyperListFloat),
+
+        HYPERDATATYPE_SET_GENERIC => empty_generic("set").map(|_| HyperSetString(Default::default())),
+        HYPERDATATYPE_SET_STRING => elements(buf, |r| r.string()).map(|v| HyperSetString(v.into_iter().collect())),
+        HYPERDATATYPE_SET_INT64 => elements(buf, |r| r.int()).map(|v| HyperSetInt(v.into_iter().collect())),
+        HYPERDATATYPE_SET_FLOAT => elements(buf, |r| r.float().map(F64)).map(|v| HyperSetFloat(v.into_iter().collect())),
+
+        HYPERDATATYPE_MAP_GENERIC => empty_generic("map").map(|_| HyperMapStringString(HashMap::new())),
+        HYPERDATATYPE_MAP_STRING_STRING =>
+            entries(buf, |r| r.string(), |r| r.string()).map(|v| HyperMapStringString(v.into_iter().collect())),
+        HYPERDATATYPE_MAP_STRING_INT64 =>
+            entries(buf, |r| r.string(), |r| r.int()).map(|v| HyperMapStringInt(v.into_iter().collect())),
+        HYPERDATATYPE_MAP_STRING_FLOAT =>
+            entries(buf, |r| r.string(), |r| r.float()).map(|v| HyperMapStringFloat(v.into_iter().collect())),
+        HYPERDATATYPE_MAP_INT64_STRING =>
+            entries(buf, |r| r.int(), |r| r.string()).map(|v| HyperMapIntString(v.into_iter().collect())),
+        HYPERDATATYPE_MAP_INT64_INT64 =>
+            entries(buf, |r| r.int(), |r| r.int()).map(|v| HyperMapIntInt(v.into_iter().collect())),
+        HYPERDATATYPE_MAP_INT64_FLOAT =>
+            entries(buf, |r| r.int(), |r| r.float()).map(|v| HyperMapIntFloat(v.into_iter().collect())),
+        HYPERDATATYPE_MAP_FLOAT_STRING =>
+            entries(buf, |r| r.float().map(F64), |r| r.string()).map(|v| HyperMapFloatString(v.into_iter().collect())),
+        HYPERDATATYPE_MAP_FLOAT_INT64 =>
+            entries(buf, |r| r.float().map(F64), |r| r.int()).map(|v| HyperMapFloatInt(v.into_iter().collect())),
+        HYPERDATATYPE_MAP_FLOAT_FLOAT =>
+            entries(buf, |r| r.float().map(F64), |r| r.float()).map(|v| HyperMapFloatFloat(v.into_iter().collect())),
+
+        _ => Err(format!("Unrecognized datatype: {}", dt)),
+    }
+}
@@ -0,0 +1,320 @@
+//! Loading CSV files into a space, with each column converted to the type of its attribute.
+//!
+//! The first row names the columns, one of which must be the key of the space; the others are
+//! attributes, and cells are read according to the attribute's type:
+//!
+//! * strings are taken as they are, and documents are parsed as JSON;
+//! * ints and floats are parsed, ignoring surrounding whitespace;
+//! * lists and sets hold their elements separated by `;`, as in `red;green;blue`;
+//! * maps hold `key=value` entries separated by `;`, as in `home=5;work=3`.
+//!
+//! Empty cells leave their attribute untouched.  Fields may be quoted with `"`, in which case
+//! they can contain the delimiter, line breaks and `""` for a quote.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let schema = admin.space_schema("contacts").unwrap();
+//! let report = CsvImporter::new(schema).import(&mut client, BufReader::new(file)).unwrap();
+//! for err in report.errors.iter() {
+//!     println!("{}", err);
+//! }
+//! ```
+
+use std::fmt::{Display, Formatter, Error};
+use std::io::{self, BufRead, Lines};
+use std::mem;
+
+use serde_json;
+
+use client_types::*;
+use client_types::HyperValue::*;
+use schema::{Datatype, SpaceSchema};
+use store::KeyValueStore;
+
+/// A row that could not be loaded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RowError {
+    /// The line the row starts on, counting the header as line 1.
+    pub line: u64,
+    pub message: String,
+}
+
+impl Display for RowError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Why an import could not run at all.
+#[derive(Debug)]
+pub enum CsvError {
+    Io(io::Error),
+    /// The header row is missing the key, or names a column the space does not have.
+    Header(String),
+}
+
+impl Display for CsvError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            CsvError::Io(ref err) => write!(f, "{}", err),
+            CsvError::Header(ref msg) => write!(f, "line 1: {}", msg),
+        }
+    }
+}
+
+impl From<io::Error> for CsvError {
+    fn from(err: io::Error) -> CsvError {
+        CsvError::Io(err)
+    }
+}
+
+/// The outcome of an import.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CsvReport {
+    /// The rows that were written to the space.
+    pub written: u64,
+    /// The rows that were not, in the order they appear in the file.
+    pub errors: Vec<RowError>,
+}
+
+/// Reads the records of a CSV file, keeping track of the line each one starts on.
+struct Records<R> {
+    lines: Lines<R>,
+    line: u64,
+    delimiter: char,
+}
+
+impl<R: BufRead> Records<R> {
+    fn next_line(&mut self) -> io::Result<Option<String>> {
+        match self.lines.next() {
+            Some(line) => {
+                self.line += 1;
+                let mut line = try!(line);
+                if line.ends_with('\r') {
+                    line.pop();
+                }
+                Ok(Some(line))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// The next record that is not blank, with its line number, or the reason it is malformed.
+    fn next(&mut self) -> io::Result<Option<(u64, Result<Vec<String>, String>)>> {
+        let mut text = loop {
+            match try!(self.next_line()) {
+                Some(ref line) if line.trim().is_empty() => continue,
+                Some(line) => break line,
+                None => return Ok(None),
+            }
+        };
+        let start = self.line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        loop {
+            let chars: Vec<char> = text.chars().collect();
+            let mut i = 0;
+            while i < chars.len() {
+                let c = chars[i];
+                if quoted {
+                    if c == '"' && chars.get(i + 1) == Some(&'"') {
+                        field.push('"');
+                        i += 1;
+                    } else if c == '"' {
+                        quoted = false;
+                    } else {
+                        field.push(c);
+                    }
+                } else if c == '"' && field.is_empty() {
+                    quoted = true;
+                } else if c == self.delimiter {
+                    fields.push(mem::replace(&mut field, String::new()));
+                } else {
+                    field.push(c);
+                }
+                i += 1;
+            }
+            if !quoted {
+                break;
+            }
+            text = match try!(self.next_line()) {
+                Some(line) => line,
+                None => return Ok(Some((start, Err("a quoted field is never closed".to_string())))),
+            };
+            field.push('\n');
+        }
+        fields.push(field);
+        Ok(Some((start, Ok(fields))))
+    }
+}
+
+fn string(text: &str) -> Result<Vec<u8>, String> {
+    Ok(text.as_bytes().to_vec())
+}
+
+fn int(text: &str) -> Result<i64, String> {
+    text.trim().parse().map_err(|_| format!("\"{}\" is not an int", text))
+}
+
+fn float(text: &str) -> Result<f64, String> {
+    text.trim().parse().map_err(|_| format!("\"{}\" is not a float", text))
+}
+
+fn float_key(text: &str) -> Result<F64, String> {
+    float(text).map(F64)
+}
+
+/// A CSV importer for one space.
+pub struct CsvImporter {
+    schema: SpaceSchema,
+    delimiter: char,
+    separator: char,
+}
+
+impl CsvImporter {
+    /// An importer for the space described by `schema`, which `Admin::space_schema` provides.
+    pub fn new(schema: SpaceSchema) -> CsvImporter {
+        CsvImporter {
+            schema: schema,
+            delimiter: ',',
+            separator: ';',
+        }
+    }
+
+    /// Uses `delimiter` between fields instead of `,`.
+    pub fn delimiter(mut self, delimiter: char) -> CsvImporter {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Uses `separator` between the elements of lists, sets and maps instead of `;`.
+    pub fn separator(mut self, separator: char) -> CsvImporter {
+        self.separator = separator;
+        self
+    }
+
+    fn elems<T, F>(&self, text: &str, f: F) -> Result<Vec<T>, String> where F: Fn(&str) -> Result<T, String> {
+        text.split(self.separator).map(f).collect()
+    }
+
+    fn pairs<K, V, FK, FV>(&self, text: &str, fk: FK, fv: FV) -> Result<Vec<(K, V)>, String>
+        where FK: Fn(&str) -> Result<K, String>, FV: Fn(&str) -> Result<V, String> {
+        text.split(self.separator).map(|entry| match entry.find('=') {
+            Some(i) => Ok((try!(fk(&entry[..i])), try!(fv(&entry[i + 1..])))),
+            None => Err(format!("\"{}\" is not a key=value pair", entry)),
+        }).collect()
+    }
+
+    /// Converts the text of a cell to a value of type `dt`.
+    pub fn convert(&self, dt: Datatype, text: &str) -> Result<HyperValue, String> {
+        use schema::Primitive::*;
+        match dt {
+            Datatype::String => string(text).map(HyperString),
+            Datatype::Int => int(text).map(HyperInt),
+            Datatype::Float => float(text).map(HyperFloat),
+            Datatype::Document => serde_json::from_str(text).map(HyperDocument)
+                                             .map_err(|err| format!("invalid JSON: {}", err)),
+            Datatype::List(String) => self.elems(text, string).map(HyperListString),
+            Datatype::List(Int) => self.elems(text, int).map(HyperListInt),
+            Datatype::List(Float) => self.elems(text, float).map(HyperListFloat),
+            Datatype::Set(String) => self.elems(text, string).map(|s| HyperSetString(s.into_iter().collect())),
+            Datatype::Set(Int) => self.elems(text, int).map(|s| HyperSetInt(s.into_iter().collect())),
+            Datatype::Set(Float) => self.elems(text, float_key).map(|s| HyperSetFloat(s.into_iter().collect())),
+            Datatype::Map(String, String) =>
+                self.pairs(text, string, string).map(|m| HyperMapStringString(m.into_iter().collect())),
+            Datatype::Map(String, Int) =>
+                self.pairs(text, string, int).map(|m| HyperMapStringInt(m.into_iter().collect())),
+            Datatype::Map(String, Float) =>
+                self.pairs(text, string, float).map(|m| HyperMapStringFloat(m.into_iter().collect())),
+            Datatype::Map(Int, String) =>
+                self.pairs(text, int, string).map(|m| HyperMapIntString(m.into_iter().collect())),
+            Datatype::Map(Int, Int) =>
+                self.pairs(text, int, int).map(|m| HyperMapIntInt(m.into_iter().collect())),
+            Datatype::Map(Int, Float) =>
+                self.pairs(text, int, float).map(|m| HyperMapIntFloat(m.into_iter().collect())),
+            Datatype::Map(Float, String) =>
+                self.pairs(text, float_key, string).map(|m| HyperMapFloatString(m.into_iter().collect())),
+            Datatype::Map(Float, Int) =>
+                self.pairs(text, float_key, int).map(|m| HyperMapFloatInt(m.into_iter().collect())),
+            Datatype::Map(Float, Float) =>
+                self.pairs(text, float_key, float).map(|m| HyperMapFloatFloat(m.into_iter().collect())),
+        }
+    }
+
+    /// Checks the header row, returning the type of each column and which one is the key.
+    fn columns(&self, header: &[String]) -> Result<(Vec<Datatype>, usize), String> {
+        let mut types = Vec::new();
+        for (i, name) in header.iter().enumerate() {
+            let name = name.trim();
+            if header[..i].iter().any(|n| n.trim() == name) {
+                return Err(format!("column \"{}\" appears twice", name));
+            }
+            match self.schema.datatype(name) {
+                Some(dt) => types.push(dt),
+                None => return Err(format!("space \"{}\" has no attribute \"{}\"", self.schema.name, name)),
+            }
+        }
+        match header.iter().position(|n| n.trim() == self.schema.key) {
+            Some(key) => Ok((types, key)),
+            None => Err(format!("there is no column for the key \"{}\"", self.schema.key)),
+        }
+    }
+
+    fn row(&self, header: &[String], types: &[Datatype], key: usize, fields: Vec<String>)
+        -> Result<(HyperValue, HyperObject), String> {
+        if fields.len() != header.len() {
+            return Err(format!("expected {} fields, found {}", header.len(), fields.len()));
+        }
+        let mut obj = HyperObject::new();
+        let mut key_val = None;
+        for (i, text) in fields.iter().enumerate() {
+            if i == key {
+                if text.trim().is_empty() {
+                    return Err("the key is empty".to_string());
+                }
+                key_val = Some(try!(self.convert(types[i], text).map_err(|msg| format!("key: {}", msg))));
+            } else if !text.is_empty() {
+                let attr = header[i].trim();
+                let val = try!(self.convert(types[i], text).map_err(|msg| format!("\"{}\": {}", attr, msg)));
+                obj.map.insert(attr.to_string(), val);
+            }
+        }
+        Ok((key_val.unwrap(), obj))
+    }
+
+    /// Writes every row of `reader` to the space with `put`.
+    ///
+    /// Rows that cannot be converted or written are skipped and reported along with their line
+    /// numbers; only a bad header or a failure to read stops the import.
+    pub fn import<C, R>(&self, store: &mut C, reader: R) -> Result<CsvReport, CsvError>
+        where C: KeyValueStore, R: BufRead {
+        let mut records = Records {
+            lines: reader.lines(),
+            line: 0,
+            delimiter: self.delimiter,
+        };
+        let header = match try!(records.next()) {
+            Some((_, Ok(header))) => header,
+            Some((_, Err(msg))) => return Err(CsvError::Header(msg)),
+            None => return Err(CsvError::Header("the file is empty".to_string())),
+        };
+        let (types, key) = try!(self.columns(&header).map_err(CsvError::Header));
+
+        let mut report = CsvReport::default();
+        while let Some((line, fields)) = try!(records.next()) {
+            let res = fields.and_then(|fields| self.row(&header, &types, key, fields)).and_then(|(key, obj)| {
+                store.put(&self.schema.name[..], key, obj).map_err(|err| err.message)
+            });
+            match res {
+                Ok(()) => report.written += 1,
+                Err(msg) => report.errors.push(RowError {
+                    line: line,
+                    message: msg,
+                }),
+            }
+        }
+        Ok(report)
+    }
+}
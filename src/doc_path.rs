@@ -0,0 +1,166 @@
+use std::fmt::{Display, Formatter, Error};
+use std::str::FromStr;
+
+use serde_json::{Number, Value};
+
+use client_types::*;
+use client_types::HyperValue::*;
+
+/// A path to a field nested inside a document attribute, such as `profile.name`.
+///
+/// HyperDex accepts such paths wherever it accepts an attribute name, so a `DocPath` can be
+/// used in `HyperPredicate::new`, `get_partial`, and as the attribute of an object passed to
+/// the atomic operations, which then only touch that field of the document.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate hyperdex;
+/// # extern crate serde_json;
+/// # use std::str::FromStr;
+/// # use hyperdex::*;
+/// # use hyperdex::HyperPredicateType::*;
+/// # use hyperdex::testing::MemoryStore;
+/// # fn main() {
+/// # let mut store = MemoryStore::new();
+/// # store.add_space("space profiles key username attributes document profile").unwrap();
+/// # store.put("profiles", "derek", NewHyperObject!(
+/// #     "profile", serde_json::Value::from_str(r#"{"name": "Derek"}"#).unwrap(),
+/// # )).unwrap();
+/// let name = DocPath::new("profile").field("name");
+/// assert_eq!(store.count("profiles", vec!(HyperPredicate::new(&name, Equals, "Derek"))).unwrap(), 1);
+///
+/// let visits = DocPath::new("profile").field("stats").field("visits");
+/// store.atomic_add("profiles", "derek", NewHyperObject!(&visits, 1,)).unwrap();
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DocPath {
+    attr: String,
+    fields: Vec<String>,
+}
+
+fn check_segment(segment: &str) -> Result<(), String> {
+    if segment.is_empty() {
+        Err("a document path cannot have empty segments".to_string())
+    } else if segment.contains('.') {
+        Err(format!("\"{}\" contains a '.'", segment))
+    } else {
+        Ok(())
+    }
+}
+
+impl DocPath {
+    /// A path to the document attribute `attr` itself.
+    ///
+    /// Panics if `attr` is empty or contains a '.'.
+    pub fn new<A>(attr: A) -> DocPath where A: ToString {
+        let attr = attr.to_string();
+        check_segment(&attr).unwrap();
+        DocPath {
+            attr: attr,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Extends the path by one field.
+    ///
+    /// Panics if `field` is empty or contains a '.'.
+    pub fn field<F>(mut self, field: F) -> DocPath where F: ToString {
+        let field = field.to_string();
+        check_segment(&field).unwrap();
+        self.fields.push(field);
+        self
+    }
+
+    /// The document attribute the path starts from.
+    pub fn attr(&self) -> &str {
+        &self.attr
+    }
+
+    /// The fields followed inside the document.
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    /// Follows the path's fields inside `doc`.
+    pub fn lookup<'a>(&self, doc: &'a Value) -> Option<&'a Value> {
+        let mut cur = doc;
+        for field in self.fields.iter() {
+            cur = match *cur {
+                Value::Object(ref fields) => match fields.get(field) {
+                    Some(next) => next,
+                    None => return None,
+                },
+                _ => return None,
+            };
+        }
+        Some(cur)
+    }
+
+    /// Finds the value at this path in an object.
+    ///
+    /// Works both on whole objects, by looking inside the document attribute, and on the result
+    /// of a `get_partial` that asked for this path.
+    pub fn get(&self, obj: &HyperObject) -> Option<HyperValue> {
+        if let Some(val) = obj.map.get(&self.to_string()) {
+            return Some(val.clone());
+        }
+        match obj.map.get(&self.attr) {
+            Some(&HyperDocument(ref doc)) => self.lookup(doc).map(json_to_hyper),
+            _ => None,
+        }
+    }
+}
+
+impl Display for DocPath {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        try!(write!(f, "{}", self.attr));
+        for field in self.fields.iter() {
+            try!(write!(f, ".{}", field));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DocPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DocPath, String> {
+        let mut segments = s.split('.');
+        let attr = segments.next().unwrap();
+        try!(check_segment(attr));
+        let mut path = DocPath::new(attr);
+        for field in segments {
+            try!(check_segment(field));
+            path.fields.push(field.to_string());
+        }
+        Ok(path)
+    }
+}
+
+/// Converts a value found inside a document to the `HyperValue` HyperDex would compare it as.
+///
+/// Strings and numbers become `HyperString`, `HyperInt` and `HyperFloat`; anything else stays
+/// a document.
+pub fn json_to_hyper(val: &Value) -> HyperValue {
+    match *val {
+        Value::String(ref s) => HyperString(s.clone().into_bytes()),
+        Value::Number(ref n) => match n.as_i64() {
+            Some(i) => HyperInt(i),
+            None => HyperFloat(n.as_f64().unwrap()),
+        },
+        ref other => HyperDocument(other.clone()),
+    }
+}
+
+/// The inverse of `json_to_hyper`, for values that can be stored inside a document.
+pub fn hyper_to_json(val: HyperValue) -> Option<Value> {
+    match val {
+        HyperString(s) => String::from_utf8(s).ok().map(Value::String),
+        HyperInt(i) => Some(Value::from(i)),
+        HyperFloat(f) => Number::from_f64(f).map(Value::Number),
+        HyperDocument(doc) => Some(doc),
+        _ => None,
+    }
+}
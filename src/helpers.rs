@@ -1,7 +1,33 @@
 
 use eventual::*;
+use std::ops::Deref;
 use std::thread;
 
+/// A raw pointer that owns what it points to, so that it can be moved to the thread that uses it.
+/// Stands in for `std::ptr::Unique`, which the standard library no longer exposes.
+pub struct Unique<T: ?Sized>(*mut T);
+
+// The same impls `std::ptr::Unique` had, so that `Client` and `Admin` stay `Send` and `Sync` as
+// they were before the port. Sharing a `Client` between threads is sound because every call on its
+// handle is made while holding `InnerClient::mutex`, and the admin handle never leaves its thread.
+unsafe impl<T: Send + ?Sized> Send for Unique<T> {}
+unsafe impl<T: Sync + ?Sized> Sync for Unique<T> {}
+
+impl<T: ?Sized> Unique<T> {
+    /// Takes ownership of `ptr`, which must stay valid for as long as the `Unique` is used.
+    pub unsafe fn new(ptr: *mut T) -> Unique<T> {
+        Unique(ptr)
+    }
+}
+
+impl<T: ?Sized> Deref for Unique<T> {
+    type Target = *mut T;
+
+    fn deref(&self) -> &*mut T {
+        &self.0
+    }
+}
+
 pub trait FutureHelpers {
     type Value: Send + 'static;
     type Error: Send + 'static;
@@ -137,6 +137,8 @@ extern "C" {
                                timeout: ::libc::c_int,
                                status: *mut Enum_hyperdex_admin_returncode) ->
      int64_t;
+    pub fn hyperdex_admin_poll_fd(admin: *mut Struct_hyperdex_admin) ->
+     ::libc::c_int;
     pub fn hyperdex_admin_raw_backup(host: *const ::libc::c_char,
                                      port: uint16_t,
                                      name: *const ::libc::c_char,
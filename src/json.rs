@@ -0,0 +1,215 @@
+//! The canonical JSON form of objects and values, used wherever they are written as text.
+//!
+//! Every value is a JSON object with a single member, named after the value's type as it is
+//! written in a space description, holding the value itself:
+//!
+//! ```text
+//! {"string": "Derek"}            {"int": 20}                {"float": 1.5}
+//! {"bytes": "3q2+7w=="}          {"document": {"name": "Derek"}}
+//! {"list(int)": [1, 2]}          {"set(float)": [0.5, 1.0]}
+//! {"map(int, string)": [[1, "one"], [2, "two"]]}
+//! ```
+//!
+//! * strings that are not UTF-8 are tagged `bytes` instead and hold their base64 encoding;
+//!   string elements of containers are encoded the same way, as `{"bytes": ...}`;
+//! * floats are written in the shortest form that reads back as the same bits, and floats
+//!   that JSON cannot hold as `"NaN"`, `"inf"` and `"-inf"`;
+//! * set elements are sorted, and maps are lists of `[key, value]` pairs sorted by key.
+//!
+//! An object maps each attribute name to its value.  When reading, a bare JSON string or
+//! number is also accepted in place of a tagged string, int or float.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use base64;
+use serde_json::{Map, Number, Value};
+
+use client_types::*;
+use client_types::HyperValue::*;
+
+fn tagged(tag: &str, val: Value) -> Value {
+    let mut map = Map::new();
+    map.insert(tag.to_string(), val);
+    Value::Object(map)
+}
+
+fn string(s: &Vec<u8>) -> Value {
+    match String::from_utf8(s.clone()) {
+        Ok(s) => Value::String(s),
+        Err(_) => tagged("bytes", Value::String(base64::encode(s))),
+    }
+}
+
+fn int(i: &i64) -> Value {
+    Value::from(*i)
+}
+
+fn float(f: &f64) -> Value {
+    match Number::from_f64(*f) {
+        Some(n) => Value::Number(n),
+        None if f.is_nan() => Value::String("NaN".to_string()),
+        None if *f > 0.0 => Value::String("inf".to_string()),
+        None => Value::String("-inf".to_string()),
+    }
+}
+
+fn float_key(f: &F64) -> Value {
+    float(&f.0)
+}
+
+fn list<'a, I, T, F>(tag: &str, elems: I, f: F) -> Value
+    where I: Iterator<Item=&'a T>, T: 'a, F: Fn(&T) -> Value {
+    tagged(tag, Value::Array(elems.map(f).collect()))
+}
+
+fn map<K, V, FK, FV>(tag: &str, map: &HashMap<K, V>, fk: FK, fv: FV) -> Value
+    where K: Ord + Eq + Hash, FK: Fn(&K) -> Value, FV: Fn(&V) -> Value {
+    let mut entries: Vec<(&K, &V)> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    tagged(tag, Value::Array(entries.into_iter().map(|(k, v)| Value::Array(vec!(fk(k), fv(v)))).collect()))
+}
+
+fn parse_string(json: &Value) -> Result<Vec<u8>, String> {
+    match *json {
+        Value::String(ref s) => Ok(s.clone().into_bytes()),
+        Value::Object(ref m) if m.len() == 1 && m.contains_key("bytes") => match m["bytes"] {
+            Value::String(ref s) => base64::decode(s).map_err(|err| format!("invalid base64: {}", err)),
+            _ => Err(format!("expected base64 text, found {}", m["bytes"])),
+        },
+        _ => Err(format!("expected a string, found {}", json)),
+    }
+}
+
+fn parse_int(json: &Value) -> Result<i64, String> {
+    json.as_i64().ok_or(format!("expected an int, found {}", json))
+}
+
+fn parse_float(json: &Value) -> Result<f64, String> {
+    match *json {
+        Value::Number(ref n) => Ok(n.as_f64().unwrap()),
+        Value::String(ref s) if s == "NaN" => Ok(::std::f64::NAN),
+        Value::String(ref s) if s == "inf" => Ok(::std::f64::INFINITY),
+        Value::String(ref s) if s == "-inf" => Ok(::std::f64::NEG_INFINITY),
+        _ => Err(format!("expected a float, found {}", json)),
+    }
+}
+
+fn parse_float_key(json: &Value) -> Result<F64, String> {
+    parse_float(json).map(F64)
+}
+
+fn parse_elems<T, F>(json: &Value, f: F) -> Result<Vec<T>, String> where F: Fn(&Value) -> Result<T, String> {
+    match *json {
+        Value::Array(ref elems) => elems.iter().map(f).collect(),
+        _ => Err(format!("expected a list, found {}", json)),
+    }
+}
+
+fn parse_pairs<K, V, FK, FV>(json: &Value, fk: FK, fv: FV) -> Result<Vec<(K, V)>, String>
+    where FK: Fn(&Value) -> Result<K, String>, FV: Fn(&Value) -> Result<V, String> {
+    parse_elems(json, |pair| match *pair {
+        Value::Array(ref kv) if kv.len() == 2 => Ok((try!(fk(&kv[0])), try!(fv(&kv[1])))),
+        _ => Err(format!("expected a [key, value] pair, found {}", pair)),
+    })
+}
+
+impl HyperValue {
+    /// The canonical JSON form of the value.
+    pub fn to_json(&self) -> Value {
+        match *self {
+            HyperString(ref s) => match string(s) {
+                Value::String(s) => tagged("string", Value::String(s)),
+                bytes => bytes,
+            },
+            HyperInt(i) => tagged("int", int(&i)),
+            HyperFloat(f) => tagged("float", float(&f)),
+            HyperListString(ref l) => list("list(string)", l.iter(), string),
+            HyperListInt(ref l) => list("list(int)", l.iter(), int),
+            HyperListFloat(ref l) => list("list(float)", l.iter(), float),
+            HyperSetString(ref s) => list("set(string)", s.iter(), string),
+            HyperSetInt(ref s) => list("set(int)", s.iter(), int),
+            HyperSetFloat(ref s) => list("set(float)", s.iter(), float_key),
+            HyperMapStringString(ref m) => map("map(string, string)", m, string, string),
+            HyperMapStringInt(ref m) => map("map(string, int)", m, string, int),
+            HyperMapStringFloat(ref m) => map("map(string, float)", m, string, float),
+            HyperMapIntString(ref m) => map("map(int, string)", m, int, string),
+            HyperMapIntInt(ref m) => map("map(int, int)", m, int, int),
+            HyperMapIntFloat(ref m) => map("map(int, float)", m, int, float),
+            HyperMapFloatString(ref m) => map("map(float, string)", m, float_key, string),
+            HyperMapFloatInt(ref m) => map("map(float, int)", m, float_key, int),
+            HyperMapFloatFloat(ref m) => map("map(float, float)", m, float_key, float),
+            HyperDocument(ref doc) => tagged("document", doc.clone()),
+        }
+    }
+
+    /// Reads a value back from its canonical JSON form.
+    pub fn from_json(json: &Value) -> Result<HyperValue, String> {
+        let (tag, val) = match *json {
+            Value::String(ref s) => return Ok(HyperString(s.clone().into_bytes())),
+            Value::Number(ref n) => return Ok(match n.as_i64() {
+                Some(i) => HyperInt(i),
+                None => HyperFloat(n.as_f64().unwrap()),
+            }),
+            Value::Object(ref m) if m.len() == 1 => m.iter().next().unwrap(),
+            _ => return Err(format!("expected a value tagged with its type, found {}", json)),
+        };
+        match &tag[..] {
+            "string" => parse_string(val).map(HyperString),
+            "bytes" => parse_string(json).map(HyperString),
+            "int" => parse_int(val).map(HyperInt),
+            "float" => parse_float(val).map(HyperFloat),
+            "list(string)" => parse_elems(val, parse_string).map(HyperListString),
+            "list(int)" => parse_elems(val, parse_int).map(HyperListInt),
+            "list(float)" => parse_elems(val, parse_float).map(HyperListFloat),
+            "set(string)" => parse_elems(val, parse_string).map(|s| HyperSetString(s.into_iter().collect())),
+            "set(int)" => parse_elems(val, parse_int).map(|s| HyperSetInt(s.into_iter().collect())),
+            "set(float)" => parse_elems(val, parse_float_key).map(|s| HyperSetFloat(s.into_iter().collect())),
+            "map(string, string)" =>
+                parse_pairs(val, parse_string, parse_string).map(|m| HyperMapStringString(m.into_iter().collect())),
+            "map(string, int)" =>
+                parse_pairs(val, parse_string, parse_int).map(|m| HyperMapStringInt(m.into_iter().collect())),
+            "map(string, float)" =>
+                parse_pairs(val, parse_string, parse_float).map(|m| HyperMapStringFloat(m.into_iter().collect())),
+            "map(int, string)" =>
+                parse_pairs(val, parse_int, parse_string).map(|m| HyperMapIntString(m.into_iter().collect())),
+            "map(int, int)" =>
+                parse_pairs(val, parse_int, parse_int).map(|m| HyperMapIntInt(m.into_iter().collect())),
+            "map(int, float)" =>
+                parse_pairs(val, parse_int, parse_float).map(|m| HyperMapIntFloat(m.into_iter().collect())),
+            "map(float, string)" =>
+                parse_pairs(val, parse_float_key, parse_string).map(|m| HyperMapFloatString(m.into_iter().collect())),
+            "map(float, int)" =>
+                parse_pairs(val, parse_float_key, parse_int).map(|m| HyperMapFloatInt(m.into_iter().collect())),
+            "map(float, float)" =>
+                parse_pairs(val, parse_float_key, parse_float).map(|m| HyperMapFloatFloat(m.into_iter().collect())),
+            "document" => Ok(HyperDocument(val.clone())),
+            other => Err(format!("unknown type \"{}\"", other)),
+        }
+    }
+}
+
+impl HyperObject {
+    /// The canonical JSON form of the object, mapping each attribute to its value.
+    pub fn to_json(&self) -> Value {
+        let mut map = Map::new();
+        for (attr, val) in self.map.iter() {
+            map.insert(attr.clone(), val.to_json());
+        }
+        Value::Object(map)
+    }
+
+    /// Reads an object back from its canonical JSON form.
+    pub fn from_json(json: &Value) -> Result<HyperObject, String> {
+        let attrs = match *json {
+            Value::Object(ref attrs) => attrs,
+            _ => return Err(format!("expected an object, found {}", json)),
+        };
+        let mut obj = HyperObject::new();
+        for (attr, val) in attrs.iter() {
+            let val = try!(HyperValue::from_json(val).map_err(|err| format!("\"{}\": {}", attr, err)));
+            obj.map.insert(attr.clone(), val);
+        }
+        Ok(obj)
+    }
+}
@@ -1,22 +1,44 @@
 #![feature(concat_idents)]
 #![feature(box_syntax)]
-#![feature(unique)]
-#![feature(ip_addr)]
-#![feature(convert)]
-#![feature(mpsc_select)]
 
+extern crate base64;
 extern crate libc;
-extern crate rustc_serialize;
+extern crate serde;
+extern crate serde_json;
 extern crate eventual;
+extern crate regex;
+#[macro_use]
+extern crate tracing;
 
 pub use common::HyperError;
-pub use client::{Client};
-pub use client_types::{F64, HyperMapAttribute, HyperObject, HyperPredicate, HyperObjectKeyError, HyperPredicateType, HyperValue};
+pub use client::{Batch, Client, LocalClient};
+pub use client_types::{Document, F64, HyperMapAttribute, HyperKey, HyperObject, HyperPredicate, HyperObjectKeyError, HyperPredicateType, HyperValue};
 pub use admin::Admin;
+pub use cache::{CacheStats, CachedClient, Clock, SystemClock};
+pub use borrowed::{BorrowedObject, BorrowedSearch};
+pub use doc_path::DocPath;
+pub use predicate::{attr, Attr, Element, Predicates};
+pub use store::{AsyncKeyValueStore, KeyValueStore, UpdateGuard, UpdateOptions};
 
 mod helpers;
 mod client;
 mod admin;
+mod reactor;
+mod borrowed;
+mod store;
+mod cache;
+mod doc_path;
+mod predicate;
+
+pub mod backup;
+pub mod codec;
+pub mod csv;
+pub mod json;
+pub mod lock;
+pub mod metrics;
+pub mod query;
+pub mod schema;
+pub mod testing;
 
 mod hyperdex;
 mod hyperdex_client;
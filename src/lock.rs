@@ -0,0 +1,259 @@
+//! Locks with a lease, kept as objects of a space so that processes on different machines can
+//! coordinate through the cluster.
+//!
+//! Each lock is an object keyed by the name of the lock, holding the id of its owner and the
+//! time its lease runs out, in milliseconds since the Unix epoch:
+//!
+//! ```text
+//! space locks
+//! key name
+//! attributes owner, int expires
+//! ```
+//!
+//! A lock is taken with `put_if_not_exist`, and every later change is a conditional write on
+//! its owner, so two clients can never both believe they hold it.  An owner that stops renewing
+//! its lease loses the lock once the lease runs out: the next `acquire` takes it over, and
+//! `recover_expired` deletes every such lock in one go.  Expiry is judged by the clock of the
+//! client that looks, so leases should be long compared to the clock skew between machines.
+//! `Lock::with_clock` and `recover_expired_with_clock` read another `Clock` instead of the
+//! system's, so that a test can let leases run out without waiting for them to.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate hyperdex;
+//! # use std::time::Duration;
+//! # use hyperdex::lock::{Lock, LockError};
+//! # use hyperdex::testing::MemoryStore;
+//! # fn main() {
+//! # let mut store = MemoryStore::new();
+//! # store.add_space("space locks key name attributes owner, int expires").unwrap();
+//! let lock = Lock::new("locks", "nightly-report", "worker-7", Duration::from_secs(30));
+//! match lock.acquire(&mut store) {
+//!     Ok(_) => {
+//!         // ... write the report, calling lock.renew() if it takes longer than the lease ...
+//!         lock.release(&mut store).unwrap();
+//!     },
+//!     Err(LockError::Held { owner, .. }) => println!("{} is on it", owner),
+//!     Err(err) => panic!("{}", err),
+//! }
+//! # }
+//! ```
+
+use std::fmt::{Debug, Display, Formatter, Error};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use cache::{Clock, SystemClock};
+use common::*;
+use client_types::*;
+use client_types::HyperPredicateType::*;
+use hyperdex_client::*;
+use store::KeyValueStore;
+
+/// The attribute holding the id of the owner.
+pub const OWNER: &'static str = "owner";
+
+/// The int attribute holding the end of the lease, in milliseconds since the Unix epoch.
+pub const EXPIRES: &'static str = "expires";
+
+/// Why a lock could not be taken, renewed or released.
+#[derive(Debug)]
+pub enum LockError {
+    /// Someone else holds the lock until `expires`.
+    Held { owner: String, expires: i64 },
+    /// The lock is not ours anymore: it was released, or its lease ran out and another owner
+    /// took it over.
+    Lost,
+    /// The store failed.
+    Store(HyperError),
+}
+
+impl Display for LockError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            LockError::Held { ref owner, expires } => write!(f, "the lock is held by {} until {}", owner, expires),
+            LockError::Lost => write!(f, "the lock is no longer held"),
+            LockError::Store(ref err) => write!(f, "{}", err.message),
+        }
+    }
+}
+
+impl From<HyperError> for LockError {
+    fn from(err: HyperError) -> LockError {
+        LockError::Store(err)
+    }
+}
+
+/// A lease on a lock.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lease {
+    pub owner: String,
+    /// When the lease runs out, in milliseconds since the Unix epoch.
+    pub expires: i64,
+}
+
+/// The current time in milliseconds since the Unix epoch.
+pub fn now() -> i64 {
+    timestamp(SystemTime::now())
+}
+
+/// `time` in milliseconds since the Unix epoch.  A clock set before the epoch reads as the
+/// epoch itself, which makes every lease look expired instead of failing.
+pub fn timestamp(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map(millis).unwrap_or(0)
+}
+
+fn millis(d: Duration) -> i64 {
+    d.as_secs() as i64 * 1000 + (d.subsec_nanos() / 1000000) as i64
+}
+
+/// A lock, as seen by one owner.
+#[derive(Clone)]
+pub struct Lock {
+    space: String,
+    name: String,
+    owner: String,
+    ttl: Duration,
+    clock: Arc<Clock + Send + Sync>,
+}
+
+impl Debug for Lock {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        f.debug_struct("Lock")
+         .field("space", &self.space)
+         .field("name", &self.name)
+         .field("owner", &self.owner)
+         .field("ttl", &self.ttl)
+         .finish()
+    }
+}
+
+impl Lock {
+    /// The lock `name` of `space`, taken by `owner` for leases of `ttl`.  Owners must have ids
+    /// that no other process uses.
+    pub fn new<S, N, O>(space: S, name: N, owner: O, ttl: Duration) -> Lock
+        where S: ToString, N: ToString, O: ToString {
+        Lock::with_clock(space, name, owner, ttl, SystemClock)
+    }
+
+    /// Like `new`, but reads the time from `clock`.
+    pub fn with_clock<S, N, O, T>(space: S, name: N, owner: O, ttl: Duration, clock: T) -> Lock
+        where S: ToString, N: ToString, O: ToString, T: Clock + Send + Sync + 'static {
+        Lock {
+            space: space.to_string(),
+            name: name.to_string(),
+            owner: owner.to_string(),
+            ttl: ttl,
+            clock: Arc::new(clock),
+        }
+    }
+
+    fn now(&self) -> i64 {
+        timestamp(self.clock.system_time())
+    }
+
+    fn lease(&self) -> (Lease, HyperObject) {
+        let lease = Lease {
+            owner: self.owner.clone(),
+            expires: self.now() + millis(self.ttl),
+        };
+        let mut obj = HyperObject::new();
+        obj.insert(OWNER, &lease.owner[..]);
+        obj.insert(EXPIRES, lease.expires);
+        (lease, obj)
+    }
+
+    /// Takes the lock, or extends our lease if we already hold it.  A lock whose lease ran out
+    /// is taken over.
+    pub fn acquire<C: KeyValueStore>(&self, store: &mut C) -> Result<Lease, LockError> {
+        loop {
+            let (lease, obj) = self.lease();
+            match store.put_if_not_exist(&self.space[..], &self.name[..], obj) {
+                Ok(()) => return Ok(lease),
+                Err(ref err) if err.status == HYPERDEX_CLIENT_CMPFAIL => (),
+                Err(err) => return Err(LockError::Store(err)),
+            }
+
+            let current = match store.get(&self.space[..], &self.name[..]) {
+                Ok(obj) => obj,
+                // Released in the meantime, so try again
+                Err(ref err) if err.status == HYPERDEX_CLIENT_NOTFOUND => continue,
+                Err(err) => return Err(LockError::Store(err)),
+            };
+            let owner: String = current.get(OWNER).unwrap_or(String::new());
+            let expires: i64 = current.get(EXPIRES).unwrap_or(0);
+            if owner == self.owner {
+                return self.renew(store);
+            }
+            if expires > self.now() {
+                return Err(LockError::Held { owner: owner, expires: expires });
+            }
+
+            // The lease ran out; take the lock over unless someone beat us to it.
+            let (lease, obj) = self.lease();
+            let checks = vec!(HyperPredicate::new(OWNER, Equals, &owner[..]),
+                              HyperPredicate::new(EXPIRES, Equals, expires));
+            match store.cond_put(&self.space[..], &self.name[..], checks, obj) {
+                Ok(()) => return Ok(lease),
+                Err(ref err) if err.status == HYPERDEX_CLIENT_CMPFAIL ||
+                                err.status == HYPERDEX_CLIENT_NOTFOUND => continue,
+                Err(err) => return Err(LockError::Store(err)),
+            }
+        }
+    }
+
+    /// Extends our lease by the lock's `ttl` from now.  Fails with `LockError::Lost` if the
+    /// lock is not ours.
+    pub fn renew<C: KeyValueStore>(&self, store: &mut C) -> Result<Lease, LockError> {
+        let (lease, obj) = self.lease();
+        let checks = vec!(HyperPredicate::new(OWNER, Equals, &self.owner[..]));
+        match store.cond_put(&self.space[..], &self.name[..], checks, obj) {
+            Ok(()) => Ok(lease),
+            Err(ref err) if err.status == HYPERDEX_CLIENT_CMPFAIL ||
+                            err.status == HYPERDEX_CLIENT_NOTFOUND => Err(LockError::Lost),
+            Err(err) => Err(LockError::Store(err)),
+        }
+    }
+
+    /// Gives the lock up.  Fails with `LockError::Lost` if the lock is not ours.
+    pub fn release<C: KeyValueStore>(&self, store: &mut C) -> Result<(), LockError> {
+        let checks = vec!(HyperPredicate::new(OWNER, Equals, &self.owner[..]));
+        match store.cond_del(&self.space[..], &self.name[..], checks) {
+            Ok(()) => Ok(()),
+            Err(ref err) if err.status == HYPERDEX_CLIENT_CMPFAIL ||
+                            err.status == HYPERDEX_CLIENT_NOTFOUND => Err(LockError::Lost),
+            Err(err) => Err(LockError::Store(err)),
+        }
+    }
+}
+
+/// Deletes every lock of `space` whose lease ran out, and returns how many there were.  A lock
+/// that is renewed or taken over while this runs is left alone.  A `Client` must have been
+/// given the schema of `space` with `set_schema`.
+pub fn recover_expired<C: KeyValueStore>(store: &mut C, space: &str) -> Result<u64, HyperError> {
+    recover_expired_with_clock(store, space, &SystemClock)
+}
+
+/// Like `recover_expired`, but judges expiry by `clock`.
+pub fn recover_expired_with_clock<C: KeyValueStore>(store: &mut C, space: &str, clock: &Clock)
+    -> Result<u64, HyperError> {
+    let checks = vec!(HyperPredicate::new(EXPIRES, LessEqual, timestamp(clock.system_time())));
+    let mut expired = Vec::new();
+    for res in store.search_keyed(space, checks).iter() {
+        let (key, obj) = try!(res);
+        let expires: i64 = obj.get(EXPIRES).unwrap_or(0);
+        expired.push((key, expires));
+    }
+
+    let mut count = 0;
+    for (key, expires) in expired.into_iter() {
+        match store.cond_del(space, key, vec!(HyperPredicate::new(EXPIRES, Equals, expires))) {
+            Ok(()) => count += 1,
+            Err(ref err) if err.status == HYPERDEX_CLIENT_CMPFAIL ||
+                            err.status == HYPERDEX_CLIENT_NOTFOUND => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(count)
+}
@@ -0,0 +1,142 @@
+//! Measurements of the operations a client makes.
+//!
+//! Every operation of `Client` and `LocalClient`, searches included, has a `tracing` span named
+//! `hyperdex` that carries the space, the name of the operation, a hash of the key (never the
+//! key itself) and, once the operation completes, its status.  The span is entered while the
+//! operation is issued and again when it completes.  The same operations
+//! are reported to the `Metrics` the client was created with by `with_metrics`, along with the
+//! number of operations each inner client has waiting on the server.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let recorder = Arc::new(Recorder::new());
+//! let mut client = Client::with_metrics(coordinator, recorder.clone()).unwrap();
+//! client.put("contacts", "derek", NewHyperObject!("age", 20,)).unwrap();
+//! let stats = recorder.stats("contacts", "put").unwrap();
+//! println!("{} puts, p99 {:?}", stats.count, stats.latency.quantile(0.99));
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use common::HyperError;
+
+/// Receives the measurements of a client.  Every method does nothing unless overridden.
+///
+/// Methods are called on whichever thread completes the operation, usually the client's
+/// event loop, so they should be quick.
+pub trait Metrics: Send + Sync {
+    /// An operation on `space` completed, successfully or not, `latency` after it was issued.
+    fn record_latency(&self, _op: &'static str, _space: &str, _latency: Duration) {}
+
+    /// An operation on `space` failed.
+    fn record_error(&self, _op: &'static str, _space: &str, _err: &HyperError) {}
+
+    /// Inner client `client` has `pending` operations waiting on the server.
+    fn set_pending(&self, _client: usize, _pending: usize) {}
+}
+
+/// Discards every measurement.  This is what clients start with.
+pub struct NoMetrics;
+
+impl Metrics for NoMetrics {}
+
+/// How many latencies fell into each of a series of buckets, the first of which holds
+/// everything up to a microsecond and each of the others twice as much as the one before.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram::default()
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let micros = latency.as_secs() * 1000000 + (latency.subsec_nanos() / 1000) as u64;
+        let mut bucket = 0;
+        while (1 << bucket) < micros {
+            bucket += 1;
+        }
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+    }
+
+    /// The number of latencies recorded.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().fold(0, |sum, n| sum + n)
+    }
+
+    /// An upper bound on the latency below which a fraction `q` of the recorded ones fall, or
+    /// zero if there are none.
+    pub fn quantile(&self, q: f64) -> Duration {
+        let wanted = (q * self.count() as f64).ceil() as u64;
+        let mut seen = 0;
+        for (bucket, n) in self.buckets.iter().enumerate() {
+            seen += *n;
+            if seen >= wanted && seen > 0 {
+                let micros = 1u64 << bucket;
+                return Duration::new(micros / 1000000, (micros % 1000000) as u32 * 1000);
+            }
+        }
+        Duration::new(0, 0)
+    }
+}
+
+/// What a `Recorder` knows about one operation on one space.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OpStats {
+    /// The operations that completed, failed ones included.
+    pub count: u64,
+    pub errors: u64,
+    pub latency: Histogram,
+}
+
+/// Keeps the measurements in memory, for tests or for exporting them on a schedule.
+pub struct Recorder {
+    ops: Mutex<HashMap<(String, &'static str), OpStats>>,
+    pending: Mutex<HashMap<usize, usize>>,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder {
+            ops: Mutex::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The measurements of `op` on `space`, if there was one.
+    pub fn stats(&self, space: &str, op: &str) -> Option<OpStats> {
+        let ops = self.ops.lock().unwrap();
+        ops.iter().find(|&(k, _)| k.0 == space && k.1 == op).map(|(_, stats)| stats.clone())
+    }
+
+    /// The last reported number of pending operations of inner client `client`.
+    pub fn pending(&self, client: usize) -> usize {
+        self.pending.lock().unwrap().get(&client).cloned().unwrap_or(0)
+    }
+}
+
+impl Metrics for Recorder {
+    fn record_latency(&self, op: &'static str, space: &str, latency: Duration) {
+        let mut ops = self.ops.lock().unwrap();
+        let stats = ops.entry((space.to_string(), op)).or_insert(OpStats::default());
+        stats.count += 1;
+        stats.latency.record(latency);
+    }
+
+    fn record_error(&self, op: &'static str, space: &str, _err: &HyperError) {
+        let mut ops = self.ops.lock().unwrap();
+        ops.entry((space.to_string(), op)).or_insert(OpStats::default()).errors += 1;
+    }
+
+    fn set_pending(&self, client: usize, pending: usize) {
+        self.pending.lock().unwrap().insert(client, pending);
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::HashSet;
+
+use codec;
+use common::*;
+use client_types::*;
+use client_types::HyperPredicateType::*;
+use hyperdex_client::*;
+use schema::SpaceSchema;
+use store::KeyValueStore;
+
+/// Starts a predicate on an attribute.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate hyperdex;
+/// # use hyperdex::*;
+/// # use hyperdex::testing::MemoryStore;
+/// # fn main() {
+/// # let mut store = MemoryStore::new();
+/// # store.add_space("space contacts key username attributes first, int age").unwrap();
+/// # store.put("contacts", "derek", NewHyperObject!("first", "Derek", "age", 20,)).unwrap();
+/// # store.put("contacts", "robert", NewHyperObject!("first", "Robert", "age", 30,)).unwrap();
+/// let checks = attr("age").le(25).and(attr("first").regex("^D")).build().unwrap();
+/// assert_eq!(store.count("contacts", checks).unwrap(), 1);
+///
+/// let young_or_robert = attr("age").lt(25).or(attr("first").eq("Robert"));
+/// assert_eq!(young_or_robert.search_keyed(&mut store, "contacts").unwrap().len(), 2);
+/// # }
+/// ```
+pub fn attr<A>(name: A) -> Attr where A: ToString {
+    Attr {
+        name: name.to_string(),
+    }
+}
+
+/// An attribute that a predicate is about to be applied to.
+pub struct Attr {
+    name: String,
+}
+
+impl Attr {
+    fn check<T>(self, predicate: HyperPredicateType, value: T) -> Predicates where T: ToHyperValue {
+        Predicates {
+            alternatives: vec!(vec!(HyperPredicate::new(self.name, predicate, value))),
+        }
+    }
+
+    pub fn eq<T>(self, value: T) -> Predicates where T: ToHyperValue {
+        self.check(Equals, value)
+    }
+
+    pub fn lt<T>(self, value: T) -> Predicates where T: ToHyperValue {
+        self.check(LessThan, value)
+    }
+
+    pub fn le<T>(self, value: T) -> Predicates where T: ToHyperValue {
+        self.check(LessEqual, value)
+    }
+
+    pub fn ge<T>(self, value: T) -> Predicates where T: ToHyperValue {
+        self.check(GreaterEqual, value)
+    }
+
+    pub fn gt<T>(self, value: T) -> Predicates where T: ToHyperValue {
+        self.check(GreaterThan, value)
+    }
+
+    /// Matches `low <= attr <= high`.
+    pub fn between<T>(self, low: T, high: T) -> Predicates where T: ToHyperValue {
+        let name = self.name.clone();
+        self.ge(low).and(attr(name).le(high))
+    }
+
+    pub fn regex<R>(self, regex: R) -> Predicates where R: ToString {
+        self.check(Regex, regex.to_string())
+    }
+
+    pub fn length_eq(self, len: i64) -> Predicates {
+        self.check(LengthEquals, len)
+    }
+
+    pub fn length_le(self, len: i64) -> Predicates {
+        self.check(LengthLessEqual, len)
+    }
+
+    pub fn length_ge(self, len: i64) -> Predicates {
+        self.check(LengthGreaterEqual, len)
+    }
+
+    /// Matches lists and sets holding `elem`, and maps with `elem` as a key.
+    pub fn contains<T>(self, elem: T) -> Predicates where T: Element {
+        self.check(Contains, elem)
+    }
+
+    /// Matches lists and sets holding an element less than `elem`, and maps with such a key.
+    pub fn contains_less_than<T>(self, elem: T) -> Predicates where T: Element {
+        self.check(ContainsLessThan, elem)
+    }
+}
+
+/// The types that a container can hold: strings, ints and floats.
+///
+/// Elements are encoded exactly like attributes of the same type, so `contains("a")` matches a
+/// `list(string)`, `set(string)` or `map(string, _)`, `contains(1)` their int counterparts and
+/// `contains(1.0)` the float ones.
+pub trait Element: ToHyperValue {}
+
+impl<'a> Element for &'a str {}
+impl Element for String {}
+impl Element for Vec<u8> {}
+impl Element for i64 {}
+impl Element for f64 {}
+
+/// Predicates joined with `and` and `or`.
+///
+/// A HyperDex search only takes a conjunction, so the predicates are kept as alternatives,
+/// each of them a conjunction, and `and` distributes over `or`.  A single conjunction is handed
+/// to the client by `build`; alternatives take one search each, which `search_keyed` runs.
+pub struct Predicates {
+    alternatives: Vec<Vec<HyperPredicate>>,
+}
+
+impl Predicates {
+    /// Requires both `self` and `other` to hold.
+    pub fn and(self, other: Predicates) -> Predicates {
+        let mut alternatives = Vec::with_capacity(self.alternatives.len() * other.alternatives.len());
+        for left in self.alternatives.iter() {
+            for right in other.alternatives.iter() {
+                let mut both = left.clone();
+                both.extend(right.iter().cloned());
+                alternatives.push(both);
+            }
+        }
+        Predicates {
+            alternatives: alternatives,
+        }
+    }
+
+    /// Requires `self` or `other` to hold.
+    pub fn or(mut self, other: Predicates) -> Predicates {
+        self.alternatives.extend(other.alternatives.into_iter());
+        self
+    }
+
+    /// Validates the predicates and returns them in the form the client accepts.  Fails with
+    /// `HYPERDEX_CLIENT_WRONGTYPE` if they were joined with `or`, since no single search can
+    /// express that; use `build_any` or `search_keyed` instead.
+    pub fn build(self) -> Result<Vec<HyperPredicate>, HyperError> {
+        let mut alternatives = try!(self.build_any());
+        if alternatives.len() != 1 {
+            return Err(HyperError {
+                status: HYPERDEX_CLIENT_WRONGTYPE,
+                message: format!("the predicates have {} alternatives, which take a search each",
+                                 alternatives.len()),
+                location: String::new(),
+            });
+        }
+        Ok(alternatives.pop().unwrap())
+    }
+
+    /// Like `build`, but also checks the predicates against the schema of the space they are
+    /// meant for, so that e.g. a length predicate on an int attribute fails here.
+    pub fn build_for(self, schema: &SpaceSchema) -> Result<Vec<HyperPredicate>, HyperError> {
+        let checks = try!(self.build());
+        try!(check_predicates(&checks, schema));
+        Ok(checks)
+    }
+
+    /// Validates every alternative and returns them, each in the form the client accepts.
+    pub fn build_any(self) -> Result<Vec<Vec<HyperPredicate>>, HyperError> {
+        for checks in self.alternatives.iter() {
+            try!(validate_predicates(checks));
+        }
+        Ok(self.alternatives)
+    }
+
+    /// Searches `space` once per alternative and returns every object that matches any of
+    /// them, each once, keyed like `KeyValueStore::search_keyed`.
+    pub fn search_keyed<C>(self, store: &mut C, space: &str) -> Result<Vec<(HyperKey, HyperObject)>, HyperError>
+        where C: KeyValueStore {
+        let mut seen = HashSet::new();
+        let mut found = Vec::new();
+        for checks in try!(self.build_any()).into_iter() {
+            for res in store.search_keyed(space, checks).iter() {
+                let (key, obj) = try!(res);
+                if seen.insert(codec::encode(&key.clone().to_hyper())) {
+                    found.push((key, obj));
+                }
+            }
+        }
+        Ok(found)
+    }
+}
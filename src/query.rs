@@ -0,0 +1,313 @@
+//! A small text language for searches, for when predicates are typed by people.
+//!
+//! A query is a list of conditions joined by `AND`, each comparing an attribute with a value:
+//!
+//! ```text
+//! age <= 25 AND first =~ "^D" AND length(tags) >= 2
+//! ```
+//!
+//! * attributes are identifiers, optionally followed by `.field`s to reach into documents;
+//! * values are double-quoted strings (with `\"`, `\\`, `\n` and `\t` escapes), ints and floats;
+//! * `=` (or `==`), `<`, `<=`, `>` and `>=` compare the attribute with the value;
+//! * `=~` matches the attribute against a regular expression;
+//! * `contains` and `contains <` test the elements of lists and sets and the keys of maps;
+//! * `length(attr)` followed by `=`, `<=` or `>=` compares the length of the attribute.
+//!
+//! Keywords are case insensitive, and the empty query matches every object.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate hyperdex;
+//! # use hyperdex::{query, HyperObject};
+//! # use hyperdex::testing::MemoryStore;
+//! # fn main() {
+//! # let mut store = MemoryStore::new();
+//! # store.add_space("space contacts key username attributes first, int age").unwrap();
+//! # store.put("contacts", "derek", NewHyperObject!("first", "Derek", "age", 20,)).unwrap();
+//! let checks = query::parse("age <= 25 AND first =~ \"^D\"").unwrap();
+//! assert_eq!(store.count("contacts", checks).unwrap(), 1);
+//!
+//! let err = query::parse("age <= AND").unwrap_err();
+//! assert_eq!(err.column, 8);
+//! # }
+//! ```
+
+use std::fmt::{Display, Formatter, Error};
+
+use client_types::*;
+use client_types::HyperValue::*;
+use client_types::HyperPredicateType::*;
+
+/// A query that could not be parsed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryError {
+    /// The column the problem was found at, starting from 1.
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Op(&'static str),
+    LParen,
+    RParen,
+    End,
+}
+
+fn describe(token: &Token) -> String {
+    match *token {
+        Token::Ident(ref name) => format!("\"{}\"", name),
+        Token::Str(ref s) => format!("the string {:?}", s),
+        Token::Int(i) => format!("the number {}", i),
+        Token::Float(f) => format!("the number {}", f),
+        Token::Op(op) => format!("\"{}\"", op),
+        Token::LParen => "\"(\"".to_string(),
+        Token::RParen => "\")\"".to_string(),
+        Token::End => "the end of the query".to_string(),
+    }
+}
+
+fn fail<T>(column: usize, message: String) -> Result<T, QueryError> {
+    Err(QueryError {
+        column: column,
+        message: message,
+    })
+}
+
+const OPERATORS: [&'static str; 7] = ["<=", ">=", "==", "=~", "=", "<", ">"];
+
+/// Splits the query into tokens, each paired with the column it starts at.
+fn tokenize(query: &str) -> Result<Vec<(Token, usize)>, QueryError> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' || c == ')' {
+            tokens.push((if c == '(' { Token::LParen } else { Token::RParen }, column));
+            i += 1;
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            let ident: String = chars[start..i].iter().cloned().collect();
+            if ident.ends_with('.') || ident.contains("..") {
+                return fail(column, format!("\"{}\" is not a valid attribute name", ident));
+            }
+            tokens.push((Token::Ident(ident), column));
+        } else if c.is_digit(10) || (c == '-' && i + 1 < chars.len() && chars[i + 1].is_digit(10)) {
+            let start = i;
+            i += 1;
+            let mut float = false;
+            while i < chars.len() {
+                let d = chars[i];
+                if d.is_digit(10) {
+                    i += 1;
+                } else if d == '.' || d == 'e' || d == 'E' {
+                    float = true;
+                    i += 1;
+                    if (d == 'e' || d == 'E') && i < chars.len() && (chars[i] == '-' || chars[i] == '+') {
+                        i += 1;
+                    }
+                } else {
+                    break;
+                }
+            }
+            let text: String = chars[start..i].iter().cloned().collect();
+            let token = if float {
+                text.parse().map(Token::Float).ok()
+            } else {
+                text.parse().map(Token::Int).ok()
+            };
+            match token {
+                Some(token) => tokens.push((token, column)),
+                None => return fail(column, format!("\"{}\" is not a valid number", text)),
+            }
+        } else if c == '"' {
+            i += 1;
+            let mut s = String::new();
+            loop {
+                if i >= chars.len() {
+                    return fail(column, "the string is never closed".to_string());
+                }
+                match chars[i] {
+                    '"' => break,
+                    '\\' if i + 1 < chars.len() => {
+                        s.push(match chars[i + 1] {
+                            '"' => '"',
+                            '\\' => '\\',
+                            'n' => '\n',
+                            't' => '\t',
+                            other => return fail(i + 1, format!("unknown escape sequence \"\\{}\"", other)),
+                        });
+                        i += 2;
+                    },
+                    other => {
+                        s.push(other);
+                        i += 1;
+                    },
+                }
+            }
+            i += 1;
+            tokens.push((Token::Str(s), column));
+        } else {
+            let rest: String = chars[i..].iter().take(2).cloned().collect();
+            match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                Some(&op) => {
+                    tokens.push((Token::Op(op), column));
+                    i += op.len();
+                },
+                None => return fail(column, format!("unexpected character '{}'", c)),
+            }
+        }
+    }
+    tokens.push((Token::End, chars.len() + 1));
+    Ok(tokens)
+}
+
+fn is_keyword(token: &Token, keyword: &str) -> bool {
+    match *token {
+        Token::Ident(ref name) => name.eq_ignore_ascii_case(keyword),
+        _ => false,
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &(Token, usize) {
+        &self.tokens[self.pos]
+    }
+
+    fn next(&mut self) -> (Token, usize) {
+        let token = self.tokens[self.pos].clone();
+        if token.0 != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), QueryError> {
+        let (token, column) = self.next();
+        if token == expected {
+            Ok(())
+        } else {
+            fail(column, format!("expected {}, found {}", describe(&expected), describe(&token)))
+        }
+    }
+
+    fn attribute(&mut self) -> Result<String, QueryError> {
+        match self.next() {
+            (Token::Ident(name), column) => {
+                if name.eq_ignore_ascii_case("and") {
+                    fail(column, format!("expected an attribute, found \"{}\"", name))
+                } else {
+                    Ok(name)
+                }
+            },
+            (token, column) => fail(column, format!("expected an attribute, found {}", describe(&token))),
+        }
+    }
+
+    fn value(&mut self) -> Result<(HyperValue, usize), QueryError> {
+        match self.next() {
+            (Token::Str(s), column) => Ok((HyperString(s.into_bytes()), column)),
+            (Token::Int(i), column) => Ok((HyperInt(i), column)),
+            (Token::Float(f), column) => Ok((HyperFloat(f), column)),
+            (token, column) => fail(column, format!("expected a value, found {}", describe(&token))),
+        }
+    }
+
+    fn condition(&mut self) -> Result<HyperPredicate, QueryError> {
+        if is_keyword(&self.peek().0, "length") && self.tokens[self.pos + 1].0 == Token::LParen {
+            self.next();
+            self.next();
+            let attr = try!(self.attribute());
+            try!(self.expect(Token::RParen));
+            let predicate = match self.next() {
+                (Token::Op("="), _) | (Token::Op("=="), _) => LengthEquals,
+                (Token::Op("<="), _) => LengthLessEqual,
+                (Token::Op(">="), _) => LengthGreaterEqual,
+                (token, column) => return fail(column, format!("expected \"=\", \"<=\" or \">=\" after length(...), \
+                                                                found {}", describe(&token))),
+            };
+            return match try!(self.value()) {
+                (HyperInt(len), _) if len >= 0 => Ok(HyperPredicate::new(attr, predicate, len)),
+                (_, column) => fail(column, "a length must be a non-negative int".to_string()),
+            };
+        }
+
+        let attr = try!(self.attribute());
+        let predicate = match self.next() {
+            (Token::Op("="), _) | (Token::Op("=="), _) => Equals,
+            (Token::Op("<"), _) => LessThan,
+            (Token::Op("<="), _) => LessEqual,
+            (Token::Op(">"), _) => GreaterThan,
+            (Token::Op(">="), _) => GreaterEqual,
+            (Token::Op("=~"), _) => Regex,
+            (ref token, _) if is_keyword(token, "contains") => {
+                if self.peek().0 == Token::Op("<") {
+                    self.next();
+                    ContainsLessThan
+                } else {
+                    Contains
+                }
+            },
+            (token, column) => return fail(column, format!("expected a comparison after \"{}\", found {}",
+                                                           attr, describe(&token))),
+        };
+        let (value, value_column) = try!(self.value());
+        let pred = HyperPredicate {
+            attr: attr,
+            value: value,
+            predicate: predicate,
+        };
+        match pred.validate() {
+            Ok(()) => Ok(pred),
+            Err(err) => fail(value_column, err.message),
+        }
+    }
+
+    fn query(&mut self) -> Result<Vec<HyperPredicate>, QueryError> {
+        let mut checks = Vec::new();
+        if self.peek().0 == Token::End {
+            return Ok(checks);
+        }
+        loop {
+            checks.push(try!(self.condition()));
+            match self.next() {
+                (Token::End, _) => return Ok(checks),
+                (ref token, _) if is_keyword(token, "and") => (),
+                (token, column) => return fail(column, format!("expected \"AND\" or the end of the query, found {}",
+                                                               describe(&token))),
+            }
+        }
+    }
+}
+
+/// Parses a query into the predicates it stands for.
+pub fn parse(query: &str) -> Result<Vec<HyperPredicate>, QueryError> {
+    let mut parser = Parser {
+        tokens: try!(tokenize(query)),
+        pos: 0,
+    };
+    parser.query()
+}
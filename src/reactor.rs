@@ -0,0 +1,129 @@
+use std::io;
+use std::sync::Arc;
+
+use libc::*;
+
+/// The token reported for wake-ups triggered through a `Waker`.
+const WAKER_TOKEN: u64 = ::std::u64::MAX;
+
+/// A tiny level-triggered epoll reactor.
+///
+/// Callers register file descriptors under a token and then block in `poll` until one of them
+/// becomes readable or somebody calls `Waker::wake`.
+pub struct Reactor {
+    epfd: c_int,
+    waker: Arc<Waker>,
+}
+
+/// Wakes up a thread blocked in `Reactor::poll`.  Backed by an eventfd.
+pub struct Waker {
+    fd: c_int,
+}
+
+impl Reactor {
+    pub fn new() -> io::Result<Reactor> {
+        unsafe {
+            let epfd = epoll_create1(EPOLL_CLOEXEC);
+            if epfd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let wfd = eventfd(0, EFD_CLOEXEC | EFD_NONBLOCK);
+            if wfd < 0 {
+                let err = io::Error::last_os_error();
+                close(epfd);
+                return Err(err);
+            }
+
+            let reactor = Reactor {
+                epfd: epfd,
+                waker: Arc::new(Waker { fd: wfd }),
+            };
+            try!(reactor.add(wfd, WAKER_TOKEN));
+            Ok(reactor)
+        }
+    }
+
+    /// Starts watching `fd` for readability.  `poll` reports it as `token`.
+    pub fn register(&self, fd: c_int, token: usize) -> io::Result<()> {
+        self.add(fd, token as u64)
+    }
+
+    fn add(&self, fd: c_int, token: u64) -> io::Result<()> {
+        let mut ev = epoll_event {
+            events: EPOLLIN as u32,
+            u64: token,
+        };
+        if unsafe { epoll_ctl(self.epfd, EPOLL_CTL_ADD, fd, &mut ev) } < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn waker(&self) -> Arc<Waker> {
+        self.waker.clone()
+    }
+
+    /// Blocks for at most `timeout` milliseconds (forever if negative) until something happens.
+    ///
+    /// The tokens of the descriptors that became readable are written into `ready`.  Returns
+    /// whether the reactor was woken up through its `Waker`.
+    pub fn poll(&self, ready: &mut Vec<usize>, timeout: i32) -> io::Result<bool> {
+        let mut events: [epoll_event; 32] = unsafe { ::std::mem::zeroed() };
+        ready.clear();
+
+        let n = unsafe { epoll_wait(self.epfd, events.as_mut_ptr(), events.len() as c_int, timeout) };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(false);
+            }
+            return Err(err);
+        }
+
+        let mut woken = false;
+        for ev in events[..n as usize].iter() {
+            if ev.u64 == WAKER_TOKEN {
+                self.waker.reset();
+                woken = true;
+            } else {
+                ready.push(ev.u64 as usize);
+            }
+        }
+        Ok(woken)
+    }
+}
+
+impl Drop for Reactor {
+    fn drop(&mut self) {
+        unsafe {
+            close(self.epfd);
+        }
+    }
+}
+
+impl Waker {
+    pub fn wake(&self) {
+        let one = 1u64;
+        unsafe {
+            // A full counter still leaves the eventfd readable, so a failed write is harmless.
+            write(self.fd, &one as *const u64 as *const c_void, 8);
+        }
+    }
+
+    fn reset(&self) {
+        let mut buf = 0u64;
+        unsafe {
+            read(self.fd, &mut buf as *mut u64 as *mut c_void, 8);
+        }
+    }
+}
+
+impl Drop for Waker {
+    fn drop(&mut self) {
+        unsafe {
+            close(self.fd);
+        }
+    }
+}
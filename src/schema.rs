@@ -0,0 +1,161 @@
+//! The attributes of a space and their types, as the coordinator reports them.
+
+use std::fmt::{Display, Formatter, Error};
+use std::str::FromStr;
+
+/// A type that the elements of lists and sets and the keys and values of maps can have.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Primitive {
+    String,
+    Int,
+    Float,
+}
+
+/// The type of an attribute, written the same way as in a space description.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Datatype {
+    String,
+    Int,
+    Float,
+    Document,
+    List(Primitive),
+    Set(Primitive),
+    Map(Primitive, Primitive),
+}
+
+impl Display for Primitive {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "{}", match *self {
+            Primitive::String => "string",
+            Primitive::Int => "int",
+            Primitive::Float => "float",
+        })
+    }
+}
+
+impl Display for Datatype {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            Datatype::String => write!(f, "string"),
+            Datatype::Int => write!(f, "int"),
+            Datatype::Float => write!(f, "float"),
+            Datatype::Document => write!(f, "document"),
+            Datatype::List(elem) => write!(f, "list({})", elem),
+            Datatype::Set(elem) => write!(f, "set({})", elem),
+            Datatype::Map(key, val) => write!(f, "map({}, {})", key, val),
+        }
+    }
+}
+
+fn primitive(name: &str) -> Option<Primitive> {
+    match name {
+        "string" => Some(Primitive::String),
+        "int" | "int64" => Some(Primitive::Int),
+        "float" => Some(Primitive::Float),
+        _ => None,
+    }
+}
+
+/// Reads `list(string)` style names, as well as `HYPERDATATYPE_LIST_STRING` style ones.
+/// Timestamps are ints as far as clients are concerned.
+impl FromStr for Datatype {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Datatype, String> {
+        let name: String = if s.starts_with("HYPERDATATYPE_") {
+            let words: Vec<String> = s["HYPERDATATYPE_".len()..].split('_').map(|w| w.to_lowercase()).collect();
+            match words.len() {
+                1 => words[0].clone(),
+                2 => format!("{}({})", words[0], words[1]),
+                3 => format!("{}({},{})", words[0], words[1], words[2]),
+                _ => return Err(format!("\"{}\" is not a type", s)),
+            }
+        } else {
+            s.chars().filter(|c| !c.is_whitespace()).collect()
+        };
+        if name.starts_with("timestamp") {
+            return Ok(Datatype::Int);
+        }
+        if name == "document" {
+            return Ok(Datatype::Document);
+        }
+        if let Some(p) = primitive(&name) {
+            return Ok(match p {
+                Primitive::String => Datatype::String,
+                Primitive::Int => Datatype::Int,
+                Primitive::Float => Datatype::Float,
+            });
+        }
+
+        if let (Some(open), true) = (name.find('('), name.ends_with(')')) {
+            let args: Vec<Option<Primitive>> = name[open + 1..name.len() - 1].split(',').map(primitive).collect();
+            match (&name[..open], args.len(), args[0], args.get(1).cloned().and_then(|a| a)) {
+                ("list", 1, Some(elem), _) => return Ok(Datatype::List(elem)),
+                ("set", 1, Some(elem), _) => return Ok(Datatype::Set(elem)),
+                ("map", 2, Some(key), Some(val)) => return Ok(Datatype::Map(key, val)),
+                _ => (),
+            }
+        }
+        Err(format!("\"{}\" is not a type", s))
+    }
+}
+
+/// The key and attributes of a space.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpaceSchema {
+    pub name: String,
+    pub key: String,
+    pub key_type: Datatype,
+    pub attributes: Vec<(String, Datatype)>,
+}
+
+impl SpaceSchema {
+    /// The type of an attribute or of the key, if the space has one by that name.
+    pub fn datatype(&self, attr: &str) -> Option<Datatype> {
+        if attr == self.key {
+            return Some(self.key_type);
+        }
+        self.attributes.iter().find(|a| a.0 == attr).map(|a| a.1)
+    }
+}
+
+/// Reads the schema of every space out of `Admin::dump_config`, where each space starts with a
+/// `space ID NAME` line and lists its attributes as `attribute NAME TYPE`, key first.
+pub fn parse_config(config: &str) -> Result<Vec<SpaceSchema>, String> {
+    let mut spaces = Vec::new();
+    let mut current: Option<(String, Vec<(String, Datatype)>)> = None;
+    for line in config.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match (words.get(0).cloned(), words.len()) {
+            (Some("space"), n) if n >= 3 => {
+                if let Some(space) = current.take() {
+                    spaces.push(space);
+                }
+                current = Some((words[n - 1].to_string(), Vec::new()));
+            },
+            (Some("attribute"), n) if n >= 3 => {
+                if let Some((_, ref mut attrs)) = current {
+                    let dt: Datatype = try!(words[2..].join(" ").parse());
+                    attrs.push((words[1].to_string(), dt));
+                }
+            },
+            _ => (),
+        }
+    }
+    if let Some(space) = current.take() {
+        spaces.push(space);
+    }
+
+    spaces.into_iter().map(|(name, mut attrs)| {
+        if attrs.is_empty() {
+            return Err(format!("space \"{}\" has no key", name));
+        }
+        let (key, key_type) = attrs.remove(0);
+        Ok(SpaceSchema {
+            name: name,
+            key: key,
+            key_type: key_type,
+            attributes: attrs,
+        })
+    }).collect()
+}
@@ -0,0 +1,658 @@
+//! Traits describing a HyperDex key-value store, so that code can be written once against
+//! `Client`, `LocalClient`, `testing::MemoryStore` or any wrapper around them, such as
+//! `CachedClient`.
+
+#![macro_use]
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::mpsc::Receiver;
+
+use eventual::Future;
+
+use common::*;
+use client::{Client, LocalClient};
+use client_types::*;
+use helpers::FutureHelpers;
+use hyperdex_client::*;
+
+/// The blocking operations of a HyperDex client.
+///
+/// Every method behaves like the method of the same name on `Client`.  The operations mirror
+/// the C client API: `space` names the space, `key` the object, and failures are reported as a
+/// `HyperError` carrying a `HYPERDEX_CLIENT_*` status.
+pub trait KeyValueStore {
+    fn get<S, K>(&mut self, space: S, key: K)
+        -> Result<HyperObject, HyperError> where S: ToCStr, K: ToHyperValue;
+
+    fn get_partial<S, K, A>(&mut self, space: S, key: K, attrs: Vec<A>)
+        -> Result<HyperObject, HyperError> where S: ToCStr, K: ToHyperValue, A: ToString;
+
+    fn del<S, K>(&mut self, space: S, key: K)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn cond_del<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+
+    fn put<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn put_if_not_exist<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn atomic_add<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn atomic_sub<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn atomic_mul<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn atomic_div<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn atomic_mod<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn atomic_and<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn atomic_or<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn atomic_xor<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn string_prepend<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn string_append<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn list_lpush<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn list_rpush<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn set_add<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn set_remove<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn set_intersect<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn set_union<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn map_remove<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+
+    fn cond_put<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+
+    fn map_add<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn map_atomic_add<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn map_atomic_sub<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn map_atomic_mul<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn map_atomic_div<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn map_atomic_mod<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn map_atomic_and<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn map_atomic_or<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn map_atomic_xor<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn map_string_prepend<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn map_string_append<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+
+    fn cond_map_add<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn cond_map_atomic_add<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn cond_map_atomic_sub<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn cond_map_atomic_mul<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn cond_map_atomic_div<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn cond_map_atomic_mod<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn cond_map_atomic_and<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn cond_map_atomic_or<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn cond_map_atomic_xor<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn cond_map_string_prepend<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn cond_map_string_append<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn search<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr;
+    fn search_keyed<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Receiver<Result<(HyperKey, HyperObject), HyperError>> where S: ToCStr;
+    fn sorted_search<S, A>(&mut self, space: S, checks: Vec<HyperPredicate>, sort_by: A, limit: u64, maxmin: bool)
+        -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr, A: ToCStr;
+    fn count<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Result<u64, HyperError> where S: ToCStr;
+
+    /// Fetches many objects, with one result per key.
+    fn get_many<S, K, I>(&mut self, space: S, keys: I)
+        -> HashMap<K, Result<HyperObject, HyperError>>
+        where S: ToCStr + Clone, K: ToHyperValue + Clone + Hash + Eq, I: IntoIterator<Item=K>;
+    fn get_partial_many<S, K, A, I>(&mut self, space: S, keys: I, attrs: Vec<A>)
+        -> HashMap<K, Result<HyperObject, HyperError>>
+        where S: ToCStr + Clone, K: ToHyperValue + Clone + Hash + Eq, A: ToString + Clone,
+              I: IntoIterator<Item=K>;
+
+    /// Reads an object from the store itself, skipping any cache in front of it.  This is `get`
+    /// for every store but `CachedClient`.
+    fn get_latest<S, K>(&mut self, space: S, key: K)
+        -> Result<HyperObject, HyperError> where S: ToCStr, K: ToHyperValue {
+        self.get(space, key)
+    }
+
+    /// Reads an object, lets `f` change it and writes it back with `cond_put`, which only
+    /// succeeds if no other client changed the object in between.  Returns the object as it
+    /// was written.
+    ///
+    /// The object is read with `get_latest`, so a cached copy never makes the write lose.  Its
+    /// strings, ints and floats are compared, and a write that loses the race starts over from
+    /// the read, up to 3 times; see `update_with` for other choices.  `f` may therefore run more
+    /// than once, and can give up by returning an error, which `update` passes on without
+    /// writing anything.  Fails with `HYPERDEX_CLIENT_NOTFOUND` if the object does not exist,
+    /// and with `HYPERDEX_CLIENT_CMPFAIL` if every attempt lost.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate hyperdex;
+    /// # use hyperdex::{HyperError, HyperObject, KeyValueStore};
+    /// # use hyperdex::testing::MemoryStore;
+    /// #[derive(Debug)]
+    /// enum PaymentError {
+    ///     Store(HyperError),
+    ///     Overdrawn,
+    /// }
+    ///
+    /// impl From<HyperError> for PaymentError {
+    ///     fn from(err: HyperError) -> PaymentError {
+    ///         PaymentError::Store(err)
+    ///     }
+    /// }
+    ///
+    /// # fn main() {
+    /// # let mut store = MemoryStore::new();
+    /// # store.add_space("space accounts key owner attributes int balance").unwrap();
+    /// # store.put("accounts", "derek", NewHyperObject!("balance", 15,)).unwrap();
+    /// let pay = |store: &mut MemoryStore, amount: i64| store.update("accounts", "derek", |obj| {
+    ///     let balance: i64 = obj.get("balance").unwrap();
+    ///     if balance < amount {
+    ///         return Err(PaymentError::Overdrawn);
+    ///     }
+    ///     obj.insert("balance", balance - amount);
+    ///     Ok(())
+    /// });
+    /// assert!(pay(&mut store, 10).is_ok());
+    /// assert!(pay(&mut store, 10).is_err());
+    /// # }
+    /// ```
+    fn update<S, K, F, E>(&mut self, space: S, key: K, f: F)
+        -> Result<HyperObject, E>
+        where S: ToCStr + Clone, K: ToHyperValue + Clone, F: FnMut(&mut HyperObject) -> Result<(), E>,
+              E: From<HyperError> {
+        self.update_with(space, key, &UpdateOptions::new(), f)
+    }
+
+    /// Like `update`, with the comparison and the number of retries given by `options`.
+    fn update_with<S, K, F, E>(&mut self, space: S, key: K, options: &UpdateOptions, mut f: F)
+        -> Result<HyperObject, E>
+        where S: ToCStr + Clone, K: ToHyperValue + Clone, F: FnMut(&mut HyperObject) -> Result<(), E>,
+              E: From<HyperError> {
+        let mut retries = 0;
+        loop {
+            let mut obj = try!(self.get_latest(space.clone(), key.clone()));
+            let (checks, next_version) = match options.guard {
+                UpdateGuard::Attributes => (try!(primitive_guard(&obj)), None),
+                UpdateGuard::Version(ref attr) => {
+                    let version = try!(version_of(&obj, attr));
+                    (vec!(HyperPredicate::new(attr, HyperPredicateType::Equals, version)), Some((attr, version + 1)))
+                },
+            };
+            try!(f(&mut obj));
+            if let Some((attr, version)) = next_version {
+                obj.insert(attr, version);
+            }
+
+            match self.cond_put(space.clone(), key.clone(), checks, obj.clone()) {
+                Ok(()) => return Ok(obj),
+                Err(ref err) if err.status == HYPERDEX_CLIENT_CMPFAIL && retries < options.retries => retries += 1,
+                Err(err) => return Err(E::from(err)),
+            }
+        }
+    }
+}
+
+/// What `KeyValueStore::update_with` compares to tell whether an object changed since it was read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UpdateGuard {
+    /// Every string, int and float attribute that was read.  Lists, sets, maps and documents
+    /// are not compared, so a change to them alone goes unnoticed; objects that have them
+    /// should use a version attribute.
+    Attributes,
+    /// An int attribute that every update increments.  Only writes that go through `update`
+    /// are noticed, but the comparison is cheap however large the object.
+    Version(String),
+}
+
+/// How `KeyValueStore::update_with` writes an object back.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UpdateOptions {
+    pub guard: UpdateGuard,
+    /// How many times to start over after losing to another client.
+    pub retries: u32,
+}
+
+impl UpdateOptions {
+    /// Compares the strings, ints and floats and retries 3 times, like `KeyValueStore::update`.
+    pub fn new() -> UpdateOptions {
+        UpdateOptions {
+            guard: UpdateGuard::Attributes,
+            retries: 3,
+        }
+    }
+
+    /// Compares the int attribute `attr` instead, and increments it on every write.
+    pub fn version<A: ToString>(mut self, attr: A) -> UpdateOptions {
+        self.guard = UpdateGuard::Version(attr.to_string());
+        self
+    }
+
+    pub fn retries(mut self, retries: u32) -> UpdateOptions {
+        self.retries = retries;
+        self
+    }
+}
+
+/// Compares the strings, ints and floats of `obj`, the only attributes whose comparison for
+/// equality HyperDex documents.
+fn primitive_guard(obj: &HyperObject) -> Result<Vec<HyperPredicate>, HyperError> {
+    let checks: Vec<HyperPredicate> = obj.map.iter().filter(|&(_, val)| match *val {
+        HyperValue::HyperString(_) | HyperValue::HyperInt(_) | HyperValue::HyperFloat(_) => true,
+        _ => false,
+    }).map(|(attr, val)| HyperPredicate::new(attr, HyperPredicateType::Equals, val.clone())).collect();
+    if checks.is_empty() {
+        return Err(HyperError {
+            status: HYPERDEX_CLIENT_WRONGTYPE,
+            message: "the object has no string, int or float attribute to compare; use a version attribute"
+                     .to_string(),
+            location: String::new(),
+        });
+    }
+    Ok(checks)
+}
+
+fn version_of(obj: &HyperObject, attr: &str) -> Result<i64, HyperError> {
+    obj.get(attr).map_err(|_| HyperError {
+        status: HYPERDEX_CLIENT_WRONGTYPE,
+        message: format!("the version attribute \"{}\" is missing or not an int", attr),
+        location: String::new(),
+    })
+}
+
+/// The non-blocking operations of a HyperDex client.
+///
+/// Each `async_*` method submits the operation and returns a future for its result, like the
+/// method of the same name on `Client`.
+pub trait AsyncKeyValueStore {
+    fn async_get<S, K>(&mut self, space: S, key: K)
+        -> Future<HyperObject, HyperError> where S: ToCStr, K: ToHyperValue;
+
+    fn async_get_partial<S, K, A>(&mut self, space: S, key: K, attrs: Vec<A>)
+        -> Future<HyperObject, HyperError> where S: ToCStr, K: ToHyperValue, A: ToString;
+
+    fn async_del<S, K>(&mut self, space: S, key: K)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_cond_del<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+
+    fn async_put<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_put_if_not_exist<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_atomic_add<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_atomic_sub<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_atomic_mul<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_atomic_div<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_atomic_mod<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_atomic_and<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_atomic_or<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_atomic_xor<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_string_prepend<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_string_append<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_list_lpush<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_list_rpush<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_set_add<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_set_remove<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_set_intersect<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_set_union<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_map_remove<S, K>(&mut self, space: S, key: K, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+
+    fn async_cond_put<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, value: HyperObject)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+
+    fn async_map_add<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_map_atomic_add<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_map_atomic_sub<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_map_atomic_mul<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_map_atomic_div<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_map_atomic_mod<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_map_atomic_and<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_map_atomic_or<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_map_atomic_xor<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_map_string_prepend<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_map_string_append<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+
+    fn async_cond_map_add<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_cond_map_atomic_add<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_cond_map_atomic_sub<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_cond_map_atomic_mul<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_cond_map_atomic_div<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_cond_map_atomic_mod<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_cond_map_atomic_and<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_cond_map_atomic_or<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_cond_map_atomic_xor<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_cond_map_string_prepend<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn async_cond_map_string_append<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+        -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue;
+    fn search<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr;
+    fn search_keyed<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Receiver<Result<(HyperKey, HyperObject), HyperError>> where S: ToCStr;
+    fn sorted_search<S, A>(&mut self, space: S, checks: Vec<HyperPredicate>, sort_by: A, limit: u64, maxmin: bool)
+        -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr, A: ToCStr;
+    fn async_count<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Future<u64, HyperError> where S: ToCStr;
+}
+
+/// Runs `submit` for every key, keeping at most `limit` requests in flight, and collects one
+/// result per key.
+pub fn fetch_many<C, K, I, F>(store: &mut C, limit: usize, keys: I, mut submit: F)
+    -> HashMap<K, Result<HyperObject, HyperError>>
+    where C: AsyncKeyValueStore, K: ToHyperValue + Clone + Hash + Eq, I: IntoIterator<Item=K>,
+          F: FnMut(&mut C, K) -> Future<HyperObject, HyperError> {
+    let mut in_flight: VecDeque<(K, Future<HyperObject, HyperError>)> = VecDeque::new();
+    let mut results = HashMap::new();
+
+    for key in keys.into_iter() {
+        if in_flight.len() >= limit {
+            let (k, fut) = in_flight.pop_front().unwrap();
+            results.insert(k, fut.into_inner());
+        }
+        let fut = submit(store, key.clone());
+        in_flight.push_back((key, fut));
+    }
+    for (k, fut) in in_flight.into_iter() {
+        results.insert(k, fut.into_inner());
+    }
+
+    results
+}
+
+/// Implements `KeyValueStore` for a type by forwarding to its inherent methods.  Generic types
+/// give their parameters in brackets first, as in `[C: KeyValueStore] CachedClient<C>`, and
+/// stores that can read around a cache name the method that does it with `latest [...]`.
+///
+/// Modules declared after this one in `lib.rs` use it for their own stores.
+macro_rules! impl_key_value_store(
+    ([$($gen: tt)*] $store: ty,
+     key [$($key: ident),*],
+     predicates [$($keycond: ident),*],
+     get [$($get: ident),*],
+     get_partial [$($partial: ident),*],
+     attributes [$($attrs: ident),*],
+     predicates_attributes [$($cond: ident),*],
+     mapattributes [$($maps: ident),*],
+     predicates_mapattributes [$($condmaps: ident),*],
+     latest [$($latest: ident),*]) => (
+        impl<$($gen)*> KeyValueStore for $store {
+            $(fn $key<S, K>(&mut self, space: S, key: K)
+                -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                <$store>::$key(self, space, key)
+            })*
+
+            $(fn $keycond<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>)
+                -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                <$store>::$keycond(self, space, key, checks)
+            })*
+
+            $(fn $get<S, K>(&mut self, space: S, key: K)
+                -> Result<HyperObject, HyperError> where S: ToCStr, K: ToHyperValue {
+                <$store>::$get(self, space, key)
+            })*
+
+            $(fn $partial<S, K, A>(&mut self, space: S, key: K, attrs: Vec<A>)
+                -> Result<HyperObject, HyperError> where S: ToCStr, K: ToHyperValue, A: ToString {
+                <$store>::$partial(self, space, key, attrs)
+            })*
+
+            $(fn $attrs<S, K>(&mut self, space: S, key: K, value: HyperObject)
+                -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                <$store>::$attrs(self, space, key, value)
+            })*
+
+            $(fn $cond<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, value: HyperObject)
+                -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                <$store>::$cond(self, space, key, checks, value)
+            })*
+
+            $(fn $maps<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+                -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                <$store>::$maps(self, space, key, mapattrs)
+            })*
+
+            $(fn $condmaps<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>,
+                                 mapattrs: Vec<HyperMapAttribute>)
+                -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                <$store>::$condmaps(self, space, key, checks, mapattrs)
+            })*
+
+            fn search<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+                -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr {
+                <$store>::search(self, space, checks)
+            }
+
+            fn search_keyed<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+                -> Receiver<Result<(HyperKey, HyperObject), HyperError>> where S: ToCStr {
+                <$store>::search_keyed(self, space, checks)
+            }
+
+            fn sorted_search<S, A>(&mut self, space: S, checks: Vec<HyperPredicate>, sort_by: A, limit: u64,
+                                   maxmin: bool)
+                -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr, A: ToCStr {
+                <$store>::sorted_search(self, space, checks, sort_by, limit, maxmin)
+            }
+
+            fn count<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+                -> Result<u64, HyperError> where S: ToCStr {
+                <$store>::count(self, space, checks)
+            }
+
+            fn get_many<S, K, I>(&mut self, space: S, keys: I)
+                -> HashMap<K, Result<HyperObject, HyperError>>
+                where S: ToCStr + Clone, K: ToHyperValue + Clone + Hash + Eq, I: IntoIterator<Item=K> {
+                <$store>::get_many(self, space, keys)
+            }
+
+            fn get_partial_many<S, K, A, I>(&mut self, space: S, keys: I, attrs: Vec<A>)
+                -> HashMap<K, Result<HyperObject, HyperError>>
+                where S: ToCStr + Clone, K: ToHyperValue + Clone + Hash + Eq, A: ToString + Clone,
+                      I: IntoIterator<Item=K> {
+                <$store>::get_partial_many(self, space, keys, attrs)
+            }
+
+            $(fn get_latest<S, K>(&mut self, space: S, key: K)
+                -> Result<HyperObject, HyperError> where S: ToCStr, K: ToHyperValue {
+                <$store>::$latest(self, space, key)
+            })*
+        }
+    );
+    ([$($gen: tt)*] $store: ty, latest [$($latest: ident),*]) => (
+        impl_key_value_store!([$($gen)*] $store,
+            key [del],
+            predicates [cond_del],
+            get [get],
+            get_partial [get_partial],
+            attributes [put, put_if_not_exist, atomic_add, atomic_sub, atomic_mul, atomic_div, atomic_mod,
+                        atomic_and, atomic_or, atomic_xor, string_prepend, string_append, list_lpush,
+                        list_rpush, set_add, set_remove, set_intersect, set_union, map_remove],
+            predicates_attributes [cond_put],
+            mapattributes [map_add, map_atomic_add, map_atomic_sub, map_atomic_mul, map_atomic_div,
+                           map_atomic_mod, map_atomic_and, map_atomic_or, map_atomic_xor,
+                           map_string_prepend, map_string_append],
+            predicates_mapattributes [cond_map_add, cond_map_atomic_add, cond_map_atomic_sub,
+                                      cond_map_atomic_mul, cond_map_atomic_div, cond_map_atomic_mod,
+                                      cond_map_atomic_and, cond_map_atomic_or, cond_map_atomic_xor,
+                                      cond_map_string_prepend, cond_map_string_append],
+            latest [$($latest),*]);
+    );
+    ([$($gen: tt)*] $store: ty) => (
+        impl_key_value_store!([$($gen)*] $store, latest []);
+    );
+    ($store: ty) => (
+        impl_key_value_store!([] $store);
+    );
+);
+
+/// Implements `AsyncKeyValueStore` for a type by forwarding to its inherent methods.
+macro_rules! impl_async_key_value_store(
+    ($store: ty,
+     key [$($key: ident),*],
+     predicates [$($keycond: ident),*],
+     get [$($get: ident),*],
+     get_partial [$($partial: ident),*],
+     attributes [$($attrs: ident),*],
+     predicates_attributes [$($cond: ident),*],
+     mapattributes [$($maps: ident),*],
+     predicates_mapattributes [$($condmaps: ident),*]) => (
+        impl AsyncKeyValueStore for $store {
+            $(fn $key<S, K>(&mut self, space: S, key: K)
+                -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                <$store>::$key(self, space, key)
+            })*
+
+            $(fn $keycond<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>)
+                -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                <$store>::$keycond(self, space, key, checks)
+            })*
+
+            $(fn $get<S, K>(&mut self, space: S, key: K)
+                -> Future<HyperObject, HyperError> where S: ToCStr, K: ToHyperValue {
+                <$store>::$get(self, space, key)
+            })*
+
+            $(fn $partial<S, K, A>(&mut self, space: S, key: K, attrs: Vec<A>)
+                -> Future<HyperObject, HyperError> where S: ToCStr, K: ToHyperValue, A: ToString {
+                <$store>::$partial(self, space, key, attrs)
+            })*
+
+            $(fn $attrs<S, K>(&mut self, space: S, key: K, value: HyperObject)
+                -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                <$store>::$attrs(self, space, key, value)
+            })*
+
+            $(fn $cond<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, value: HyperObject)
+                -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                <$store>::$cond(self, space, key, checks, value)
+            })*
+
+            $(fn $maps<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+                -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                <$store>::$maps(self, space, key, mapattrs)
+            })*
+
+            $(fn $condmaps<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>,
+                                 mapattrs: Vec<HyperMapAttribute>)
+                -> Future<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                <$store>::$condmaps(self, space, key, checks, mapattrs)
+            })*
+
+            fn search<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+                -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr {
+                <$store>::search(self, space, checks)
+            }
+
+            fn search_keyed<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+                -> Receiver<Result<(HyperKey, HyperObject), HyperError>> where S: ToCStr {
+                <$store>::search_keyed(self, space, checks)
+            }
+
+            fn sorted_search<S, A>(&mut self, space: S, checks: Vec<HyperPredicate>, sort_by: A, limit: u64,
+                                   maxmin: bool)
+                -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr, A: ToCStr {
+                <$store>::sorted_search(self, space, checks, sort_by, limit, maxmin)
+            }
+
+            fn async_count<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+                -> Future<u64, HyperError> where S: ToCStr {
+                <$store>::async_count(self, space, checks)
+            }
+        }
+    );
+    ($store: ty) => (
+        impl_async_key_value_store!($store,
+            key [async_del],
+            predicates [async_cond_del],
+            get [async_get],
+            get_partial [async_get_partial],
+            attributes [async_put, async_put_if_not_exist, async_atomic_add, async_atomic_sub,
+                        async_atomic_mul, async_atomic_div, async_atomic_mod, async_atomic_and,
+                        async_atomic_or, async_atomic_xor, async_string_prepend, async_string_append,
+                        async_list_lpush, async_list_rpush, async_set_add, async_set_remove,
+                        async_set_intersect, async_set_union, async_map_remove],
+            predicates_attributes [async_cond_put],
+            mapattributes [async_map_add, async_map_atomic_add, async_map_atomic_sub,
+                           async_map_atomic_mul, async_map_atomic_div, async_map_atomic_mod,
+                           async_map_atomic_and, async_map_atomic_or, async_map_atomic_xor,
+                           async_map_string_prepend, async_map_string_append],
+            predicates_mapattributes [async_cond_map_add, async_cond_map_atomic_add,
+                                      async_cond_map_atomic_sub, async_cond_map_atomic_mul,
+                                      async_cond_map_atomic_div, async_cond_map_atomic_mod,
+                                      async_cond_map_atomic_and, async_cond_map_atomic_or,
+                                      async_cond_map_atomic_xor, async_cond_map_string_prepend,
+                                      async_cond_map_string_append]);
+    );
+);
+
+impl_key_value_store!(Client);
+impl_async_key_value_store!(Client);
+impl_async_key_value_store!(LocalClient);
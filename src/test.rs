@@ -1,13 +1,25 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::ptr;
+use std::slice;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use std::thread;
 
-use rustc_serialize::json::Json;
-use rustc_serialize::json::ToJson;
+use serde_json::Value;
 
 use super::*;
+use helpers::FutureHelpers;
 use super::HyperValue::*;
 use super::HyperPredicateType::*;
+use hyperdex::*;
 use hyperdex_client::*;
+use hyperdex_admin::*;
+use hyperdex_datastructures::*;
+use client::{build_hyperobject, convert_predicates, convert_type};
+use borrowed::AttributeBuffer;
 
 static coord_addr: &'static str = "127.0.0.1:1982";
 
@@ -21,6 +33,13 @@ subspace first, last
 create 2 partitions
 tolerate 2 failures";
 
+/// A `MemoryStore` holding the single, empty space described by `desc`.
+fn memory_store(desc: &str) -> testing::MemoryStore {
+    let mut store = testing::MemoryStore::new();
+    store.add_space(desc).unwrap();
+    store
+}
+
 #[test]
 fn test_documents() {
     let admin = Admin::new(FromStr::from_str(coord_addr).unwrap()).unwrap();
@@ -41,7 +60,7 @@ document profile") {
     profile.insert("age".to_string(), 456);
 
     let mut obj = HyperObject::new();
-    obj.insert("profile", profile.to_json());
+    obj.insert("profile", Document(profile).encode().unwrap());
 
     match client.put(space, "me", obj) {
         Ok(()) => (),
@@ -50,12 +69,12 @@ document profile") {
 
     match client.get(space, "me") {
         Ok(mut obj) => {
-            let profile: Json = match obj.get("profile") {
+            let profile: Value = match obj.get("profile") {
                 Ok(s) => s,
                 Err(err) => panic!(err),
             };
 
-            assert_eq!(profile, Json::from_str("{\"name\": 123, \"age\": 456}").unwrap());
+            assert_eq!(profile, Value::from_str("{\"name\": 123, \"age\": 456}").unwrap());
         },
         Err(err) => panic!(format!("{}", err)),
     }
@@ -169,7 +188,7 @@ fn test_add_and_search_objects() {
         Err(err) => panic!(err),
     }
 
-    let predicates = vec!(HyperPredicate::new("age", LESS_EQUAL, 25));
+    let predicates = vec!(HyperPredicate::new("age", LessEqual, 25));
 
     let res = client.search(space_name, predicates);
 
@@ -182,3 +201,1111 @@ fn test_add_and_search_objects() {
 
     admin.remove_space(space_name).unwrap();
 }
+
+#[test]
+fn test_shutdown() {
+    let admin = Admin::new(FromStr::from_str(coord_addr).unwrap()).unwrap();
+    match admin.add_space(space_desc) {
+        Ok(()) => (),
+        Err(err) => panic!(format!("{}", err)),
+    };
+
+    let mut client = Client::new(FromStr::from_str(coord_addr).unwrap()).unwrap();
+    let fut = client.async_put(space_name, "derek", NewHyperObject!(
+        "first", "Derek",
+        "last", "Chiang",
+    ));
+    client.shutdown(Duration::from_secs(5));
+
+    // The put was in flight when we shut down, so it must have been allowed to finish
+    match fut.into_inner() {
+        Ok(()) => (),
+        Err(err) => panic!(err),
+    }
+
+    admin.remove_space(space_name).unwrap();
+    admin.shutdown(Duration::from_secs(5));
+}
+
+#[test]
+fn test_local_client() {
+    let admin = Admin::new(FromStr::from_str(coord_addr).unwrap()).unwrap();
+    match admin.add_space(space_desc) {
+        Ok(()) => (),
+        Err(err) => panic!(format!("{}", err)),
+    };
+
+    let mut client = LocalClient::new(FromStr::from_str(coord_addr).unwrap()).unwrap();
+    let put = client.async_put(space_name, "derek", NewHyperObject!(
+        "first", "Derek",
+        "last", "Chiang",
+    ));
+    while client.pending() > 0 {
+        client.process_ready().unwrap();
+        thread::sleep_ms(10);
+    }
+    match put.into_inner() {
+        Ok(()) => (),
+        Err(err) => panic!(err),
+    }
+
+    let get = client.async_get(space_name, "derek");
+    while client.pending() > 0 {
+        client.process_ready().unwrap();
+        thread::sleep_ms(10);
+    }
+    let first: Vec<u8> = get.into_inner().unwrap().get("first").unwrap();
+    assert_eq!(first, "Derek".as_bytes());
+
+    admin.remove_space(space_name).unwrap();
+}
+
+#[test]
+fn test_batch() {
+    let admin = Admin::new(FromStr::from_str(coord_addr).unwrap()).unwrap();
+    match admin.add_space(space_desc) {
+        Ok(()) => (),
+        Err(err) => panic!(format!("{}", err)),
+    };
+
+    let mut client = Client::new(FromStr::from_str(coord_addr).unwrap()).unwrap();
+
+    let mut batch = client.batch();
+    for i in 0..100 {
+        batch.put(space_name, format!("user{}", i), NewHyperObject!(
+            "first", format!("First{}", i),
+            "age", i as i64,
+        ));
+    }
+    for res in batch.execute().into_iter() {
+        assert!(res.unwrap().is_none());
+    }
+
+    let mut batch = client.batch();
+    for i in 0..100 {
+        batch.get(space_name, format!("user{}", i));
+    }
+    batch.get(space_name, "nobody");
+    let results = batch.execute();
+    assert_eq!(results.len(), 101);
+    for (i, res) in results.into_iter().enumerate() {
+        if i == 100 {
+            assert_eq!(res.unwrap_err().status, HYPERDEX_CLIENT_NOTFOUND);
+        } else {
+            let age: i64 = res.unwrap().unwrap().get("age").unwrap();
+            assert_eq!(age, i as i64);
+        }
+    }
+
+    admin.remove_space(space_name).unwrap();
+}
+
+#[test]
+fn test_get_many() {
+    let admin = Admin::new(FromStr::from_str(coord_addr).unwrap()).unwrap();
+    match admin.add_space(space_desc) {
+        Ok(()) => (),
+        Err(err) => panic!(format!("{}", err)),
+    };
+
+    let mut client = Client::new(FromStr::from_str(coord_addr).unwrap()).unwrap();
+    for name in vec!("derek", "robert") {
+        client.put(space_name, name, NewHyperObject!(
+            "first", name,
+            "age", 20,
+        )).unwrap();
+    }
+
+    let res = client.get_many(space_name, vec!("derek", "robert", "emin"));
+    assert_eq!(res.len(), 3);
+    let first: Vec<u8> = res[&"derek"].as_ref().unwrap().get("first").unwrap();
+    assert_eq!(first, "derek".as_bytes());
+    assert!(res[&"robert"].is_ok());
+    assert_eq!(res[&"emin"].as_ref().unwrap_err().status, HYPERDEX_CLIENT_NOTFOUND);
+
+    let res = client.get_partial_many(space_name, vec!("derek"), vec!("age"));
+    let obj = res[&"derek"].as_ref().unwrap();
+    assert!(obj.map.contains_key("age"));
+    assert!(!obj.map.contains_key("first"));
+
+    admin.remove_space(space_name).unwrap();
+}
+
+#[test]
+fn test_memory_store() {
+    let mut store = memory_store(space_desc);
+    assert_eq!(store.add_space(space_desc).unwrap_err().status, HYPERDEX_ADMIN_DUPLICATE);
+
+    store.put(space_name, "derek", NewHyperObject!(
+        "first", "Derek",
+        "age", 20,
+    )).unwrap();
+    store.put(space_name, "robert", NewHyperObject!(
+        "first", "Robert",
+        "age", 30,
+    )).unwrap();
+
+    let obj = store.get(space_name, "derek").unwrap();
+    let last: Vec<u8> = obj.get("last").unwrap();
+    assert_eq!(last, "".as_bytes());
+    assert_eq!(store.get(space_name, "emin").unwrap_err().status, HYPERDEX_CLIENT_NOTFOUND);
+    assert_eq!(store.put(space_name, "derek", NewHyperObject!("age", "old",)).unwrap_err().status,
+               HYPERDEX_CLIENT_WRONGTYPE);
+    assert_eq!(store.put(space_name, "derek", NewHyperObject!("height", 180,)).unwrap_err().status,
+               HYPERDEX_CLIENT_UNKNOWNATTR);
+    assert_eq!(store.put_if_not_exist(space_name, "derek", HyperObject::new()).unwrap_err().status,
+               HYPERDEX_CLIENT_CMPFAIL);
+
+    store.atomic_add(space_name, "derek", NewHyperObject!("age", 5,)).unwrap();
+    let age: i64 = store.get(space_name, "derek").unwrap().get("age").unwrap();
+    assert_eq!(age, 25);
+    assert_eq!(store.atomic_add(space_name, "emin", NewHyperObject!("age", 5,)).unwrap_err().status,
+               HYPERDEX_CLIENT_NOTFOUND);
+
+    let found: Vec<HyperObject> = store.search(space_name, vec!(HyperPredicate::new("age", GreaterEqual, 25)))
+                                       .iter().map(|r| r.unwrap()).collect();
+    assert_eq!(found.len(), 2);
+    let username: Vec<u8> = found[0].get("username").unwrap();
+    assert_eq!(username, "derek".as_bytes());
+    assert_eq!(store.count(space_name, vec!(HyperPredicate::new("age", GreaterEqual, 25))).unwrap(), 2);
+
+    let oldest: Vec<HyperObject> = store.sorted_search(space_name, vec!(), "age", 1, true)
+                                        .iter().map(|r| r.unwrap()).collect();
+    let first: Vec<u8> = oldest[0].get("first").unwrap();
+    assert_eq!(first, "Robert".as_bytes());
+
+    store.del(space_name, "derek").unwrap();
+    assert_eq!(store.del(space_name, "derek").unwrap_err().status, HYPERDEX_CLIENT_NOTFOUND);
+    store.remove_space(space_name).unwrap();
+}
+
+fn birthday<C: KeyValueStore>(store: &mut C, username: &str) -> Result<i64, HyperError> {
+    try!(store.atomic_add(space_name, username, NewHyperObject!("age", 1,)));
+    let obj = try!(store.get(space_name, username));
+    Ok(obj.get("age").unwrap())
+}
+
+#[test]
+fn test_key_value_store() {
+    let mut store = testing::MemoryStore::new();
+    store.add_space(space_desc).unwrap();
+    store.put(space_name, "derek", NewHyperObject!("age", 20,)).unwrap();
+
+    assert_eq!(birthday(&mut store, "derek").unwrap(), 21);
+    assert_eq!(birthday(&mut store, "emin").unwrap_err().status, HYPERDEX_CLIENT_NOTFOUND);
+
+    let res = KeyValueStore::get_many(&mut store, space_name, vec!("derek", "emin"));
+    assert!(res[&"derek"].is_ok());
+    assert!(res[&"emin"].is_err());
+}
+
+#[test]
+fn test_export_and_import() {
+    let mut store = testing::MemoryStore::new();
+    store.add_space(space_desc).unwrap();
+    store.put(space_name, "derek", NewHyperObject!("first", "Derek", "age", 20,)).unwrap();
+    store.put(space_name, "robert", NewHyperObject!("first", "Robert", "age", 30,)).unwrap();
+
+    let mut dump = Vec::new();
+    assert_eq!(backup::export(&mut store, space_name, &mut dump).unwrap(), 2);
+    let text = String::from_utf8(dump).unwrap();
+    assert_eq!(text.lines().next().unwrap(),
+               r#"{"attributes":{"age":{"int":20},"first":{"string":"Derek"},"last":{"string":""}},"key":{"string":"derek"}}"#);
+
+    let mut copy = testing::MemoryStore::new();
+    copy.add_space(space_desc).unwrap();
+    copy.put(space_name, "derek", NewHyperObject!("age", 99,)).unwrap();
+    let mut calls = 0;
+    let progress = backup::import(&mut copy, space_name, text.as_bytes(), backup::ImportMode::KeepExisting, 1,
+                                  |_| calls += 1).unwrap();
+    assert_eq!(progress, backup::ImportProgress { read: 2, written: 1, skipped: 1 });
+    assert_eq!(calls, 2);
+    let age: i64 = copy.get(space_name, "derek").unwrap().get("age").unwrap();
+    assert_eq!(age, 99);
+
+    let progress = backup::import(&mut copy, space_name, text.as_bytes(), backup::ImportMode::Overwrite, 8,
+                                  |_| ()).unwrap();
+    assert_eq!(progress.written, 2);
+    assert_eq!(copy.get(space_name, "derek").unwrap(), store.get(space_name, "derek").unwrap());
+
+    let bad = format!("{}\n{{\"key\": {{\"string\": \"emin\"}}}}\n", text.trim());
+    match backup::import(&mut copy, space_name, bad.as_bytes(), backup::ImportMode::Overwrite, 8, |_| ()) {
+        Err(backup::BackupError::Record { line, .. }) => assert_eq!(line, 3),
+        other => panic!("expected a bad record, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_csv_import() {
+    let config = "
+cluster 1
+space 3 people
+  fault_tolerance 0
+  schema
+    attribute id string
+    attribute name string
+    attribute age int64
+    attribute score HYPERDATATYPE_FLOAT
+    attribute tags list(string)
+    attribute counts map(string, int64)
+  subspace 4
+";
+    let schemas = schema::parse_config(config).unwrap();
+    assert_eq!(schemas.len(), 1);
+    let people = schemas[0].clone();
+    assert_eq!(people.key, "id");
+    assert_eq!(people.datatype("score"), Some(schema::Datatype::Float));
+    assert_eq!(people.datatype("counts").unwrap().to_string(), "map(string, int)");
+
+    let mut store = memory_store("space people key id attributes name, int age, float score, list(string) tags, \
+                                  map(string, int) counts");
+    let data = "id,name,age,tags,counts\n\
+                ann,\"Smith, Ann\",31,red;blue,home=2;work=3\n\
+                bob,Bob,old,,\n\
+                \n\
+                ,Nobody,1,,\n\
+                carl,\"Carl\n\"\"CJ\"\"\",40,,home\n";
+    let importer = csv::CsvImporter::new(people.clone());
+    let report = importer.import(&mut store, data.as_bytes()).unwrap();
+    assert_eq!(report.written, 1);
+    let lines: Vec<u64> = report.errors.iter().map(|e| e.line).collect();
+    assert_eq!(lines, vec!(3, 5, 6));
+
+    let ann = store.get("people", "ann").unwrap();
+    let name: Vec<u8> = ann.get("name").unwrap();
+    assert_eq!(name, "Smith, Ann".as_bytes());
+    let tags: Vec<Vec<u8>> = ann.get("tags").unwrap();
+    assert_eq!(tags, vec!("red".as_bytes().to_vec(), "blue".as_bytes().to_vec()));
+    let counts: HashMap<Vec<u8>, i64> = ann.get("counts").unwrap();
+    assert_eq!(counts[&"work".as_bytes().to_vec()], 3);
+
+    assert_eq!(importer.convert(schema::Datatype::Map(schema::Primitive::Int, schema::Primitive::String), "1=a=b")
+                       .unwrap(), HyperMapIntString(vec!((1, "a=b".as_bytes().to_vec())).into_iter().collect()));
+    // Runs of blank lines are skipped without growing the stack
+    let blanks = format!("id,name\n{}dan,Dan\n", "\n".repeat(10000));
+    assert_eq!(importer.import(&mut store, blanks.as_bytes()).unwrap().written, 1);
+    match importer.import(&mut store, "id,height\n".as_bytes()) {
+        Err(csv::CsvError::Header(_)) => (),
+        other => panic!("expected a bad header, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_typed_keys() {
+    let mut store = memory_store("space readings key int sensor attributes float value");
+    let schema = store.space_schema("readings").unwrap();
+    assert_eq!(schema.key_type, schema::Datatype::Int);
+    assert_eq!(store.space_schema("nowhere").unwrap_err().status, HYPERDEX_ADMIN_NOTFOUND);
+
+    let key = HyperKey::from_hyper(HyperInt(7)).unwrap();
+    assert_eq!(key, HyperKey::Int(7));
+    assert!(key.check(&schema).is_ok());
+    assert_eq!(HyperKey::from("seven").check(&schema).unwrap_err().status, HYPERDEX_CLIENT_WRONGTYPE);
+    assert_eq!(HyperKey::from_hyper(HyperListInt(vec!(7))).unwrap_err().status, HYPERDEX_CLIENT_WRONGTYPE);
+
+    store.put("readings", 7i64, NewHyperObject!("value", 1.5,)).unwrap();
+    store.put("readings", 9i64, NewHyperObject!("value", 2.5,)).unwrap();
+    assert_eq!(store.put("readings", "seven", NewHyperObject!("value", 0.5,)).unwrap_err().status,
+               HYPERDEX_CLIENT_WRONGTYPE);
+
+    let mut found: Vec<(HyperKey, HyperObject)> =
+        store.search_keyed("readings", Vec::new()).iter().map(|res| res.unwrap()).collect();
+    found.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    assert_eq!(found.iter().map(|f| f.0.clone()).collect::<Vec<_>>(), vec!(HyperKey::Int(7), HyperKey::Int(9)));
+    assert!(found[0].1.map.get("sensor").is_none());
+    let value: f64 = found[1].1.get("value").unwrap();
+    assert_eq!(value, 2.5);
+}
+
+#[test]
+fn test_client_checks_declared_key_types() {
+    let admin = Admin::new(FromStr::from_str(coord_addr).unwrap()).unwrap();
+    admin.add_space("space readings key int sensor attributes float value, int count").unwrap();
+
+    // Without a declared schema the client checks nothing, and cannot tell the key apart
+    let mut client = Client::new(FromStr::from_str(coord_addr).unwrap()).unwrap();
+    client.put("readings", 7i64, NewHyperObject!("value", 1.5, "count", 2,)).unwrap();
+    assert_eq!(client.search_keyed("readings", Vec::new()).recv().unwrap().unwrap_err().status,
+               HYPERDEX_CLIENT_UNKNOWNSPACE);
+
+    client.set_schema(admin.space_schema("readings").unwrap());
+    assert_eq!(client.put("readings", "seven", NewHyperObject!("value", 0.5,)).unwrap_err().status,
+               HYPERDEX_CLIENT_WRONGTYPE);
+    assert_eq!(client.get("readings", 7.0).unwrap_err().status, HYPERDEX_CLIENT_WRONGTYPE);
+
+    // The key is taken out by its name
+    let found: Vec<(HyperKey, HyperObject)> =
+        client.search_keyed("readings", Vec::new()).iter().map(|res| res.unwrap()).collect();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].0, HyperKey::Int(7));
+    assert!(found[0].1.map.get("sensor").is_none());
+    let count: i64 = found[0].1.get("count").unwrap();
+    assert_eq!(count, 2);
+
+    assert_eq!(client.search_keyed("nowhere", Vec::new()).recv().unwrap().unwrap_err().status,
+               HYPERDEX_CLIENT_UNKNOWNSPACE);
+    admin.remove_space("readings").unwrap();
+}
+
+#[test]
+fn test_cached_client() {
+    let mut store = memory_store(space_desc);
+    store.put(space_name, "derek", NewHyperObject!("age", 20,)).unwrap();
+    store.put(space_name, "emin", NewHyperObject!("age", 30,)).unwrap();
+    let clock = testing::ManualClock::new();
+    let mut cache = CachedClient::with_clock(store, 1, Duration::from_secs(30), clock.clone());
+
+    let age = |cache: &mut CachedClient<testing::MemoryStore>, key| -> i64 {
+        cache.get(space_name, key).unwrap().get("age").unwrap()
+    };
+    assert_eq!(age(&mut cache, "derek"), 20);
+    assert_eq!(age(&mut cache, "derek"), 20);
+    assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1 });
+
+    // Writes through the cache are seen at once.
+    cache.atomic_add(space_name, "derek", NewHyperObject!("age", 1,)).unwrap();
+    assert_eq!(age(&mut cache, "derek"), 21);
+    assert_eq!(cache.cond_put(space_name, "derek", vec!(HyperPredicate::new("age", Equals, 20)),
+                              NewHyperObject!("age", 0,)).unwrap_err().status,
+               HYPERDEX_CLIENT_CMPFAIL);
+    cache.cond_put(space_name, "derek", vec!(HyperPredicate::new("age", Equals, 21)), NewHyperObject!("age", 22,))
+         .unwrap();
+    assert_eq!(age(&mut cache, "derek"), 22);
+
+    // The least recently used object makes room for the next.
+    assert_eq!(age(&mut cache, "emin"), 30);
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 4 });
+
+    // Writes that bypass the cache show up once the cached copy expires.
+    cache.inner_mut().put(space_name, "emin", NewHyperObject!("age", 31,)).unwrap();
+    assert_eq!(age(&mut cache, "emin"), 30);
+    clock.advance(Duration::from_secs(29));
+    assert_eq!(age(&mut cache, "emin"), 30);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(age(&mut cache, "emin"), 31);
+    assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 5 });
+}
+
+#[test]
+fn test_metrics() {
+    let admin = Admin::new(FromStr::from_str(coord_addr).unwrap()).unwrap();
+    admin.add_space(space_desc).unwrap();
+
+    let recorder = Arc::new(metrics::Recorder::new());
+    let mut client = Client::with_metrics(FromStr::from_str(coord_addr).unwrap(), recorder.clone()).unwrap();
+    client.put(space_name, "derek", NewHyperObject!("age", 20,)).unwrap();
+    client.put(space_name, "emin", NewHyperObject!("age", 30,)).unwrap();
+    assert_eq!(client.get(space_name, "lol").unwrap_err().status, HYPERDEX_CLIENT_NOTFOUND);
+    assert_eq!(client.search(space_name, Vec::new()).iter().count(), 2);
+
+    let puts = recorder.stats(space_name, "put").unwrap();
+    assert_eq!((puts.count, puts.errors, puts.latency.count()), (2, 0, 2));
+    assert!(puts.latency.quantile(0.5) <= puts.latency.quantile(1.0));
+    let gets = recorder.stats(space_name, "get").unwrap();
+    assert_eq!((gets.count, gets.errors), (1, 1));
+    assert_eq!(recorder.stats(space_name, "search").unwrap().count, 1);
+    assert_eq!(recorder.pending(0), 0);
+
+    admin.remove_space(space_name).unwrap();
+}
+
+#[test]
+fn test_update() {
+    let mut store = memory_store("space accounts key owner attributes int balance, int version, first, \
+                                  list(int) history");
+    store.put("accounts", "derek", NewHyperObject!("balance", 100, "first", "Derek",)).unwrap();
+
+    // Lists are left out of the comparison, so the object can be updated although it has one
+    let obj = store.update("accounts", "derek", |obj| -> Result<(), HyperError> {
+        let balance: i64 = obj.get("balance").unwrap();
+        obj.insert("balance", balance - 10);
+        obj.insert("history", vec!(balance));
+        Ok(())
+    }).unwrap();
+    let balance: i64 = obj.get("balance").unwrap();
+    assert_eq!(balance, 90);
+    assert_eq!(store.update("accounts", "emin", |_| Ok::<(), HyperError>(())).unwrap_err().status,
+               HYPERDEX_CLIENT_NOTFOUND);
+
+    // Returning an error gives up without writing
+    let err = store.update("accounts", "derek", |obj| {
+        obj.insert("balance", 0);
+        Err(HyperError { status: HYPERDEX_CLIENT_CMPFAIL, message: "overdrawn".to_string(), location: String::new() })
+    }).unwrap_err();
+    assert_eq!(err.message, "overdrawn");
+    let balance: i64 = store.get("accounts", "derek").unwrap().get("balance").unwrap();
+    assert_eq!(balance, 90);
+
+    // A cached copy that went stale is not used, so the first attempt wins.
+    let mut cache = CachedClient::new(store, 10, Duration::from_secs(60));
+    cache.get("accounts", "derek").unwrap();
+    cache.inner_mut().put("accounts", "derek", NewHyperObject!("balance", 50,)).unwrap();
+    let mut calls = 0;
+    let obj = cache.update("accounts", "derek", |obj| -> Result<(), HyperError> {
+        calls += 1;
+        let balance: i64 = obj.get("balance").unwrap();
+        obj.insert("balance", balance * 2);
+        Ok(())
+    }).unwrap();
+    assert_eq!(calls, 1);
+    let balance: i64 = obj.get("balance").unwrap();
+    assert_eq!(balance, 100);
+    let balance: i64 = cache.get("accounts", "derek").unwrap().get("balance").unwrap();
+    assert_eq!(balance, 100);
+
+    // With a version attribute, only the version is compared, and every update bumps it.
+    let options = UpdateOptions::new().version("version");
+    let obj = cache.update_with("accounts", "derek", &options, |_| Ok::<(), HyperError>(())).unwrap();
+    let version: i64 = obj.get("version").unwrap();
+    assert_eq!(version, 1);
+
+    cache.get("accounts", "derek").unwrap();
+    cache.inner_mut().put("accounts", "derek", NewHyperObject!("version", 7,)).unwrap();
+    let obj = cache.update_with("accounts", "derek", &options.clone().retries(0), |_| Ok::<(), HyperError>(()))
+                   .unwrap();
+    let version: i64 = obj.get("version").unwrap();
+    assert_eq!(version, 8);
+
+    // Without a string, int or float to compare, only a version attribute can guard the write
+    let mut store = memory_store("space tags key id attributes set(string) tags");
+    store.put("tags", "derek", NewHyperObject!("tags", vec!("a".to_string()).into_iter().collect::<BTreeSet<_>>(),))
+         .unwrap();
+    assert_eq!(store.update("tags", "derek", |_| Ok::<(), HyperError>(())).unwrap_err().status, HYPERDEX_CLIENT_WRONGTYPE);
+}
+
+#[test]
+fn test_lock() {
+    use lock::{self, Lock, LockError};
+
+    let mut store = memory_store("space locks key name attributes owner, int expires");
+    let clock = testing::ManualClock::new();
+    let ttl = Duration::from_secs(60);
+    let a = Lock::with_clock("locks", "report", "a", ttl, clock.clone());
+    let b = Lock::with_clock("locks", "report", "b", ttl, clock.clone());
+
+    let lease = a.acquire(&mut store).unwrap();
+    assert_eq!(lease.owner, "a");
+    assert_eq!(lease.expires, lock::timestamp(clock.system_time()) + 60000);
+    match b.acquire(&mut store) {
+        Err(LockError::Held { owner, expires }) => {
+            assert_eq!(owner, "a");
+            assert_eq!(expires, lease.expires);
+        },
+        other => panic!("{:?}", other),
+    }
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(a.renew(&mut store).unwrap().expires, lease.expires + 1000);
+    assert!(a.acquire(&mut store).is_ok());
+    match b.renew(&mut store) { Err(LockError::Lost) => (), other => panic!("{:?}", other) }
+    match b.release(&mut store) { Err(LockError::Lost) => (), other => panic!("{:?}", other) }
+    a.release(&mut store).unwrap();
+    match a.release(&mut store) { Err(LockError::Lost) => (), other => panic!("{:?}", other) }
+
+    // An owner that stops renewing loses the lock to the next one to ask, and not before.
+    let short = Lock::with_clock("locks", "report", "a", Duration::from_millis(10), clock.clone());
+    short.acquire(&mut store).unwrap();
+    clock.advance(Duration::from_millis(9));
+    assert!(b.acquire(&mut store).is_err());
+    clock.advance(Duration::from_millis(1));
+    assert_eq!(b.acquire(&mut store).unwrap().owner, "b");
+    match short.renew(&mut store) { Err(LockError::Lost) => (), other => panic!("{:?}", other) }
+
+    Lock::with_clock("locks", "cleanup", "a", Duration::from_millis(10), clock.clone()).acquire(&mut store).unwrap();
+    assert_eq!(lock::recover_expired_with_clock(&mut store, "locks", &clock).unwrap(), 0);
+    clock.advance(Duration::from_millis(10));
+    assert_eq!(lock::recover_expired_with_clock(&mut store, "locks", &clock).unwrap(), 1);
+    assert_eq!(store.count("locks", Vec::new()).unwrap(), 1);
+    b.release(&mut store).unwrap();
+
+    assert_eq!(lock::timestamp(UNIX_EPOCH + Duration::from_millis(1500)), 1500);
+    assert_eq!(lock::timestamp(UNIX_EPOCH - Duration::from_secs(1)), 0);
+    assert!(lock::now() > 0);
+}
+
+#[test]
+fn test_doc_paths() {
+    let mut store = memory_store("space profiles key username attributes document profile");
+    store.put("profiles", "derek", NewHyperObject!(
+        "profile", Value::from_str(r#"{"name": "Derek", "stats": {"visits": 1}}"#).unwrap(),
+    )).unwrap();
+    store.put("profiles", "robert", NewHyperObject!(
+        "profile", Value::from_str(r#"{"name": "Robert"}"#).unwrap(),
+    )).unwrap();
+
+    let name = DocPath::new("profile").field("name");
+    let visits = DocPath::new("profile").field("stats").field("visits");
+    assert_eq!(visits.to_string(), "profile.stats.visits");
+    assert_eq!(DocPath::from_str("profile.stats.visits").unwrap(), visits);
+    assert!(DocPath::from_str("profile..visits").is_err());
+    assert!(DocPath::from_str("").is_err());
+
+    let found: Vec<HyperObject> = store.search("profiles", vec!(HyperPredicate::new(&name, Equals, "Robert")))
+                                       .iter().map(|r| r.unwrap()).collect();
+    assert_eq!(found.len(), 1);
+    assert_eq!(name.get(&found[0]), Some(HyperString("Robert".as_bytes().to_vec())));
+    assert_eq!(store.count("profiles", vec!(HyperPredicate::new(&visits, GreaterEqual, 1))).unwrap(), 1);
+    assert_eq!(store.count("profiles", vec!(HyperPredicate::new("username.first", Equals, "d")))
+                    .unwrap_err().status, HYPERDEX_CLIENT_UNKNOWNATTR);
+
+    store.atomic_add("profiles", "derek", NewHyperObject!(&visits, 2,)).unwrap();
+    store.atomic_add("profiles", "robert", NewHyperObject!(&visits, 2,)).unwrap();
+    store.string_append("profiles", "derek", NewHyperObject!(&name, " Chiou",)).unwrap();
+    assert_eq!(store.string_append("profiles", "derek", NewHyperObject!(&visits, "x",)).unwrap_err().status,
+               HYPERDEX_CLIENT_WRONGTYPE);
+
+    let obj = store.get_partial("profiles", "derek", vec!(name.clone(), visits.clone())).unwrap();
+    assert_eq!(obj.map.len(), 2);
+    assert_eq!(name.get(&obj), Some(HyperString("Derek Chiou".as_bytes().to_vec())));
+    assert_eq!(visits.get(&obj), Some(HyperInt(3)));
+
+    // Missing fields are created, along with the documents that lead to them
+    let obj = store.get("profiles", "robert").unwrap();
+    assert_eq!(visits.get(&obj), Some(HyperInt(2)));
+}
+
+#[test]
+fn test_serde_documents() {
+    let mut store = memory_store("space profiles key username attributes document profile");
+
+    let mut profile: HashMap<String, Vec<i64>> = HashMap::new();
+    profile.insert("scores".to_string(), vec!(3, 1, 2));
+    store.put("profiles", "derek", NewHyperObject!("profile", Document(profile.clone()).encode().unwrap(),)).unwrap();
+
+    let obj = store.get("profiles", "derek").unwrap();
+    let Document(stored): Document<HashMap<String, Vec<i64>>> = obj.get("profile").unwrap();
+    assert_eq!(stored, profile);
+    let doc: Value = obj.get("profile").unwrap();
+    assert_eq!(doc, Value::from_str(r#"{"scores": [3, 1, 2]}"#).unwrap());
+    let wrong: Result<Document<Vec<String>>, HyperObjectKeyError> = obj.get("profile");
+    match wrong {
+        Err(HyperObjectKeyError::MalformedDocument(msg)) => assert!(msg.contains("sequence")),
+        other => panic!("unexpected {:?}", other.map(|Document(v)| v)),
+    }
+    let err = Document::<Vec<String>>::decode(obj.map["profile"].clone()).unwrap_err();
+    assert_eq!(err.status, HYPERDEX_CLIENT_WRONGTYPE);
+    assert!(err.message.contains("sequence"));
+    assert_eq!(Document::<Vec<String>>::decode(HyperInt(1)).unwrap_err().status, HYPERDEX_CLIENT_WRONGTYPE);
+
+    // Maps need string keys to have a JSON form
+    let mut by_bytes: HashMap<Vec<u8>, i64> = HashMap::new();
+    by_bytes.insert(vec!(1, 2), 3);
+    let err = Document(by_bytes).encode().unwrap_err();
+    assert_eq!(err.status, HYPERDEX_CLIENT_WRONGTYPE);
+    assert!(err.message.contains("key must be a string"));
+}
+
+#[test]
+fn test_predicate_builder() {
+    let mut store = memory_store(space_desc);
+    store.put(space_name, "derek", NewHyperObject!("first", "Derek", "age", 20,)).unwrap();
+    store.put(space_name, "robert", NewHyperObject!("first", "Robert", "age", 30,)).unwrap();
+    store.put(space_name, "dan", NewHyperObject!("first", "Dan", "age", 40,)).unwrap();
+
+    let checks = attr("age").le(25).and(attr("first").regex("^D")).build().unwrap();
+    assert_eq!(checks.len(), 2);
+    assert_eq!(store.count(space_name, checks).unwrap(), 1);
+    assert_eq!(store.count(space_name, attr("age").between(20, 30).build().unwrap()).unwrap(), 2);
+
+    assert_eq!(attr("first").regex("^D").and(attr("age").lt(vec!(1i64, 2))).build().unwrap_err().status,
+               HYPERDEX_CLIENT_WRONGTYPE);
+    assert!(attr("first").length_ge(2).build().is_ok());
+
+    // Predicates built by hand are validated before the search starts
+    let err = store.count(space_name, vec!(HyperPredicate::new("first", LengthEquals, "five"))).unwrap_err();
+    assert_eq!(err.status, HYPERDEX_CLIENT_WRONGTYPE);
+
+    // ...and checked against the schema, even when no object would be looked at
+    let mut empty = memory_store(space_desc);
+    assert_eq!(empty.count(space_name, attr("age").length_eq(2).build().unwrap()).unwrap_err().status,
+               HYPERDEX_CLIENT_WRONGTYPE);
+    assert_eq!(empty.count(space_name, attr("first").lt(3).build().unwrap()).unwrap_err().status,
+               HYPERDEX_CLIENT_WRONGTYPE);
+    assert_eq!(empty.count(space_name, attr("height").eq(3).build().unwrap()).unwrap_err().status,
+               HYPERDEX_CLIENT_UNKNOWNATTR);
+    let schema = store.space_schema(space_name).unwrap();
+    assert_eq!(attr("age").length_eq(2).build_for(&schema).unwrap_err().status, HYPERDEX_CLIENT_WRONGTYPE);
+    assert_eq!(attr("age").regex("^2").build_for(&schema).unwrap_err().status, HYPERDEX_CLIENT_WRONGTYPE);
+    assert!(attr("first").length_eq(5).and(attr("age").ge(20)).build_for(&schema).is_ok());
+    assert!(attr("username").regex("^d").build_for(&schema).is_ok());
+
+    // Alternatives take a search each, and objects matching several are found once
+    let either = attr("age").lt(25).or(attr("first").eq("Dan")).or(attr("first").regex("^D"));
+    assert_eq!(either.build_any().unwrap().len(), 3);
+    let either = attr("age").lt(25).or(attr("first").eq("Dan")).or(attr("first").regex("^D"));
+    let mut keys: Vec<HyperKey> = either.search_keyed(&mut store, space_name).unwrap()
+                                        .into_iter().map(|(key, _)| key).collect();
+    keys.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(keys, vec!(HyperKey::from("dan"), HyperKey::from("derek")));
+    let both = attr("age").ge(30).and(attr("first").eq("Robert").or(attr("first").eq("Dan")));
+    assert_eq!(both.build_any().unwrap().len(), 2);
+    assert_eq!(attr("age").lt(25).or(attr("age").gt(35)).build().unwrap_err().status,
+               HYPERDEX_CLIENT_WRONGTYPE);
+}
+
+#[test]
+fn test_container_predicates() {
+    let mut store = memory_store("space bags key id attributes set(string) tags, list(int) scores, \
+                                  map(float, string) labels");
+    let tags: BTreeSet<String> = vec!("red".to_string(), "blue".to_string()).into_iter().collect();
+    let mut labels: HashMap<F64, Vec<u8>> = HashMap::new();
+    labels.insert(F64(0.5), "half".as_bytes().to_vec());
+    store.put("bags", "a", NewHyperObject!("tags", tags, "scores", vec!(7i64, 3),
+                                           "labels", labels,)).unwrap();
+    store.put("bags", "b", NewHyperObject!("scores", vec!(9i64),)).unwrap();
+
+    let count = |store: &mut testing::MemoryStore, checks: Predicates| store.count("bags", checks.build().unwrap()).unwrap();
+    assert_eq!(count(&mut store, attr("tags").contains("red")), 1);
+    assert_eq!(count(&mut store, attr("tags").contains("green")), 0);
+    assert_eq!(count(&mut store, attr("scores").contains(9)), 1);
+    assert_eq!(count(&mut store, attr("scores").contains_less_than(8)), 1);
+    assert_eq!(count(&mut store, attr("scores").contains_less_than(10)), 2);
+    assert_eq!(count(&mut store, attr("labels").contains(0.5)), 1);
+    assert_eq!(count(&mut store, attr("labels").contains_less_than(0.5)), 0);
+
+    assert_eq!(store.count("bags", attr("scores").contains("9").build().unwrap()).unwrap_err().status,
+               HYPERDEX_CLIENT_WRONGTYPE);
+    assert_eq!(format!("{}", ContainsLessThan), "CONTAINS_LESS_THAN");
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_deprecated_predicate_names() {
+    assert_eq!(HyperPredicateType::EQUALS, Equals);
+    assert_eq!(HyperPredicateType::CONTAINS_LESS_THAN, ContainsLessThan);
+    assert_eq!(HyperPredicateType::LENGTH_GREATER_EQUAL as u32, HYPERPREDICATE_LENGTH_GREATER_EQUAL);
+}
+
+#[test]
+fn test_predicate_encoding() {
+    let preds = vec!(
+        (Fail, HYPERPREDICATE_FAIL), (Equals, HYPERPREDICATE_EQUALS),
+        (LessThan, HYPERPREDICATE_LESS_THAN), (LessEqual, HYPERPREDICATE_LESS_EQUAL),
+        (GreaterEqual, HYPERPREDICATE_GREATER_EQUAL), (GreaterThan, HYPERPREDICATE_GREATER_THAN),
+        (ContainsLessThan, HYPERPREDICATE_CONTAINS_LESS_THAN), (Contains, HYPERPREDICATE_CONTAINS),
+    );
+    // The bytes libhyperdex packs each value into: strings as they are, ints and floats as
+    // little-endian 64-bit words
+    let elems: Vec<(HyperValue, u32, Vec<u8>)> = vec!(
+        (HyperString("a".as_bytes().to_vec()), HYPERDATATYPE_STRING, vec!(0x61)),
+        (HyperString(Vec::new()), HYPERDATATYPE_STRING, vec!()),
+        (HyperInt(-3), HYPERDATATYPE_INT64, vec!(0xfd, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff)),
+        (HyperFloat(2.5), HYPERDATATYPE_FLOAT, vec!(0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x40)),
+    );
+    let mut checks = Vec::new();
+    for &(pred, _) in preds.iter() {
+        for &(ref elem, _, _) in elems.iter() {
+            checks.push(HyperPredicate { attr: "tags".to_string(), value: elem.clone(), predicate: pred });
+        }
+    }
+    checks.push(attr("names").regex("^D").build().unwrap().pop().unwrap());
+    checks.push(attr("names").length_le(4).build().unwrap().pop().unwrap());
+
+    unsafe {
+        let arena = hyperdex_ds_arena_create();
+        let c_checks = convert_predicates(arena, checks.clone()).unwrap();
+        assert_eq!(c_checks.len(), checks.len());
+        for (i, c_check) in c_checks.iter().take(preds.len() * elems.len()).enumerate() {
+            let (_, code) = preds[i / elems.len()];
+            let (_, dt, ref bytes) = elems[i % elems.len()];
+            assert_eq!(CStr::from_ptr(c_check.attr).to_bytes(), "tags".as_bytes());
+            assert_eq!(c_check.predicate, code);
+            assert_eq!(c_check.datatype, dt);
+            assert_eq!(slice::from_raw_parts(c_check.value as *const u8, c_check.value_sz as usize),
+                       &bytes[..]);
+        }
+        let regex = &c_checks[c_checks.len() - 2];
+        assert_eq!((regex.predicate, regex.datatype), (HYPERPREDICATE_REGEX, HYPERDATATYPE_STRING));
+        assert_eq!(slice::from_raw_parts(regex.value as *const u8, regex.value_sz as usize), "^D".as_bytes());
+        let length = &c_checks[c_checks.len() - 1];
+        assert_eq!((length.predicate, length.datatype), (HYPERPREDICATE_LENGTH_LESS_EQUAL, HYPERDATATYPE_INT64));
+        assert_eq!(slice::from_raw_parts(length.value as *const u8, length.value_sz as usize),
+                   &[4, 0, 0, 0, 0, 0, 0, 0][..]);
+        hyperdex_ds_arena_destroy(arena);
+    }
+}
+
+#[test]
+fn test_query_parser() {
+    let checks = query::parse("age <= 25 AND first =~ \"^D\" and length(tags) >= 2").unwrap();
+    assert_eq!(checks, vec!(
+        HyperPredicate::new("age", LessEqual, 25),
+        HyperPredicate::new("first", Regex, "^D"),
+        HyperPredicate::new("tags", LengthGreaterEqual, 2),
+    ));
+    assert_eq!(query::parse("profile.height > -1.5e2 AND tags CONTAINS < \"a\\\"b\"").unwrap(), vec!(
+        HyperPredicate::new("profile.height", GreaterThan, -150.0),
+        HyperPredicate::new("tags", ContainsLessThan, "a\"b"),
+    ));
+    assert_eq!(query::parse("  ").unwrap(), vec!());
+
+    let column = |q: &str| query::parse(q).unwrap_err().column;
+    assert_eq!(column("age <= AND"), 8);
+    assert_eq!(column("age <= 25 first = 1"), 11);
+    assert_eq!(column("age ! 25"), 5);
+    assert_eq!(column("first = \"Derek"), 9);
+    assert_eq!(column("first =~ 5"), 10);
+    assert_eq!(column("length(tags) < 2"), 14);
+    assert_eq!(column("age = 1.2.3"), 7);
+    assert_eq!(format!("{}", query::parse("AND").unwrap_err()), "column 1: expected an attribute, found \"AND\"");
+
+    let mut store = testing::MemoryStore::new();
+    store.add_space(space_desc).unwrap();
+    store.put(space_name, "derek", NewHyperObject!("first", "Derek", "age", 20,)).unwrap();
+    store.put(space_name, "dan", NewHyperObject!("first", "Dan", "age", 40,)).unwrap();
+    assert_eq!(store.count(space_name, query::parse("age <= 25 AND first =~ \"^D\"").unwrap()).unwrap(), 1);
+}
+
+/// A small deterministic generator, so that codec failures can be reproduced.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn int(&mut self) -> i64 {
+        self.next() as i64
+    }
+
+    fn float(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64 * 2e6 - 1e6
+    }
+
+    fn string(&mut self) -> Vec<u8> {
+        let len = self.next() % 16;
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+
+    fn len(&mut self) -> usize {
+        (self.next() % 6) as usize
+    }
+}
+
+fn random_value(rng: &mut XorShift, variant: usize) -> HyperValue {
+    let n = rng.len();
+    match variant {
+        0 => HyperString(rng.string()),
+        1 => HyperInt(rng.int()),
+        2 => HyperFloat(rng.float()),
+        3 => HyperListString((0..n).map(|_| rng.string()).collect()),
+        4 => HyperListInt((0..n).map(|_| rng.int()).collect()),
+        5 => HyperListFloat((0..n).map(|_| rng.float()).collect()),
+        6 => HyperSetString((0..n).map(|_| rng.string()).collect()),
+        7 => HyperSetInt((0..n).map(|_| rng.int()).collect()),
+        8 => HyperSetFloat((0..n).map(|_| F64(rng.float())).collect()),
+        9 => HyperMapStringString((0..n).map(|_| (rng.string(), rng.string())).collect()),
+        10 => HyperMapStringInt((0..n).map(|_| (rng.string(), rng.int())).collect()),
+        11 => HyperMapStringFloat((0..n).map(|_| (rng.string(), rng.float())).collect()),
+        12 => HyperMapIntString((0..n).map(|_| (rng.int(), rng.string())).collect()),
+        13 => HyperMapIntInt((0..n).map(|_| (rng.int(), rng.int())).collect()),
+        14 => HyperMapIntFloat((0..n).map(|_| (rng.int(), rng.float())).collect()),
+        15 => HyperMapFloatString((0..n).map(|_| (F64(rng.float()), rng.string())).collect()),
+        16 => HyperMapFloatInt((0..n).map(|_| (F64(rng.float()), rng.int())).collect()),
+        17 => HyperMapFloatFloat((0..n).map(|_| (F64(rng.float()), rng.float())).collect()),
+        _ => HyperDocument(Value::from_str(&format!("{{\"n\": {}, \"xs\": [{}, {}]}}",
+                                                   rng.int(), rng.float(), rng.int())).unwrap()),
+    }
+}
+
+fn c_str(s: &[u8]) -> *const ::libc::c_char {
+    s.as_ptr() as *const ::libc::c_char
+}
+
+/// Packs `val` with libhyperdex's own packers and containers, as the reference for `codec`.
+/// Returns `None` for documents, which libhyperdex has no packer for.
+unsafe fn libhyperdex_encode(val: &HyperValue) -> Option<(Vec<u8>, u32)> {
+    let arena = hyperdex_ds_arena_create();
+    let mut st = 0;
+    let mut value: *const ::libc::c_char = ptr::null();
+    let mut sz = 0;
+    let mut dt = 0;
+    let mut packed = [0u8; 8];
+
+    // Each element must go in, whatever the container
+    macro_rules! fill {
+        ($container: expr, $finalize: ident, $items: expr, |$c: ident, $x: pat| $insert: expr) => {{
+            let $c = $container;
+            for $x in $items {
+                assert_eq!($insert, 0, "inserting into a container failed with {}", st);
+            }
+            $finalize($c, &mut st, &mut value, &mut sz, &mut dt)
+        }}
+    }
+
+    let res = match *val {
+        HyperString(ref x) => {
+            dt = HYPERDATATYPE_STRING;
+            hyperdex_ds_copy_string(arena, c_str(x), x.len() as u64, &mut st, &mut value, &mut sz)
+        },
+        HyperInt(x) => {
+            dt = HYPERDATATYPE_INT64;
+            hyperdex_ds_pack_int(x, packed.as_mut_ptr() as *mut ::libc::c_char);
+            value = c_str(&packed);
+            sz = 8;
+            0
+        },
+        HyperFloat(x) => {
+            dt = HYPERDATATYPE_FLOAT;
+            hyperdex_ds_pack_float(x, packed.as_mut_ptr() as *mut ::libc::c_char);
+            value = c_str(&packed);
+            sz = 8;
+            0
+        },
+        HyperListString(ref xs) => fill!(hyperdex_ds_allocate_list(arena), hyperdex_ds_list_finalize, xs.iter(),
+            |l, x| hyperdex_ds_list_append_string(l, c_str(x), x.len() as u64, &mut st)),
+        HyperListInt(ref xs) => fill!(hyperdex_ds_allocate_list(arena), hyperdex_ds_list_finalize, xs.iter(),
+            |l, &x| hyperdex_ds_list_append_int(l, x, &mut st)),
+        HyperListFloat(ref xs) => fill!(hyperdex_ds_allocate_list(arena), hyperdex_ds_list_finalize, xs.iter(),
+            |l, &x| hyperdex_ds_list_append_float(l, x, &mut st)),
+        HyperSetString(ref xs) => fill!(hyperdex_ds_allocate_set(arena), hyperdex_ds_set_finalize, xs.iter(),
+            |s, x| hyperdex_ds_set_insert_string(s, c_str(x), x.len() as u64, &mut st)),
+        HyperSetInt(ref xs) => fill!(hyperdex_ds_allocate_set(arena), hyperdex_ds_set_finalize, xs.iter(),
+            |s, &x| hyperdex_ds_set_insert_int(s, x, &mut st)),
+        HyperSetFloat(ref xs) => fill!(hyperdex_ds_allocate_set(arena), hyperdex_ds_set_finalize, xs.iter(),
+            |s, &F64(x)| hyperdex_ds_set_insert_float(s, x, &mut st)),
+        HyperMapStringString(ref xs) => fill!(hyperdex_ds_allocate_map(arena), hyperdex_ds_map_finalize, xs.iter(),
+            |m, (k, v)| hyperdex_ds_map_insert_key_string(m, c_str(k), k.len() as u64, &mut st) |
+                        hyperdex_ds_map_insert_val_string(m, c_str(v), v.len() as u64, &mut st)),
+        HyperMapStringInt(ref xs) => fill!(hyperdex_ds_allocate_map(arena), hyperdex_ds_map_finalize, xs.iter(),
+            |m, (k, &v)| hyperdex_ds_map_insert_key_string(m, c_str(k), k.len() as u64, &mut st) |
+                         hyperdex_ds_map_insert_val_int(m, v, &mut st)),
+        HyperMapStringFloat(ref xs) => fill!(hyperdex_ds_allocate_map(arena), hyperdex_ds_map_finalize, xs.iter(),
+            |m, (k, &v)| hyperdex_ds_map_insert_key_string(m, c_str(k), k.len() as u64, &mut st) |
+                         hyperdex_ds_map_insert_val_float(m, v, &mut st)),
+        HyperMapIntString(ref xs) => fill!(hyperdex_ds_allocate_map(arena), hyperdex_ds_map_finalize, xs.iter(),
+            |m, (&k, v)| hyperdex_ds_map_insert_key_int(m, k, &mut st) |
+                         hyperdex_ds_map_insert_val_string(m, c_str(v), v.len() as u64, &mut st)),
+        HyperMapIntInt(ref xs) => fill!(hyperdex_ds_allocate_map(arena), hyperdex_ds_map_finalize, xs.iter(),
+            |m, (&k, &v)| hyperdex_ds_map_insert_key_int(m, k, &mut st) |
+                          hyperdex_ds_map_insert_val_int(m, v, &mut st)),
+        HyperMapIntFloat(ref xs) => fill!(hyperdex_ds_allocate_map(arena), hyperdex_ds_map_finalize, xs.iter(),
+            |m, (&k, &v)| hyperdex_ds_map_insert_key_int(m, k, &mut st) |
+                          hyperdex_ds_map_insert_val_float(m, v, &mut st)),
+        HyperMapFloatString(ref xs) => fill!(hyperdex_ds_allocate_map(arena), hyperdex_ds_map_finalize, xs.iter(),
+            |m, (&F64(k), v)| hyperdex_ds_map_insert_key_float(m, k, &mut st) |
+                              hyperdex_ds_map_insert_val_string(m, c_str(v), v.len() as u64, &mut st)),
+        HyperMapFloatInt(ref xs) => fill!(hyperdex_ds_allocate_map(arena), hyperdex_ds_map_finalize, xs.iter(),
+            |m, (&F64(k), &v)| hyperdex_ds_map_insert_key_float(m, k, &mut st) |
+                               hyperdex_ds_map_insert_val_int(m, v, &mut st)),
+        HyperMapFloatFloat(ref xs) => fill!(hyperdex_ds_allocate_map(arena), hyperdex_ds_map_finalize, xs.iter(),
+            |m, (&F64(k), &v)| hyperdex_ds_map_insert_key_float(m, k, &mut st) |
+                               hyperdex_ds_map_insert_val_float(m, v, &mut st)),
+        HyperDocument(_) => {
+            hyperdex_ds_arena_destroy(arena);
+            return None;
+        },
+    };
+    assert_eq!(res, 0, "packing {:?} failed with {}", val, st);
+    let bytes = slice::from_raw_parts(value as *const u8, sz as usize).to_vec();
+    hyperdex_ds_arena_destroy(arena);
+    Some((bytes, dt))
+}
+
+#[test]
+fn test_codec_matches_libhyperdex() {
+    let mut rng = XorShift(0x2545f4914f6cdd1d);
+    for i in 0..2000 {
+        let val = random_value(&mut rng, i % 19);
+        let encoded = codec::encode(&val);
+        let dt = codec::datatype(&val);
+
+        if let Some((c_encoded, c_dt)) = unsafe { libhyperdex_encode(&val) } {
+            assert_eq!(encoded, c_encoded, "{:?}", val);
+            assert_eq!(dt, c_dt, "{:?}", val);
+        }
+        // The client packs values with the codec too
+        unsafe {
+            let arena = hyperdex_ds_arena_create();
+            let (ptr, sz, client_dt) = convert_type(arena, val.clone()).unwrap();
+            assert_eq!(slice::from_raw_parts(ptr as *const u8, sz as usize), &encoded[..]);
+            assert_eq!(client_dt, dt);
+            hyperdex_ds_arena_destroy(arena);
+        }
+
+        let decoded = codec::decode(dt, &encoded).unwrap();
+        assert_eq!(codec::encode(&decoded), encoded);
+        if dt != HYPERDATATYPE_LIST_GENERIC && dt != HYPERDATATYPE_SET_GENERIC && dt != HYPERDATATYPE_MAP_GENERIC {
+            assert_eq!(decoded, val);
+        }
+    }
+}
+
+#[test]
+fn test_canonical_json() {
+    let mut rng = XorShift(0x9e3779b97f4a7c15);
+    for i in 0..500 {
+        let val = random_value(&mut rng, i % 19);
+        let json = val.to_json();
+        let text = json.to_string();
+        assert_eq!(HyperValue::from_json(&Value::from_str(&text).unwrap()).unwrap(), val);
+    }
+
+    let mut obj = HyperObject::new();
+    obj.insert("name", vec!(0xdeu8, 0xad, 0xbe, 0xef));
+    obj.insert("age", 20);
+    let mut scores: HashMap<i64, f64> = HashMap::new();
+    scores.insert(2, 0.5);
+    scores.insert(1, ::std::f64::INFINITY);
+    obj.insert("scores", scores);
+    assert_eq!(obj.to_json(), Value::from_str(r#"{
+        "name": {"bytes": "3q2+7w=="},
+        "age": {"int": 20},
+        "scores": {"map(int, float)": [[1, "inf"], [2, 0.5]]}
+    }"#).unwrap());
+    assert_eq!(HyperObject::from_json(&obj.to_json()).unwrap(), obj);
+
+    let loose = HyperObject::from_json(&Value::from_str(r#"{"first": "Derek", "age": 20}"#).unwrap()).unwrap();
+    assert_eq!(loose, NewHyperObject!("first", "Derek", "age", 20,));
+    assert!(HyperObject::from_json(&Value::from_str(r#"{"tags": ["a"]}"#).unwrap()).is_err());
+    assert!(HyperObject::from_json(&Value::from_str(r#"{"tags": {"list(int)": ["a"]}}"#).unwrap()).is_err());
+    assert!(HyperValue::from_json(&Value::from_str(r#"{"tuple": []}"#).unwrap()).is_err());
+}
+
+#[test]
+fn test_json_float_roundtrip() {
+    // Values whose shortest form needs all 17 digits, or lies at the edge of the range, are the
+    // ones a fast but inexact parser reads back wrong
+    let floats = [0.1 + 0.2, 1.0 / 3.0, 1e23, 5e-324, ::std::f64::MIN_POSITIVE, ::std::f64::MAX,
+                  ::std::f64::MIN_POSITIVE - 5e-324, -0.0];
+    let mut rng = XorShift(0x2545f4914f6cdd1d);
+    let randoms: Vec<f64> = (0..1000).map(|_| f64::from_bits(rng.next())).filter(|f| f.is_finite()).collect();
+    for f in floats.iter().chain(randoms.iter()) {
+        let text = HyperFloat(*f).to_json().to_string();
+        match HyperValue::from_json(&Value::from_str(&text).unwrap()).unwrap() {
+            HyperFloat(g) => assert_eq!(g.to_bits(), f.to_bits(), "{} read back as {:?}", text, g),
+            other => panic!("{} read back as {:?}", text, other),
+        }
+    }
+}
+
+#[test]
+fn test_codec_rejects_malformed_values() {
+    assert!(codec::decode(HYPERDATATYPE_INT64, &[1, 2, 3]).is_err());
+    assert!(codec::decode(HYPERDATATYPE_FLOAT, &[0; 9]).is_err());
+    assert!(codec::decode(HYPERDATATYPE_LIST_STRING, &[5, 0, 0, 0, b'a']).is_err());
+    assert!(codec::decode(HYPERDATATYPE_MAP_INT64_INT64, &[0; 12]).is_err());
+    assert!(codec::decode(HYPERDATATYPE_LIST_GENERIC, &[0]).is_err());
+    assert!(codec::decode(HYPERDATATYPE_DOCUMENT, b"{").is_err());
+
+    assert_eq!(codec::decode(HYPERDATATYPE_INT64, &[]).unwrap(), HyperInt(0));
+    let set: BTreeSet<i64> = vec!(3, 1, 2).into_iter().collect();
+    assert_eq!(codec::decode(HYPERDATATYPE_SET_INT64, &codec::encode(&HyperSetInt(set.clone()))).unwrap(),
+               HyperSetInt(set));
+}
+
+#[test]
+fn test_borrowed_objects() {
+    let admin = Admin::new(FromStr::from_str(coord_addr).unwrap()).unwrap();
+    match admin.add_space(space_desc) {
+        Ok(()) => (),
+        Err(err) => panic!(format!("{}", err)),
+    };
+
+    let mut client = Client::new(FromStr::from_str(coord_addr).unwrap()).unwrap();
+    client.put(space_name, "derek", NewHyperObject!(
+        "first", "Derek",
+        "age", 20,
+    )).unwrap();
+    client.put(space_name, "robert", NewHyperObject!(
+        "first", "Robert",
+        "age", 25,
+    )).unwrap();
+
+    let owned = client.get(space_name, "derek").unwrap();
+    let obj = client.get_borrowed(space_name, "derek").unwrap();
+    assert_eq!(obj.get_str("first").unwrap(), "Derek");
+    let age: i64 = obj.get("age").unwrap();
+    assert_eq!(age, 20);
+    assert!(obj.get_bytes("age").is_err());
+    assert!(obj.get_value("height").is_err());
+    assert_eq!(obj.to_object().unwrap(), owned);
+    drop(obj);
+
+    let mut total = 0;
+    for res in client.search_borrowed(space_name, vec!(HyperPredicate::new("age", GreaterEqual, 20))) {
+        let obj = res.unwrap();
+        let age: i64 = obj.get("age").unwrap();
+        total += age;
+    }
+    assert_eq!(total, 45);
+
+    admin.remove_space(space_name).unwrap();
+}
+
+/// Lays out attributes the way libhyperdex hands them out, but in memory owned by Rust, so that
+/// decoding can be checked without a server and under Miri or valgrind.
+struct SyntheticAttrs {
+    _names: Vec<CString>,
+    _values: Vec<Vec<u8>>,
+    attrs: Vec<Struct_hyperdex_client_attribute>,
+}
+
+impl SyntheticAttrs {
+    fn new(attrs: Vec<(&str, Vec<u8>, u32)>) -> SyntheticAttrs {
+        let names: Vec<CString> = attrs.iter().map(|&(name, _, _)| CString::new(name).unwrap()).collect();
+        let values: Vec<Vec<u8>> = attrs.iter().map(|&(_, ref value, _)| value.clone()).collect();
+        let c_attrs = attrs.iter().enumerate().map(|(i, &(_, _, dt))| Struct_hyperdex_client_attribute {
+            attr: names[i].as_ptr(),
+            value: if values[i].is_empty() { ptr::null() } else { values[i].as_ptr() as *const i8 },
+            value_sz: values[i].len() as u64,
+            datatype: dt,
+        }).collect();
+        SyntheticAttrs {
+            _names: names,
+            _values: values,
+            attrs: c_attrs,
+        }
+    }
+
+    fn build(&self) -> Result<HyperObject, String> {
+        unsafe { build_hyperobject(self.attrs.as_ptr(), self.attrs.len() as u64) }
+    }
+}
+
+#[test]
+fn test_decode_synthetic_attributes() {
+    let mut map = HashMap::new();
+    map.insert("hello".as_bytes().to_vec(), 7i64);
+    let doc = Value::from_str("{\"a\": [1, 2]}").unwrap();
+    let mut doc_bytes = format!("{}", doc).into_bytes();
+    doc_bytes.push(0);
+
+    let attrs = SyntheticAttrs::new(vec!(
+        ("first", "Derek".as_bytes().to_vec(), HYPERDATATYPE_STRING),
+        ("empty", Vec::new(), HYPERDATATYPE_STRING),
+        ("age", codec::encode(&HyperInt(-20)), HYPERDATATYPE_INT64),
+        ("unset", Vec::new(), HYPERDATATYPE_INT64),
+        ("height", codec::encode(&HyperFloat(1.5)), HYPERDATATYPE_FLOAT),
+        ("names", codec::encode(&HyperListString(vec!(b"a".to_vec(), Vec::new()))), HYPERDATATYPE_LIST_STRING),
+        ("counts", codec::encode(&HyperMapStringInt(map.clone())), HYPERDATATYPE_MAP_STRING_INT64),
+        ("none", Vec::new(), HYPERDATATYPE_SET_GENERIC),
+        ("profile", doc_bytes, HYPERDATATYPE_DOCUMENT),
+    ));
+    let obj = attrs.build().unwrap();
+    assert_eq!(obj.map.len(), 9);
+    assert_eq!(obj.map["first"], HyperString("Derek".as_bytes().to_vec()));
+    assert_eq!(obj.map["empty"], HyperString(Vec::new()));
+    assert_eq!(obj.map["age"], HyperInt(-20));
+    assert_eq!(obj.map["unset"], HyperInt(0));
+    assert_eq!(obj.map["height"], HyperFloat(1.5));
+    assert_eq!(obj.map["names"], HyperListString(vec!(b"a".to_vec(), Vec::new())));
+    assert_eq!(obj.map["counts"], HyperMapStringInt(map));
+    assert_eq!(obj.map["none"], HyperSetString(BTreeSet::new()));
+    assert_eq!(obj.map["profile"], HyperDocument(doc));
+
+    // Views only borrow; the view is forgotten so that destroy_attrs never sees memory owned by Rust
+    let view = BorrowedObject::new(unsafe { AttributeBuffer::new(attrs.attrs.as_ptr(), attrs.attrs.len() as u64) });
+    assert_eq!(view.get_str("first").unwrap(), "Derek");
+    assert_eq!(view.get_bytes("empty").unwrap(), &[][..]);
+    assert_eq!(view.to_object().unwrap(), obj);
+    mem::forget(view);
+
+    assert!(SyntheticAttrs::new(vec!(("age", vec!(1, 2, 3), HYPERDATATYPE_INT64))).build().is_err());
+    assert!(SyntheticAttrs::new(vec!(("x", vec!(9, 0, 0, 0), HYPERDATATYPE_LIST_STRING))).build().is_err());
+    assert!(SyntheticAttrs::new(vec!(("x", vec!(1), 12345))).build().is_err());
+    assert_eq!(SyntheticAttrs::new(vec!()).build().unwrap(), HyperObject::new());
+}
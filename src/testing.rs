@@ -0,0 +1,1307 @@
+//! Test doubles for code that talks to HyperDex.
+
+use std::collections::{BTreeMap, HashMap};
+use std::cmp::Ordering;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant, SystemTime};
+
+use eventual::Future;
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use cache::Clock;
+use common::*;
+use hyperdex::*;
+use hyperdex_admin::*;
+use hyperdex_client::*;
+use client_types::*;
+use client_types::HyperValue::*;
+use doc_path::{DocPath, hyper_to_json, json_to_hyper};
+use schema::SpaceSchema;
+use store::{AsyncKeyValueStore, KeyValueStore};
+
+/// An in-memory stand-in for a HyperDex cluster.
+///
+/// `MemoryStore` offers the same operations as `Client` with the same semantics: objects are
+/// type checked against their space's schema, unset attributes read back as their type's
+/// default, and failures carry the same `HYPERDEX_CLIENT_*` status codes a real cluster would
+/// return.  Spaces are created from the same descriptions that `Admin::add_space` accepts.
+/// The `async_*` operations are there too, and return futures that have already completed.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate hyperdex;
+/// # use hyperdex::*;
+/// # use hyperdex::HyperPredicateType::*;
+/// # use hyperdex::testing::MemoryStore;
+/// # fn main() {
+/// let mut store = MemoryStore::new();
+/// store.add_space("space contacts key username attributes first, last, int age").unwrap();
+/// store.put("contacts", "derek", NewHyperObject!("first", "Derek", "age", 20,)).unwrap();
+/// assert_eq!(store.count("contacts", vec!(HyperPredicate::new("age", LessEqual, 25))).unwrap(), 1);
+/// # }
+/// ```
+pub struct MemoryStore {
+    spaces: HashMap<String, Space>,
+}
+
+struct Space {
+    name: String,
+    key_name: String,
+    key_type: Enum_hyperdatatype,
+    attrs: Vec<(String, Enum_hyperdatatype)>,
+    objects: BTreeMap<MemKey, HashMap<String, HyperValue>>,
+}
+
+/// The key of a stored object.  HyperDex only allows strings, ints and floats as keys.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum MemKey {
+    Str(Vec<u8>),
+    Int(i64),
+    Float(F64),
+}
+
+impl MemKey {
+    fn to_hyper(&self) -> HyperValue {
+        match *self {
+            MemKey::Str(ref s) => HyperString(s.clone()),
+            MemKey::Int(i) => HyperInt(i),
+            MemKey::Float(F64(f)) => HyperFloat(f),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Arith {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    And,
+    Or,
+    Xor,
+}
+
+fn error(status: u32, message: String) -> HyperError {
+    HyperError {
+        status: status,
+        message: message,
+        location: String::new(),
+    }
+}
+
+fn wrong_type(expected: Enum_hyperdatatype, got: Enum_hyperdatatype) -> HyperError {
+    error(HYPERDEX_CLIENT_WRONGTYPE,
+          format!("expected a {} but got a {}", type_name(expected), type_name(got)))
+}
+
+fn space_name<S>(space: S) -> String where S: ToCStr {
+    String::from_utf8_lossy(space.to_c_str().as_bytes()).into_owned()
+}
+
+/* Datatypes */
+
+fn kind(dt: Enum_hyperdatatype) -> Enum_hyperdatatype {
+    dt & 0xffc0
+}
+
+fn is_list(dt: Enum_hyperdatatype) -> bool {
+    kind(dt) == HYPERDATATYPE_LIST_GENERIC
+}
+
+fn is_set(dt: Enum_hyperdatatype) -> bool {
+    kind(dt) == HYPERDATATYPE_SET_GENERIC
+}
+
+fn is_map(dt: Enum_hyperdatatype) -> bool {
+    kind(dt) == HYPERDATATYPE_MAP_GENERIC
+}
+
+/// The type of the elements of a list or set type.
+fn elem_type(dt: Enum_hyperdatatype) -> Enum_hyperdatatype {
+    HYPERDATATYPE_GENERIC + (dt - kind(dt))
+}
+
+fn map_key_type(dt: Enum_hyperdatatype) -> Enum_hyperdatatype {
+    HYPERDATATYPE_GENERIC + ((dt >> 3) & 7)
+}
+
+fn map_val_type(dt: Enum_hyperdatatype) -> Enum_hyperdatatype {
+    HYPERDATATYPE_GENERIC + (dt & 7)
+}
+
+fn type_name(dt: Enum_hyperdatatype) -> String {
+    match dt {
+        HYPERDATATYPE_STRING => "string".to_string(),
+        HYPERDATATYPE_INT64 => "int".to_string(),
+        HYPERDATATYPE_FLOAT => "float".to_string(),
+        HYPERDATATYPE_DOCUMENT => "document".to_string(),
+        _ if is_list(dt) => format!("list({})", type_name(elem_type(dt))),
+        _ if is_set(dt) => format!("set({})", type_name(elem_type(dt))),
+        _ if is_map(dt) => format!("map({}, {})", type_name(map_key_type(dt)), type_name(map_val_type(dt))),
+        _ => format!("datatype {}", dt),
+    }
+}
+
+fn datatype_of(val: &HyperValue) -> Enum_hyperdatatype {
+    match *val {
+        HyperString(_) => HYPERDATATYPE_STRING,
+        HyperInt(_) => HYPERDATATYPE_INT64,
+        HyperFloat(_) => HYPERDATATYPE_FLOAT,
+        HyperListString(_) => HYPERDATATYPE_LIST_STRING,
+        HyperListInt(_) => HYPERDATATYPE_LIST_INT64,
+        HyperListFloat(_) => HYPERDATATYPE_LIST_FLOAT,
+        HyperSetString(_) => HYPERDATATYPE_SET_STRING,
+        HyperSetInt(_) => HYPERDATATYPE_SET_INT64,
+        HyperSetFloat(_) => HYPERDATATYPE_SET_FLOAT,
+        HyperMapStringString(_) => HYPERDATATYPE_MAP_STRING_STRING,
+        HyperMapStringInt(_) => HYPERDATATYPE_MAP_STRING_INT64,
+        HyperMapStringFloat(_) => HYPERDATATYPE_MAP_STRING_FLOAT,
+        HyperMapIntString(_) => HYPERDATATYPE_MAP_INT64_STRING,
+        HyperMapIntInt(_) => HYPERDATATYPE_MAP_INT64_INT64,
+        HyperMapIntFloat(_) => HYPERDATATYPE_MAP_INT64_FLOAT,
+        HyperMapFloatString(_) => HYPERDATATYPE_MAP_FLOAT_STRING,
+        HyperMapFloatInt(_) => HYPERDATATYPE_MAP_FLOAT_INT64,
+        HyperMapFloatFloat(_) => HYPERDATATYPE_MAP_FLOAT_FLOAT,
+        HyperDocument(_) => HYPERDATATYPE_DOCUMENT,
+    }
+}
+
+fn default_value(dt: Enum_hyperdatatype) -> HyperValue {
+    match dt {
+        HYPERDATATYPE_STRING => HyperString(Vec::new()),
+        HYPERDATATYPE_INT64 => HyperInt(0),
+        HYPERDATATYPE_FLOAT => HyperFloat(0.0),
+        HYPERDATATYPE_DOCUMENT => HyperDocument(Value::Object(Map::new())),
+        _ if is_list(dt) => build_list(dt, Vec::new()),
+        _ if is_set(dt) => build_set(dt, Vec::new()),
+        _ if is_map(dt) => build_map(dt, Vec::new()),
+        _ => panic!("no default for datatype {}", dt),
+    }
+}
+
+fn length(val: &HyperValue) -> Option<usize> {
+    match *val {
+        HyperString(ref s) => Some(s.len()),
+        HyperListString(ref l) => Some(l.len()),
+        HyperListInt(ref l) => Some(l.len()),
+        HyperListFloat(ref l) => Some(l.len()),
+        HyperSetString(ref s) => Some(s.len()),
+        HyperSetInt(ref s) => Some(s.len()),
+        HyperSetFloat(ref s) => Some(s.len()),
+        HyperMapStringString(ref m) => Some(m.len()),
+        HyperMapStringInt(ref m) => Some(m.len()),
+        HyperMapStringFloat(ref m) => Some(m.len()),
+        HyperMapIntString(ref m) => Some(m.len()),
+        HyperMapIntInt(ref m) => Some(m.len()),
+        HyperMapIntFloat(ref m) => Some(m.len()),
+        HyperMapFloatString(ref m) => Some(m.len()),
+        HyperMapFloatInt(ref m) => Some(m.len()),
+        HyperMapFloatFloat(ref m) => Some(m.len()),
+        HyperInt(_) | HyperFloat(_) | HyperDocument(_) => None,
+    }
+}
+
+/// Checks that `val` can be stored in an attribute of type `dt`.
+///
+/// Like the real server, an empty container is accepted for any container type of the same
+/// kind, since the wire format can't tell empty lists of strings and ints apart.
+fn coerce(dt: Enum_hyperdatatype, val: HyperValue) -> Result<HyperValue, HyperError> {
+    let vdt = datatype_of(&val);
+    if vdt == dt {
+        Ok(val)
+    } else if (is_list(dt) || is_set(dt) || is_map(dt)) && kind(vdt) == kind(dt) && length(&val) == Some(0) {
+        Ok(default_value(dt))
+    } else {
+        Err(wrong_type(dt, vdt))
+    }
+}
+
+/* Taking containers apart and putting them back together */
+
+fn bytes(val: HyperValue) -> Vec<u8> {
+    match val {
+        HyperString(s) => s,
+        _ => panic!("value was type checked as a string"),
+    }
+}
+
+fn int(val: HyperValue) -> i64 {
+    match val {
+        HyperInt(i) => i,
+        _ => panic!("value was type checked as an int"),
+    }
+}
+
+fn float(val: HyperValue) -> f64 {
+    match val {
+        HyperFloat(f) => f,
+        _ => panic!("value was type checked as a float"),
+    }
+}
+
+fn elements(val: HyperValue) -> Vec<HyperValue> {
+    match val {
+        HyperListString(l) => l.into_iter().map(HyperString).collect(),
+        HyperListInt(l) => l.into_iter().map(HyperInt).collect(),
+        HyperListFloat(l) => l.into_iter().map(HyperFloat).collect(),
+        HyperSetString(s) => s.into_iter().map(HyperString).collect(),
+        HyperSetInt(s) => s.into_iter().map(HyperInt).collect(),
+        HyperSetFloat(s) => s.into_iter().map(|F64(f)| HyperFloat(f)).collect(),
+        _ => panic!("value was type checked as a list or set"),
+    }
+}
+
+fn entries(val: HyperValue) -> Vec<(HyperValue, HyperValue)> {
+    match val {
+        HyperMapStringString(m) => m.into_iter().map(|(k, v)| (HyperString(k), HyperString(v))).collect(),
+        HyperMapStringInt(m) => m.into_iter().map(|(k, v)| (HyperString(k), HyperInt(v))).collect(),
+        HyperMapStringFloat(m) => m.into_iter().map(|(k, v)| (HyperString(k), HyperFloat(v))).collect(),
+        HyperMapIntString(m) => m.into_iter().map(|(k, v)| (HyperInt(k), HyperString(v))).collect(),
+        HyperMapIntInt(m) => m.into_iter().map(|(k, v)| (HyperInt(k), HyperInt(v))).collect(),
+        HyperMapIntFloat(m) => m.into_iter().map(|(k, v)| (HyperInt(k), HyperFloat(v))).collect(),
+        HyperMapFloatString(m) => m.into_iter().map(|(F64(k), v)| (HyperFloat(k), HyperString(v))).collect(),
+        HyperMapFloatInt(m) => m.into_iter().map(|(F64(k), v)| (HyperFloat(k), HyperInt(v))).collect(),
+        HyperMapFloatFloat(m) => m.into_iter().map(|(F64(k), v)| (HyperFloat(k), HyperFloat(v))).collect(),
+        _ => panic!("value was type checked as a map"),
+    }
+}
+
+fn build_list(dt: Enum_hyperdatatype, elems: Vec<HyperValue>) -> HyperValue {
+    match elem_type(dt) {
+        HYPERDATATYPE_STRING => HyperListString(elems.into_iter().map(bytes).collect()),
+        HYPERDATATYPE_INT64 => HyperListInt(elems.into_iter().map(int).collect()),
+        _ => HyperListFloat(elems.into_iter().map(float).collect()),
+    }
+}
+
+fn build_set(dt: Enum_hyperdatatype, elems: Vec<HyperValue>) -> HyperValue {
+    match elem_type(dt) {
+        HYPERDATATYPE_STRING => HyperSetString(elems.into_iter().map(bytes).collect()),
+        HYPERDATATYPE_INT64 => HyperSetInt(elems.into_iter().map(int).collect()),
+        _ => HyperSetFloat(elems.into_iter().map(|v| F64(float(v))).collect()),
+    }
+}
+
+fn build_map(dt: Enum_hyperdatatype, entries: Vec<(HyperValue, HyperValue)>) -> HyperValue {
+    let it = entries.into_iter();
+    match dt {
+        HYPERDATATYPE_MAP_STRING_STRING => HyperMapStringString(it.map(|(k, v)| (bytes(k), bytes(v))).collect()),
+        HYPERDATATYPE_MAP_STRING_INT64 => HyperMapStringInt(it.map(|(k, v)| (bytes(k), int(v))).collect()),
+        HYPERDATATYPE_MAP_STRING_FLOAT => HyperMapStringFloat(it.map(|(k, v)| (bytes(k), float(v))).collect()),
+        HYPERDATATYPE_MAP_INT64_STRING => HyperMapIntString(it.map(|(k, v)| (int(k), bytes(v))).collect()),
+        HYPERDATATYPE_MAP_INT64_INT64 => HyperMapIntInt(it.map(|(k, v)| (int(k), int(v))).collect()),
+        HYPERDATATYPE_MAP_INT64_FLOAT => HyperMapIntFloat(it.map(|(k, v)| (int(k), float(v))).collect()),
+        HYPERDATATYPE_MAP_FLOAT_STRING => HyperMapFloatString(it.map(|(k, v)| (F64(float(k)), bytes(v))).collect()),
+        HYPERDATATYPE_MAP_FLOAT_INT64 => HyperMapFloatInt(it.map(|(k, v)| (F64(float(k)), int(v))).collect()),
+        _ => HyperMapFloatFloat(it.map(|(k, v)| (F64(float(k)), float(v))).collect()),
+    }
+}
+
+/* Operations on values */
+
+fn arith(op: Arith, cur: HyperValue, arg: HyperValue) -> Result<HyperValue, HyperError> {
+    let overflow = || error(HYPERDEX_CLIENT_OVERFLOW, "the operation overflowed".to_string());
+    match (cur, arg) {
+        (HyperInt(x), HyperInt(y)) => {
+            let res = match op {
+                Arith::Add => x.checked_add(y),
+                Arith::Sub => x.checked_sub(y),
+                Arith::Mul => x.checked_mul(y),
+                Arith::Div => if y == 0 { None } else { x.checked_div(y) },
+                Arith::Mod => if y == 0 { None } else { x.checked_rem(y) },
+                Arith::And => Some(x & y),
+                Arith::Or => Some(x | y),
+                Arith::Xor => Some(x ^ y),
+            };
+            match res {
+                Some(i) => Ok(HyperInt(i)),
+                None => Err(overflow()),
+            }
+        },
+        (HyperFloat(x), HyperFloat(y)) => {
+            match op {
+                Arith::Add => Ok(HyperFloat(x + y)),
+                Arith::Sub => Ok(HyperFloat(x - y)),
+                Arith::Mul => Ok(HyperFloat(x * y)),
+                Arith::Div => Ok(HyperFloat(x / y)),
+                _ => Err(error(HYPERDEX_CLIENT_WRONGTYPE,
+                               "bitwise and modulo operations need integers".to_string())),
+            }
+        },
+        (cur, arg) => {
+            let cdt = datatype_of(&cur);
+            if cdt == HYPERDATATYPE_INT64 || cdt == HYPERDATATYPE_FLOAT {
+                Err(wrong_type(cdt, datatype_of(&arg)))
+            } else {
+                Err(error(HYPERDEX_CLIENT_WRONGTYPE,
+                          format!("cannot do arithmetic on a {}", type_name(cdt))))
+            }
+        },
+    }
+}
+
+fn concat(cur: HyperValue, arg: HyperValue, prepend: bool) -> Result<HyperValue, HyperError> {
+    match (cur, arg) {
+        (HyperString(mut s), HyperString(a)) => {
+            if prepend {
+                let mut res = a;
+                res.extend(s.into_iter());
+                Ok(HyperString(res))
+            } else {
+                s.extend(a.into_iter());
+                Ok(HyperString(s))
+            }
+        },
+        (cur, arg) => Err(wrong_type(HYPERDATATYPE_STRING,
+                                     if datatype_of(&cur) == HYPERDATATYPE_STRING {
+                                         datatype_of(&arg)
+                                     } else {
+                                         datatype_of(&cur)
+                                     })),
+    }
+}
+
+fn compare(a: &HyperValue, b: &HyperValue) -> Result<Ordering, HyperError> {
+    match (a, b) {
+        (&HyperString(ref x), &HyperString(ref y)) => Ok(x.cmp(y)),
+        (&HyperInt(x), &HyperInt(y)) => Ok(x.cmp(&y)),
+        (&HyperFloat(x), &HyperFloat(y)) => Ok(F64(x).cmp(&F64(y))),
+        _ => {
+            let adt = datatype_of(a);
+            if adt == HYPERDATATYPE_STRING || adt == HYPERDATATYPE_INT64 || adt == HYPERDATATYPE_FLOAT {
+                Err(wrong_type(adt, datatype_of(b)))
+            } else {
+                Err(error(HYPERDEX_CLIENT_WRONGTYPE, format!("a {} has no ordering", type_name(adt))))
+            }
+        },
+    }
+}
+
+fn check(val: &HyperValue, pred: &HyperPredicate) -> Result<bool, HyperError> {
+    let dt = datatype_of(val);
+    match pred.predicate {
+        HyperPredicateType::Fail => Ok(false),
+        HyperPredicateType::Equals => {
+            let expected = try!(coerce(dt, pred.value.clone()));
+            Ok(*val == expected)
+        },
+        HyperPredicateType::LessThan => Ok(try!(compare(val, &pred.value)) == Ordering::Less),
+        HyperPredicateType::LessEqual => Ok(try!(compare(val, &pred.value)) != Ordering::Greater),
+        HyperPredicateType::GreaterEqual => Ok(try!(compare(val, &pred.value)) != Ordering::Less),
+        HyperPredicateType::GreaterThan => Ok(try!(compare(val, &pred.value)) == Ordering::Greater),
+        HyperPredicateType::Regex => {
+            match (val, &pred.value) {
+                (&HyperString(ref s), &HyperString(ref re)) => {
+                    match Regex::new(&String::from_utf8_lossy(re)) {
+                        Ok(re) => Ok(re.is_match(&String::from_utf8_lossy(s))),
+                        Err(err) => Err(error(HYPERDEX_CLIENT_WRONGTYPE,
+                                              format!("invalid regular expression: {}", err))),
+                    }
+                },
+                _ => Err(wrong_type(HYPERDATATYPE_STRING,
+                                    if dt == HYPERDATATYPE_STRING { datatype_of(&pred.value) } else { dt })),
+            }
+        },
+        HyperPredicateType::LengthEquals |
+        HyperPredicateType::LengthLessEqual |
+        HyperPredicateType::LengthGreaterEqual => {
+            let len = match length(val) {
+                Some(len) => len as i64,
+                None => return Err(error(HYPERDEX_CLIENT_WRONGTYPE,
+                                         format!("a {} has no length", type_name(dt)))),
+            };
+            let bound = match pred.value {
+                HyperInt(i) => i,
+                ref other => return Err(wrong_type(HYPERDATATYPE_INT64, datatype_of(other))),
+            };
+            Ok(match pred.predicate {
+                HyperPredicateType::LengthEquals => len == bound,
+                HyperPredicateType::LengthLessEqual => len <= bound,
+                _ => len >= bound,
+            })
+        },
+        HyperPredicateType::Contains => {
+            if is_list(dt) || is_set(dt) {
+                let elem = try!(coerce(elem_type(dt), pred.value.clone()));
+                Ok(elements(val.clone()).contains(&elem))
+            } else if is_map(dt) {
+                let key = try!(coerce(map_key_type(dt), pred.value.clone()));
+                Ok(entries(val.clone()).iter().any(|&(ref k, _)| *k == key))
+            } else {
+                Err(error(HYPERDEX_CLIENT_WRONGTYPE,
+                          format!("a {} has no elements", type_name(dt))))
+            }
+        },
+        HyperPredicateType::ContainsLessThan => {
+            let elems = if is_list(dt) || is_set(dt) {
+                elements(val.clone())
+            } else if is_map(dt) {
+                entries(val.clone()).into_iter().map(|(k, _)| k).collect()
+            } else {
+                return Err(error(HYPERDEX_CLIENT_WRONGTYPE,
+                                 format!("a {} has no elements", type_name(dt))));
+            };
+            for elem in elems.iter() {
+                if try!(compare(elem, &pred.value)) == Ordering::Less {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        },
+    }
+}
+
+/* Space descriptions */
+
+fn tokenize(desc: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut cur = String::new();
+    for c in desc.chars() {
+        if c.is_whitespace() || c == ',' || c == '(' || c == ')' {
+            if !cur.is_empty() {
+                tokens.push(cur.clone());
+                cur.clear();
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            cur.push(c);
+        }
+    }
+    if !cur.is_empty() {
+        tokens.push(cur);
+    }
+    tokens
+}
+
+/// Words that end the attribute list of a space description.
+const SECTIONS: &'static [&'static str] = &["subspace", "create", "tolerate", "index", "with", "pending"];
+
+struct DescParser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl DescParser {
+    fn peek(&self, offset: usize) -> Option<&str> {
+        self.tokens.get(self.pos + offset).map(|t| &t[..])
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        match self.tokens.get(self.pos) {
+            Some(t) => {
+                self.pos += 1;
+                Ok(t.clone())
+            },
+            None => Err("unexpected end of description".to_string()),
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        let t = try!(self.next());
+        if t == token {
+            Ok(())
+        } else {
+            Err(format!("expected \"{}\" but found \"{}\"", token, t))
+        }
+    }
+
+    fn scalar(&mut self) -> Result<Enum_hyperdatatype, String> {
+        let t = try!(self.next());
+        match &t[..] {
+            "string" => Ok(HYPERDATATYPE_STRING),
+            "int" | "int64" => Ok(HYPERDATATYPE_INT64),
+            "float" => Ok(HYPERDATATYPE_FLOAT),
+            _ => Err(format!("\"{}\" is not a primitive type", t)),
+        }
+    }
+
+    fn datatype(&mut self) -> Result<Enum_hyperdatatype, String> {
+        let t = try!(self.next());
+        match &t[..] {
+            "string" => Ok(HYPERDATATYPE_STRING),
+            "int" | "int64" => Ok(HYPERDATATYPE_INT64),
+            "float" => Ok(HYPERDATATYPE_FLOAT),
+            "document" => Ok(HYPERDATATYPE_DOCUMENT),
+            "timestamp" => {
+                try!(self.expect("("));
+                try!(self.next());
+                try!(self.expect(")"));
+                Ok(HYPERDATATYPE_INT64)
+            },
+            "list" | "set" => {
+                try!(self.expect("("));
+                let elem = try!(self.scalar());
+                try!(self.expect(")"));
+                let base = if t == "list" { HYPERDATATYPE_LIST_GENERIC } else { HYPERDATATYPE_SET_GENERIC };
+                Ok(base + (elem - HYPERDATATYPE_GENERIC))
+            },
+            "map" => {
+                try!(self.expect("("));
+                let k = try!(self.scalar());
+                try!(self.expect(","));
+                let v = try!(self.scalar());
+                try!(self.expect(")"));
+                Ok(HYPERDATATYPE_MAP_GENERIC + ((k - HYPERDATATYPE_GENERIC) << 3) + (v - HYPERDATATYPE_GENERIC))
+            },
+            _ => Err(format!("\"{}\" is not a type", t)),
+        }
+    }
+
+    /// Parses `[type] name`.  Attributes without a type are strings.
+    fn attribute(&mut self) -> Result<(String, Enum_hyperdatatype), String> {
+        let typed = match (self.peek(0), self.peek(1)) {
+            (Some("list"), Some("(")) | (Some("set"), Some("(")) |
+            (Some("map"), Some("(")) | (Some("timestamp"), Some("(")) => true,
+            (Some("string"), Some(n)) | (Some("int"), Some(n)) | (Some("int64"), Some(n)) |
+            (Some("float"), Some(n)) | (Some("document"), Some(n)) => {
+                n != "," && !SECTIONS.contains(&n)
+            },
+            _ => false,
+        };
+        let dt = if typed { try!(self.datatype()) } else { HYPERDATATYPE_STRING };
+        Ok((try!(self.next()), dt))
+    }
+}
+
+fn parse_space(desc: &str) -> Result<(String, Space), String> {
+    let mut p = DescParser {
+        tokens: tokenize(desc),
+        pos: 0,
+    };
+
+    try!(p.expect("space"));
+    let name = try!(p.next());
+    try!(p.expect("key"));
+    let (key_name, key_type) = try!(p.attribute());
+    if key_type != HYPERDATATYPE_STRING && key_type != HYPERDATATYPE_INT64 && key_type != HYPERDATATYPE_FLOAT {
+        return Err(format!("a key cannot be a {}", type_name(key_type)));
+    }
+
+    let mut attrs: Vec<(String, Enum_hyperdatatype)> = Vec::new();
+    if p.peek(0) == Some("attributes") {
+        p.pos += 1;
+        loop {
+            match p.peek(0) {
+                None => break,
+                Some(t) if SECTIONS.contains(&t) => break,
+                _ => (),
+            }
+            let (attr, dt) = try!(p.attribute());
+            if attr == key_name || attrs.iter().any(|&(ref a, _)| *a == attr) {
+                return Err(format!("attribute \"{}\" is defined twice", attr));
+            }
+            attrs.push((attr, dt));
+            if p.peek(0) == Some(",") {
+                p.pos += 1;
+            }
+        }
+    }
+
+    Ok((name.clone(), Space {
+        name: name,
+        key_name: key_name,
+        key_type: key_type,
+        attrs: attrs,
+        objects: BTreeMap::new(),
+    }))
+}
+
+/* Spaces */
+
+impl Space {
+    fn schema(&self) -> SpaceSchema {
+        let datatype = |dt| type_name(dt).parse().unwrap();
+        SpaceSchema {
+            name: self.name.clone(),
+            key: self.key_name.clone(),
+            key_type: datatype(self.key_type),
+            attributes: self.attrs.iter().map(|&(ref a, dt)| (a.clone(), datatype(dt))).collect(),
+        }
+    }
+
+    fn attr_type(&self, attr: &str) -> Result<Enum_hyperdatatype, HyperError> {
+        if attr == self.key_name {
+            return Ok(self.key_type);
+        }
+        match self.attrs.iter().find(|&&(ref a, _)| a == attr) {
+            Some(&(_, dt)) => Ok(dt),
+            None => Err(error(HYPERDEX_CLIENT_UNKNOWNATTR, format!("\"{}\" is not an attribute", attr))),
+        }
+    }
+
+    /// Like `attr_type`, but for attributes that are about to be written.
+    fn writable_type(&self, attr: &str) -> Result<Enum_hyperdatatype, HyperError> {
+        if attr == self.key_name {
+            Err(error(HYPERDEX_CLIENT_DONTUSEKEY, "the key cannot be modified".to_string()))
+        } else {
+            self.attr_type(attr)
+        }
+    }
+
+    fn key_of(&self, key: HyperValue) -> Result<MemKey, HyperError> {
+        match try!(coerce(self.key_type, key)) {
+            HyperString(s) => Ok(MemKey::Str(s)),
+            HyperInt(i) => Ok(MemKey::Int(i)),
+            HyperFloat(f) => Ok(MemKey::Float(F64(f))),
+            _ => unreachable!(),
+        }
+    }
+
+    fn new_object(&self) -> HashMap<String, HyperValue> {
+        self.attrs.iter().map(|&(ref a, dt)| (a.clone(), default_value(dt))).collect()
+    }
+
+    /// An object together with its key, as returned by searches.
+    fn view(&self, key: &MemKey, obj: &HashMap<String, HyperValue>) -> HyperObject {
+        let mut res = HyperObject::new();
+        res.map = obj.clone();
+        res.map.insert(self.key_name.clone(), key.to_hyper());
+        res
+    }
+
+    /// Parses `attr` as a path into a document attribute, if it is one.
+    fn doc_path(&self, attr: &str) -> Result<Option<DocPath>, HyperError> {
+        if !attr.contains('.') {
+            return Ok(None);
+        }
+        let path: DocPath = try!(attr.parse().map_err(|msg| error(HYPERDEX_CLIENT_UNKNOWNATTR, msg)));
+        match try!(self.attr_type(path.attr())) {
+            HYPERDATATYPE_DOCUMENT => Ok(Some(path)),
+            dt => Err(error(HYPERDEX_CLIENT_UNKNOWNATTR,
+                            format!("\"{}\" is a {}, not a document", path.attr(), type_name(dt)))),
+        }
+    }
+
+    fn matches(&self, key: &MemKey, obj: &HashMap<String, HyperValue>, checks: &[HyperPredicate])
+        -> Result<bool, HyperError> {
+        for pred in checks.iter() {
+            let ok = if let Some(path) = try!(self.doc_path(&pred.attr)) {
+                // Objects whose document lacks the field never match
+                match path.lookup(document(obj, path.attr())) {
+                    Some(val) => try!(check(&json_to_hyper(val), pred)),
+                    None => false,
+                }
+            } else {
+                try!(self.attr_type(&pred.attr));
+                if pred.attr == self.key_name {
+                    try!(check(&key.to_hyper(), pred))
+                } else {
+                    try!(check(&obj[&pred.attr], pred))
+                }
+            };
+            if !ok {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn search(&self, checks: &[HyperPredicate]) -> Result<Vec<HyperObject>, HyperError> {
+        try!(check_predicates(checks, &self.schema()));
+        let mut res = Vec::new();
+        for (key, obj) in self.objects.iter() {
+            if try!(self.matches(key, obj, checks)) {
+                res.push(self.view(key, obj));
+            }
+        }
+        Ok(res)
+    }
+}
+
+fn document<'a>(obj: &'a HashMap<String, HyperValue>, attr: &str) -> &'a Value {
+    match obj[attr] {
+        HyperDocument(ref doc) => doc,
+        _ => unreachable!(),
+    }
+}
+
+/// Stores `val` at `fields` inside `doc`, creating intermediate objects as needed.
+fn set_path(doc: &mut Value, fields: &[String], val: Value) -> Result<(), HyperError> {
+    match *doc {
+        Value::Object(ref mut map) => {
+            if fields.len() == 1 {
+                map.insert(fields[0].clone(), val);
+                Ok(())
+            } else {
+                let next = map.entry(fields[0].clone()).or_insert(Value::Object(Map::new()));
+                set_path(next, &fields[1..], val)
+            }
+        },
+        _ => Err(error(HYPERDEX_CLIENT_WRONGTYPE,
+                       format!("cannot set field \"{}\" of a value that is not an object", fields[0]))),
+    }
+}
+
+fn stream<T: Send>(res: Result<Vec<T>, HyperError>) -> Receiver<Result<T, HyperError>> {
+    let (res_tx, res_rx) = channel();
+    match res {
+        Ok(objs) => {
+            for obj in objs.into_iter() {
+                res_tx.send(Ok(obj)).unwrap();
+            }
+        },
+        Err(err) => {
+            res_tx.send(Err(err)).unwrap();
+        },
+    }
+    res_rx
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore {
+            spaces: HashMap::new(),
+        }
+    }
+
+    /// Creates a space from a description, just like `Admin::add_space`.
+    ///
+    /// Only the `space`, `key` and `attributes` sections matter; subspaces, partitioning and
+    /// fault tolerance are accepted and ignored.
+    pub fn add_space(&mut self, desc: &str) -> Result<(), HyperError> {
+        let (name, space) = match parse_space(desc) {
+            Ok(x) => x,
+            Err(msg) => return Err(error(HYPERDEX_ADMIN_BADSPACE, msg)),
+        };
+        if self.spaces.contains_key(&name) {
+            return Err(error(HYPERDEX_ADMIN_DUPLICATE, format!("space \"{}\" already exists", name)));
+        }
+        self.spaces.insert(name, space);
+        Ok(())
+    }
+
+    pub fn remove_space(&mut self, name: &str) -> Result<(), HyperError> {
+        match self.spaces.remove(name) {
+            Some(_) => Ok(()),
+            None => Err(error(HYPERDEX_ADMIN_NOTFOUND, format!("space \"{}\" does not exist", name))),
+        }
+    }
+
+    /// The names of all spaces, one per line, like `Admin::list_spaces`.
+    pub fn list_spaces(&self) -> String {
+        let mut names: Vec<&String> = self.spaces.keys().collect();
+        names.sort();
+        names.into_iter().fold(String::new(), |mut acc, name| {
+            acc.push_str(name);
+            acc.push('\n');
+            acc
+        })
+    }
+
+    /// The key and attributes of a space, like `Admin::space_schema`.
+    pub fn space_schema(&self, space: &str) -> Result<SpaceSchema, HyperError> {
+        match self.spaces.get(space) {
+            Some(s) => Ok(s.schema()),
+            None => Err(error(HYPERDEX_ADMIN_NOTFOUND, format!("space \"{}\" does not exist", space))),
+        }
+    }
+
+    fn space(&self, space: &str) -> Result<&Space, HyperError> {
+        match self.spaces.get(space) {
+            Some(s) => Ok(s),
+            None => Err(error(HYPERDEX_CLIENT_UNKNOWNSPACE, format!("space \"{}\" does not exist", space))),
+        }
+    }
+
+    fn space_mut(&mut self, space: &str) -> Result<&mut Space, HyperError> {
+        match self.spaces.get_mut(space) {
+            Some(s) => Ok(s),
+            None => Err(error(HYPERDEX_CLIENT_UNKNOWNSPACE, format!("space \"{}\" does not exist", space))),
+        }
+    }
+
+    /// Runs `f` on a copy of the object stored under `key` and stores the result if `f`
+    /// succeeds.  A missing object is created when `create` is set and fails with `NOTFOUND`
+    /// otherwise; `checks` failing results in `CMPFAIL`.
+    fn modify<S, K, F>(&mut self, space: S, key: K, checks: &[HyperPredicate], create: bool, f: F)
+        -> Result<(), HyperError>
+        where S: ToCStr, K: ToHyperValue,
+              F: FnOnce(&Space, &mut HashMap<String, HyperValue>) -> Result<(), HyperError> {
+        let space = try!(self.space_mut(&space_name(space)));
+        try!(check_predicates(checks, &space.schema()));
+        let key = try!(space.key_of(key.to_hyper()));
+        let mut obj = match space.objects.get(&key) {
+            Some(obj) => obj.clone(),
+            None if create => space.new_object(),
+            None => return Err(error(HYPERDEX_CLIENT_NOTFOUND, "the object does not exist".to_string())),
+        };
+        if !try!(space.matches(&key, &obj, checks)) {
+            return Err(error(HYPERDEX_CLIENT_CMPFAIL, "the object does not match the predicates".to_string()));
+        }
+        try!(f(&*space, &mut obj));
+        space.objects.insert(key, obj);
+        Ok(())
+    }
+
+    /// Replaces each attribute of `value` with `f(attribute type, current value, argument)`.
+    fn modify_attrs<S, K, F>(&mut self, space: S, key: K, checks: &[HyperPredicate], create: bool,
+                             value: HyperObject, f: F) -> Result<(), HyperError>
+        where S: ToCStr, K: ToHyperValue,
+              F: Fn(Enum_hyperdatatype, HyperValue, HyperValue) -> Result<HyperValue, HyperError> {
+        self.modify(space, key, checks, create, move |space, obj| {
+            for (attr, arg) in value.map.into_iter() {
+                if let Some(path) = try!(space.doc_path(&attr)) {
+                    let doc = match obj.get_mut(path.attr()) {
+                        Some(&mut HyperDocument(ref mut doc)) => doc,
+                        _ => unreachable!(),
+                    };
+                    let cur = match path.lookup(doc) {
+                        Some(val) => json_to_hyper(val),
+                        None => default_value(datatype_of(&arg)),
+                    };
+                    let new = try!(f(datatype_of(&cur), cur, arg));
+                    let new = match hyper_to_json(new) {
+                        Some(new) => new,
+                        None => return Err(error(HYPERDEX_CLIENT_WRONGTYPE,
+                                                 "documents can only hold strings, numbers and documents".to_string())),
+                    };
+                    try!(set_path(doc, path.fields(), new));
+                    continue;
+                }
+                let dt = try!(space.writable_type(&attr));
+                let cur = obj.remove(&attr).unwrap();
+                obj.insert(attr, try!(f(dt, cur, arg)));
+            }
+            Ok(())
+        })
+    }
+
+    /// Replaces the entry of each map attribute with `f(value type, current value, argument)`.
+    fn modify_map_attrs<S, K, F>(&mut self, space: S, key: K, checks: &[HyperPredicate],
+                                 mapattrs: Vec<HyperMapAttribute>, f: F) -> Result<(), HyperError>
+        where S: ToCStr, K: ToHyperValue,
+              F: Fn(Enum_hyperdatatype, Option<HyperValue>, HyperValue) -> Result<HyperValue, HyperError> {
+        self.modify(space, key, checks, false, move |space, obj| {
+            for mapattr in mapattrs.into_iter() {
+                let dt = try!(space.writable_type(&mapattr.attr));
+                if !is_map(dt) {
+                    return Err(error(HYPERDEX_CLIENT_WRONGTYPE,
+                                     format!("\"{}\" is a {}, not a map", mapattr.attr, type_name(dt))));
+                }
+                let map_key = try!(coerce(map_key_type(dt), mapattr.key));
+                let mut es = entries(obj.remove(&mapattr.attr).unwrap());
+                let pos = es.iter().position(|&(ref k, _)| *k == map_key);
+                let cur = pos.map(|i| es.remove(i).1);
+                let new = try!(f(map_val_type(dt), cur, mapattr.value));
+                es.push((map_key, new));
+                obj.insert(mapattr.attr, build_map(dt, es));
+            }
+            Ok(())
+        })
+    }
+
+    pub fn get<S, K>(&mut self, space: S, key: K) -> Result<HyperObject, HyperError>
+        where S: ToCStr, K: ToHyperValue {
+        let space = try!(self.space(&space_name(space)));
+        let key = try!(space.key_of(key.to_hyper()));
+        match space.objects.get(&key) {
+            Some(obj) => {
+                let mut res = HyperObject::new();
+                res.map = obj.clone();
+                Ok(res)
+            },
+            None => Err(error(HYPERDEX_CLIENT_NOTFOUND, "the object does not exist".to_string())),
+        }
+    }
+
+    pub fn get_partial<S, K, A>(&mut self, space: S, key: K, attrs: Vec<A>) -> Result<HyperObject, HyperError>
+        where S: ToCStr, K: ToHyperValue, A: ToString {
+        let space = try!(self.space(&space_name(space)));
+        let key = try!(space.key_of(key.to_hyper()));
+        let attrs: Vec<String> = attrs.into_iter().map(|a| a.to_string()).collect();
+        let mut paths = Vec::new();
+        for attr in attrs.iter() {
+            match try!(space.doc_path(attr)) {
+                Some(path) => paths.push(Some(path)),
+                None => {
+                    try!(space.writable_type(attr));
+                    paths.push(None);
+                },
+            }
+        }
+        match space.objects.get(&key) {
+            Some(obj) => {
+                let mut res = HyperObject::new();
+                for (attr, path) in attrs.into_iter().zip(paths.into_iter()) {
+                    let val = match path {
+                        Some(path) => match path.lookup(document(obj, path.attr())) {
+                            Some(val) => json_to_hyper(val),
+                            None => continue,
+                        },
+                        None => obj[&attr].clone(),
+                    };
+                    res.map.insert(attr, val);
+                }
+                Ok(res)
+            },
+            None => Err(error(HYPERDEX_CLIENT_NOTFOUND, "the object does not exist".to_string())),
+        }
+    }
+
+    /// Fetches many objects, with one result per key.
+    pub fn get_many<S, K, I>(&mut self, space: S, keys: I) -> HashMap<K, Result<HyperObject, HyperError>>
+        where S: ToCStr + Clone, K: ToHyperValue + Clone + Hash + Eq, I: IntoIterator<Item=K> {
+        keys.into_iter().map(|key| {
+            let res = self.get(space.clone(), key.clone());
+            (key, res)
+        }).collect()
+    }
+
+    /// Like `get_many`, but only fetches the given attributes of each object.
+    pub fn get_partial_many<S, K, A, I>(&mut self, space: S, keys: I, attrs: Vec<A>)
+        -> HashMap<K, Result<HyperObject, HyperError>>
+        where S: ToCStr + Clone, K: ToHyperValue + Clone + Hash + Eq, A: ToString + Clone,
+              I: IntoIterator<Item=K> {
+        keys.into_iter().map(|key| {
+            let res = self.get_partial(space.clone(), key.clone(), attrs.clone());
+            (key, res)
+        }).collect()
+    }
+
+    pub fn put<S, K>(&mut self, space: S, key: K, value: HyperObject) -> Result<(), HyperError>
+        where S: ToCStr, K: ToHyperValue {
+        self.modify_attrs(space, key, &[], true, value, |dt, _, arg| coerce(dt, arg))
+    }
+
+    pub fn put_if_not_exist<S, K>(&mut self, space: S, key: K, value: HyperObject) -> Result<(), HyperError>
+        where S: ToCStr, K: ToHyperValue {
+        let space = space_name(space);
+        let key = key.to_hyper();
+        {
+            let s = try!(self.space(&space));
+            if s.objects.contains_key(&try!(s.key_of(key.clone()))) {
+                return Err(error(HYPERDEX_CLIENT_CMPFAIL, "the object already exists".to_string()));
+            }
+        }
+        self.put(&space[..], key, value)
+    }
+
+    pub fn cond_put<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>, value: HyperObject)
+        -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue {
+        self.modify_attrs(space, key, &checks, false, value, |dt, _, arg| coerce(dt, arg))
+    }
+
+    pub fn del<S, K>(&mut self, space: S, key: K) -> Result<(), HyperError>
+        where S: ToCStr, K: ToHyperValue {
+        let space = try!(self.space_mut(&space_name(space)));
+        let key = try!(space.key_of(key.to_hyper()));
+        match space.objects.remove(&key) {
+            Some(_) => Ok(()),
+            None => Err(error(HYPERDEX_CLIENT_NOTFOUND, "the object does not exist".to_string())),
+        }
+    }
+
+    pub fn cond_del<S, K>(&mut self, space: S, key: K, checks: Vec<HyperPredicate>) -> Result<(), HyperError>
+        where S: ToCStr, K: ToHyperValue {
+        let space = try!(self.space_mut(&space_name(space)));
+        try!(check_predicates(&checks, &space.schema()));
+        let key = try!(space.key_of(key.to_hyper()));
+        let matches = match space.objects.get(&key) {
+            Some(obj) => try!(space.matches(&key, obj, &checks)),
+            None => return Err(error(HYPERDEX_CLIENT_NOTFOUND, "the object does not exist".to_string())),
+        };
+        if !matches {
+            return Err(error(HYPERDEX_CLIENT_CMPFAIL, "the object does not match the predicates".to_string()));
+        }
+        space.objects.remove(&key);
+        Ok(())
+    }
+
+    pub fn search<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr {
+        stream(self.space(&space_name(space)).and_then(|s| s.search(&checks)))
+    }
+
+    /// Like `search`, but hands back the key of each object separately from its attributes.
+    pub fn search_keyed<S>(&mut self, space: S, checks: Vec<HyperPredicate>)
+        -> Receiver<Result<(HyperKey, HyperObject), HyperError>> where S: ToCStr {
+        stream(self.space(&space_name(space)).and_then(|s| {
+            let found = try!(s.search(&checks));
+            Ok(found.into_iter().map(|mut obj| {
+                let key = obj.map.remove(&s.key_name).unwrap();
+                (HyperKey::from_hyper(key).unwrap(), obj)
+            }).collect())
+        }))
+    }
+
+    pub fn sorted_search<S, A>(&mut self, space: S, checks: Vec<HyperPredicate>, sort_by: A, limit: u64, maxmin: bool)
+        -> Receiver<Result<HyperObject, HyperError>> where S: ToCStr, A: ToCStr {
+        let sort_by = space_name(sort_by);
+        stream(self.space(&space_name(space)).and_then(|s| {
+            let dt = try!(s.attr_type(&sort_by));
+            if dt != HYPERDATATYPE_STRING && dt != HYPERDATATYPE_INT64 && dt != HYPERDATATYPE_FLOAT {
+                return Err(error(HYPERDEX_CLIENT_WRONGTYPE, format!("cannot sort by a {}", type_name(dt))));
+            }
+            let mut objs = try!(s.search(&checks));
+            objs.sort_by(|a, b| {
+                let ord = compare(&a.map[&sort_by], &b.map[&sort_by]).unwrap();
+                if maxmin { ord.reverse() } else { ord }
+            });
+            objs.truncate(limit as usize);
+            Ok(objs)
+        }))
+    }
+
+    pub fn count<S>(&mut self, space: S, checks: Vec<HyperPredicate>) -> Result<u64, HyperError>
+        where S: ToCStr {
+        let space = try!(self.space(&space_name(space)));
+        space.search(&checks).map(|objs| objs.len() as u64)
+    }
+}
+
+macro_rules! memory_fn_spacename_key_attributes(
+    ($fn_name: ident, $f: expr) => (
+        impl MemoryStore {
+            pub fn $fn_name<S, K>(&mut self, space: S, key: K, value: HyperObject)
+                -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                self.modify_attrs(space, key, &[], false, value, $f)
+            }
+        }
+    );
+);
+
+macro_rules! memory_fn_spacename_key_mapattributes(
+    ($fn_name: ident, $cond_name: ident, $f: expr) => (
+        impl MemoryStore {
+            pub fn $fn_name<S, K>(&mut self, space: S, key: K, mapattrs: Vec<HyperMapAttribute>)
+                -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                self.modify_map_attrs(space, key, &[], mapattrs, $f)
+            }
+
+            pub fn $cond_name<S, K>(&mut self, space: S, key: K,
+                                    checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>)
+                -> Result<(), HyperError> where S: ToCStr, K: ToHyperValue {
+                self.modify_map_attrs(space, key, &checks, mapattrs, $f)
+            }
+        }
+    );
+);
+
+fn push(dt: Enum_hyperdatatype, cur: HyperValue, arg: HyperValue, front: bool) -> Result<HyperValue, HyperError> {
+    if !is_list(dt) {
+        return Err(error(HYPERDEX_CLIENT_WRONGTYPE, format!("cannot push onto a {}", type_name(dt))));
+    }
+    let elem = try!(coerce(elem_type(dt), arg));
+    let mut elems = elements(cur);
+    if front {
+        elems.insert(0, elem);
+    } else {
+        elems.push(elem);
+    }
+    Ok(build_list(dt, elems))
+}
+
+fn set_op<F>(dt: Enum_hyperdatatype, cur: HyperValue, arg: HyperValue, whole_set: bool, f: F)
+    -> Result<HyperValue, HyperError> where F: Fn(Vec<HyperValue>, Vec<HyperValue>) -> Vec<HyperValue> {
+    if !is_set(dt) {
+        return Err(error(HYPERDEX_CLIENT_WRONGTYPE, format!("\"{}\" is not a set", type_name(dt))));
+    }
+    let args = if whole_set {
+        elements(try!(coerce(dt, arg)))
+    } else {
+        vec!(try!(coerce(elem_type(dt), arg)))
+    };
+    Ok(build_set(dt, f(elements(cur), args)))
+}
+
+fn map_value_or_default(dt: Enum_hyperdatatype, cur: Option<HyperValue>) -> HyperValue {
+    match cur {
+        Some(v) => v,
+        None => default_value(dt),
+    }
+}
+
+memory_fn_spacename_key_attributes!(atomic_add, |_, cur, arg| arith(Arith::Add, cur, arg));
+memory_fn_spacename_key_attributes!(atomic_sub, |_, cur, arg| arith(Arith::Sub, cur, arg));
+memory_fn_spacename_key_attributes!(atomic_mul, |_, cur, arg| arith(Arith::Mul, cur, arg));
+memory_fn_spacename_key_attributes!(atomic_div, |_, cur, arg| arith(Arith::Div, cur, arg));
+memory_fn_spacename_key_attributes!(atomic_mod, |_, cur, arg| arith(Arith::Mod, cur, arg));
+memory_fn_spacename_key_attributes!(atomic_and, |_, cur, arg| arith(Arith::And, cur, arg));
+memory_fn_spacename_key_attributes!(atomic_or, |_, cur, arg| arith(Arith::Or, cur, arg));
+memory_fn_spacename_key_attributes!(atomic_xor, |_, cur, arg| arith(Arith::Xor, cur, arg));
+memory_fn_spacename_key_attributes!(string_prepend, |_, cur, arg| concat(cur, arg, true));
+memory_fn_spacename_key_attributes!(string_append, |_, cur, arg| concat(cur, arg, false));
+memory_fn_spacename_key_attributes!(list_lpush, |dt, cur, arg| push(dt, cur, arg, true));
+memory_fn_spacename_key_attributes!(list_rpush, |dt, cur, arg| push(dt, cur, arg, false));
+memory_fn_spacename_key_attributes!(set_add, |dt, cur, arg| set_op(dt, cur, arg, false, |mut cur, args| {
+    cur.extend(args.into_iter());
+    cur
+}));
+memory_fn_spacename_key_attributes!(set_remove, |dt, cur, arg| set_op(dt, cur, arg, false, |cur, args| {
+    cur.into_iter().filter(|e| !args.contains(e)).collect()
+}));
+memory_fn_spacename_key_attributes!(set_intersect, |dt, cur, arg| set_op(dt, cur, arg, true, |cur, args| {
+    cur.into_iter().filter(|e| args.contains(e)).collect()
+}));
+memory_fn_spacename_key_attributes!(set_union, |dt, cur, arg| set_op(dt, cur, arg, true, |mut cur, args| {
+    cur.extend(args.into_iter());
+    cur
+}));
+memory_fn_spacename_key_attributes!(map_remove, |dt, cur, arg| {
+    if !is_map(dt) {
+        return Err(error(HYPERDEX_CLIENT_WRONGTYPE, format!("\"{}\" is not a map", type_name(dt))));
+    }
+    let map_key = try!(coerce(map_key_type(dt), arg));
+    Ok(build_map(dt, entries(cur).into_iter().filter(|&(ref k, _)| *k != map_key).collect()))
+});
+
+memory_fn_spacename_key_mapattributes!(map_add, cond_map_add, |dt, _, arg| coerce(dt, arg));
+memory_fn_spacename_key_mapattributes!(map_atomic_add, cond_map_atomic_add,
+                                       |dt, cur, arg| arith(Arith::Add, map_value_or_default(dt, cur), arg));
+memory_fn_spacename_key_mapattributes!(map_atomic_sub, cond_map_atomic_sub,
+                                       |dt, cur, arg| arith(Arith::Sub, map_value_or_default(dt, cur), arg));
+memory_fn_spacename_key_mapattributes!(map_atomic_mul, cond_map_atomic_mul,
+                                       |dt, cur, arg| arith(Arith::Mul, map_value_or_default(dt, cur), arg));
+memory_fn_spacename_key_mapattributes!(map_atomic_div, cond_map_atomic_div,
+                                       |dt, cur, arg| arith(Arith::Div, map_value_or_default(dt, cur), arg));
+memory_fn_spacename_key_mapattributes!(map_atomic_mod, cond_map_atomic_mod,
+                                       |dt, cur, arg| arith(Arith::Mod, map_value_or_default(dt, cur), arg));
+memory_fn_spacename_key_mapattributes!(map_atomic_and, cond_map_atomic_and,
+                                       |dt, cur, arg| arith(Arith::And, map_value_or_default(dt, cur), arg));
+memory_fn_spacename_key_mapattributes!(map_atomic_or, cond_map_atomic_or,
+                                       |dt, cur, arg| arith(Arith::Or, map_value_or_default(dt, cur), arg));
+memory_fn_spacename_key_mapattributes!(map_atomic_xor, cond_map_atomic_xor,
+                                       |dt, cur, arg| arith(Arith::Xor, map_value_or_default(dt, cur), arg));
+memory_fn_spacename_key_mapattributes!(map_string_prepend, cond_map_string_prepend,
+                                       |dt, cur, arg| concat(map_value_or_default(dt, cur), arg, true));
+memory_fn_spacename_key_mapattributes!(map_string_append, cond_map_string_append,
+                                       |dt, cur, arg| concat(map_value_or_default(dt, cur), arg, false));
+
+/* Asynchronous operations */
+
+/// A future that has already completed with `res`.
+fn completed<T: Send + 'static>(res: Result<T, HyperError>) -> Future<T, HyperError> {
+    match res {
+        Ok(val) => Future::of(val),
+        Err(err) => Future::error(err),
+    }
+}
+
+/// Adds the `async_*` form of a blocking operation.  Operations on a `MemoryStore` finish
+/// immediately, so the future it returns has already completed.
+macro_rules! memory_async_fn(
+    ($async_name: ident, $fn_name: ident, $res: ty $(, $arg: ident: $arg_ty: ty)*) => (
+        impl MemoryStore {
+            pub fn $async_name<S, K>(&mut self, space: S, key: K $(, $arg: $arg_ty)*)
+                -> Future<$res, HyperError> where S: ToCStr, K: ToHyperValue {
+                completed(self.$fn_name(space, key $(, $arg)*))
+            }
+        }
+    );
+);
+
+impl MemoryStore {
+    pub fn async_get_partial<S, K, A>(&mut self, space: S, key: K, attrs: Vec<A>)
+        -> Future<HyperObject, HyperError> where S: ToCStr, K: ToHyperValue, A: ToString {
+        completed(self.get_partial(space, key, attrs))
+    }
+
+    pub fn async_count<S>(&mut self, space: S, checks: Vec<HyperPredicate>) -> Future<u64, HyperError>
+        where S: ToCStr {
+        completed(self.count(space, checks))
+    }
+}
+
+memory_async_fn!(async_get, get, HyperObject);
+memory_async_fn!(async_del, del, ());
+memory_async_fn!(async_cond_del, cond_del, (), checks: Vec<HyperPredicate>);
+
+memory_async_fn!(async_put, put, (), value: HyperObject);
+memory_async_fn!(async_put_if_not_exist, put_if_not_exist, (), value: HyperObject);
+memory_async_fn!(async_atomic_add, atomic_add, (), value: HyperObject);
+memory_async_fn!(async_atomic_sub, atomic_sub, (), value: HyperObject);
+memory_async_fn!(async_atomic_mul, atomic_mul, (), value: HyperObject);
+memory_async_fn!(async_atomic_div, atomic_div, (), value: HyperObject);
+memory_async_fn!(async_atomic_mod, atomic_mod, (), value: HyperObject);
+memory_async_fn!(async_atomic_and, atomic_and, (), value: HyperObject);
+memory_async_fn!(async_atomic_or, atomic_or, (), value: HyperObject);
+memory_async_fn!(async_atomic_xor, atomic_xor, (), value: HyperObject);
+memory_async_fn!(async_string_prepend, string_prepend, (), value: HyperObject);
+memory_async_fn!(async_string_append, string_append, (), value: HyperObject);
+memory_async_fn!(async_list_lpush, list_lpush, (), value: HyperObject);
+memory_async_fn!(async_list_rpush, list_rpush, (), value: HyperObject);
+memory_async_fn!(async_set_add, set_add, (), value: HyperObject);
+memory_async_fn!(async_set_remove, set_remove, (), value: HyperObject);
+memory_async_fn!(async_set_intersect, set_intersect, (), value: HyperObject);
+memory_async_fn!(async_set_union, set_union, (), value: HyperObject);
+memory_async_fn!(async_map_remove, map_remove, (), value: HyperObject);
+
+memory_async_fn!(async_cond_put, cond_put, (), checks: Vec<HyperPredicate>, value: HyperObject);
+
+memory_async_fn!(async_map_add, map_add, (), mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_map_atomic_add, map_atomic_add, (), mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_map_atomic_sub, map_atomic_sub, (), mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_map_atomic_mul, map_atomic_mul, (), mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_map_atomic_div, map_atomic_div, (), mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_map_atomic_mod, map_atomic_mod, (), mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_map_atomic_and, map_atomic_and, (), mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_map_atomic_or, map_atomic_or, (), mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_map_atomic_xor, map_atomic_xor, (), mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_map_string_prepend, map_string_prepend, (), mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_map_string_append, map_string_append, (), mapattrs: Vec<HyperMapAttribute>);
+
+memory_async_fn!(async_cond_map_add, cond_map_add, (),
+                 checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_cond_map_atomic_add, cond_map_atomic_add, (),
+                 checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_cond_map_atomic_sub, cond_map_atomic_sub, (),
+                 checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_cond_map_atomic_mul, cond_map_atomic_mul, (),
+                 checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_cond_map_atomic_div, cond_map_atomic_div, (),
+                 checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_cond_map_atomic_mod, cond_map_atomic_mod, (),
+                 checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_cond_map_atomic_and, cond_map_atomic_and, (),
+                 checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_cond_map_atomic_or, cond_map_atomic_or, (),
+                 checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_cond_map_atomic_xor, cond_map_atomic_xor, (),
+                 checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_cond_map_string_prepend, cond_map_string_prepend, (),
+                 checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+memory_async_fn!(async_cond_map_string_append, cond_map_string_append, (),
+                 checks: Vec<HyperPredicate>, mapattrs: Vec<HyperMapAttribute>);
+
+impl_key_value_store!(MemoryStore);
+impl_async_key_value_store!(MemoryStore);
+
+/// A `Clock` that stands still until it is moved along with `advance`.  Clones share the same
+/// time, so a test can keep one and hand another to the code under test.
+#[derive(Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<(Instant, SystemTime)>>,
+}
+
+impl ManualClock {
+    /// A clock that starts at the current time.
+    pub fn new() -> ManualClock {
+        ManualClock {
+            now: Arc::new(Mutex::new((Instant::now(), SystemTime::now()))),
+        }
+    }
+
+    /// Moves both `now` and `system_time` forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.lock().unwrap();
+        now.0 += by;
+        now.1 += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.now.lock().unwrap().0
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.now.lock().unwrap().1
+    }
+}